@@ -0,0 +1,93 @@
+/*
+ * C API for macos-resolver (`capi` feature).
+ *
+ * Build the library with
+ *   cargo rustc --release --features capi --crate-type cdylib
+ * (or --crate-type staticlib) and link against libmacos_resolver.
+ *
+ * Every fallible call returns an MrStatus. On failure,
+ * mr_last_error_message() describes the error on the calling thread.
+ */
+
+#ifndef MACOS_RESOLVER_H
+#define MACOS_RESOLVER_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Opaque resolver handle. */
+typedef struct MrResolver MrResolver;
+
+/* Result of a call. Error codes mirror ResolverError. */
+typedef enum MrStatus {
+    MR_OK = 0,
+    MR_ERR_IO = 1,
+    MR_ERR_PERMISSION_DENIED = 2, /* not running as root */
+    MR_ERR_DIR_NOT_FOUND = 3,
+    MR_ERR_NOT_MANAGED = 4,       /* file exists but is not ours */
+    MR_ERR_OWNED_BY_OTHER = 5,    /* file carries another app's marker */
+    MR_ERR_NOT_OWNER = 6,         /* managed by a live process other than us */
+    MR_ERR_NOT_FOUND = 7,
+    MR_ERR_LOCKED = 8,
+    MR_ERR_INVALID_CONFIG = 9,
+    MR_ERR_OTHER = 10,
+    MR_ERR_NULL_ARGUMENT = 11,
+    MR_ERR_INVALID_UTF8 = 12,
+    MR_ERR_PANIC = 13,
+    MR_ERR_UNREACHABLE = 14,      /* nameserver failed the preflight check */
+} MrStatus;
+
+/*
+ * Creates a resolver for `prefix` (files are marked "# managed by <prefix>").
+ * `dir` overrides the resolver directory, or NULL for /etc/resolver.
+ * Returns NULL on invalid arguments. Free with mr_resolver_free().
+ */
+MrResolver *mr_resolver_new(const char *prefix, const char *dir);
+
+/* Frees a handle. Registrations stay in place. NULL is ignored. */
+void mr_resolver_free(MrResolver *resolver);
+
+/* Registers `domain`, bound to the calling process. */
+MrStatus mr_register(const MrResolver *resolver, const char *domain,
+                     const char *nameserver, uint16_t port,
+                     uint32_t search_order);
+
+/* Registers `domain` so it survives the calling process. */
+MrStatus mr_register_permanent(const MrResolver *resolver, const char *domain,
+                               const char *nameserver, uint16_t port,
+                               uint32_t search_order);
+
+/* Removes a managed domain. Missing domains are not an error. */
+MrStatus mr_unregister(const MrResolver *resolver, const char *domain);
+
+/*
+ * Lists managed domains. On success `*out_domains` holds `*out_len` strings;
+ * release them with mr_string_array_free().
+ */
+MrStatus mr_list(const MrResolver *resolver, char ***out_domains,
+                 size_t *out_len);
+
+/* Frees an array returned by mr_list(). NULL is ignored. */
+void mr_string_array_free(char **domains, size_t len);
+
+/*
+ * Removes files left by processes that exited without unregistering.
+ * `out_removed` (may be NULL) receives the number of files removed.
+ */
+MrStatus mr_cleanup_orphaned(const MrResolver *resolver, size_t *out_removed);
+
+/*
+ * Message of the last failed call on this thread, or NULL after a successful
+ * call. Valid until the next call on the same thread.
+ */
+const char *mr_last_error_message(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* MACOS_RESOLVER_H */
@@ -0,0 +1,281 @@
+//! Just enough HTTP/1.1 for the admin API: one request per connection,
+//! `Content-Length` bodies, JSON responses.
+
+use std::fmt;
+use std::io::{self, BufRead, Read, Write};
+
+/// Longest accepted request line plus headers, in bytes.
+pub const MAX_HEAD: usize = 8 * 1024;
+
+/// Longest accepted request body, in bytes.
+pub const MAX_BODY: usize = 64 * 1024;
+
+/// A parsed request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub query: Option<String>,
+    /// Header names are lowercased.
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    /// Value of the first header called `name` (lowercase).
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Whether the query string sets `name` to `true` or `1`, or names it
+    /// without a value.
+    pub fn query_flag(&self, name: &str) -> bool {
+        self.query
+            .as_deref()
+            .into_iter()
+            .flat_map(|q| q.split('&'))
+            .any(|pair| match pair.split_once('=') {
+                Some((key, value)) => key == name && matches!(value, "true" | "1"),
+                None => pair == name,
+            })
+    }
+}
+
+/// Why a request could not be read.
+#[derive(Debug)]
+pub enum ParseError {
+    Io(io::Error),
+    Malformed(&'static str),
+    TooLarge,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{e}"),
+            Self::Malformed(what) => write!(f, "malformed request: {what}"),
+            Self::TooLarge => write!(f, "request too large"),
+        }
+    }
+}
+
+impl From<io::Error> for ParseError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// Reads one request, or `None` if the peer closed the connection without
+/// sending anything.
+pub fn read_request(reader: &mut impl BufRead) -> Result<Option<Request>, ParseError> {
+    let mut head_len = 0;
+    let Some(request_line) = read_head_line(reader, &mut head_len)? else {
+        return Ok(None);
+    };
+    let mut parts = request_line.split(' ');
+    let (Some(method), Some(target), Some(version), None) =
+        (parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return Err(ParseError::Malformed("request line"));
+    };
+    if !version.starts_with("HTTP/1.") {
+        return Err(ParseError::Malformed("HTTP version"));
+    }
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path, Some(query.to_string())),
+        None => (target, None),
+    };
+
+    let mut headers = Vec::new();
+    loop {
+        let line = read_head_line(reader, &mut head_len)?
+            .ok_or(ParseError::Malformed("unterminated headers"))?;
+        if line.is_empty() {
+            break;
+        }
+        let (name, value) = line
+            .split_once(':')
+            .ok_or(ParseError::Malformed("header"))?;
+        headers.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+    }
+
+    let mut request = Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        query,
+        headers,
+        body: Vec::new(),
+    };
+    if request.header("transfer-encoding").is_some() {
+        return Err(ParseError::Malformed("chunked bodies are not supported"));
+    }
+    let length = match request.header("content-length") {
+        Some(value) => value
+            .parse::<usize>()
+            .map_err(|_| ParseError::Malformed("content-length"))?,
+        None => 0,
+    };
+    if length > MAX_BODY {
+        return Err(ParseError::TooLarge);
+    }
+    request.body.resize(length, 0);
+    reader.read_exact(&mut request.body)?;
+    Ok(Some(request))
+}
+
+/// Reads a CRLF- or LF-terminated line of the request head, counting it
+/// against [`MAX_HEAD`].
+fn read_head_line(
+    reader: &mut impl BufRead,
+    head_len: &mut usize,
+) -> Result<Option<String>, ParseError> {
+    let mut line = Vec::new();
+    let limit = (MAX_HEAD - *head_len + 1) as u64;
+    reader.by_ref().take(limit).read_until(b'\n', &mut line)?;
+    if line.is_empty() {
+        return Ok(None);
+    }
+    *head_len += line.len();
+    if *head_len > MAX_HEAD {
+        return Err(ParseError::TooLarge);
+    }
+    if line.pop() != Some(b'\n') {
+        return Err(ParseError::Malformed("truncated request"));
+    }
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|_| ParseError::Malformed("non-UTF-8 request head"))
+}
+
+/// A response with an optional JSON body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    pub body: Option<serde_json::Value>,
+    /// Adds `WWW-Authenticate: Bearer` (for 401 responses).
+    pub challenge: bool,
+}
+
+impl Response {
+    pub const fn json(status: u16, body: serde_json::Value) -> Self {
+        Self {
+            status,
+            body: Some(body),
+            challenge: false,
+        }
+    }
+
+    pub const fn empty(status: u16) -> Self {
+        Self {
+            status,
+            body: None,
+            challenge: false,
+        }
+    }
+}
+
+/// Writes `response`, closing the exchange with `Connection: close`.
+pub fn write_response(writer: &mut impl Write, response: &Response) -> io::Result<()> {
+    let body = response
+        .body
+        .as_ref()
+        .map(|v| format!("{v}\n"))
+        .unwrap_or_default();
+    let mut head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        response.status,
+        reason(response.status),
+        body.len()
+    );
+    if response.body.is_some() {
+        head.push_str("Content-Type: application/json\r\n");
+    }
+    if response.challenge {
+        head.push_str("WWW-Authenticate: Bearer\r\n");
+    }
+    head.push_str("\r\n");
+    writer.write_all(head.as_bytes())?;
+    writer.write_all(body.as_bytes())?;
+    writer.flush()
+}
+
+const fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(raw: &str) -> Result<Option<Request>, ParseError> {
+        read_request(&mut io::Cursor::new(raw.as_bytes().to_vec()))
+    }
+
+    #[test]
+    fn parses_request_with_body() {
+        let request = parse(
+            "POST /v1/domains?permanent=true HTTP/1.1\r\nHost: x\r\nContent-Length: 2\r\nAuthorization: Bearer t\r\n\r\n{}",
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.path, "/v1/domains");
+        assert!(request.query_flag("permanent"));
+        assert!(!request.query_flag("other"));
+        assert_eq!(request.header("authorization"), Some("Bearer t"));
+        assert_eq!(request.body, b"{}");
+        assert!(parse("").unwrap().is_none());
+    }
+
+    #[test]
+    fn rejects_malformed_and_oversized_requests() {
+        assert!(matches!(
+            parse("GET /\r\n\r\n"),
+            Err(ParseError::Malformed(_))
+        ));
+        assert!(matches!(
+            parse("GET / HTTP/1.1\r\nHost: x\r\n"),
+            Err(ParseError::Malformed(_))
+        ));
+        let long = format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(MAX_HEAD));
+        assert!(matches!(parse(&long), Err(ParseError::TooLarge)));
+        let big = format!(
+            "POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY + 1
+        );
+        assert!(matches!(parse(&big), Err(ParseError::TooLarge)));
+    }
+
+    #[test]
+    fn writes_json_response() {
+        let mut out = Vec::new();
+        let mut response = Response::json(401, serde_json::json!({"a": 1}));
+        response.challenge = true;
+        write_response(&mut out, &response).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "HTTP/1.1 401 Unauthorized\r\nContent-Length: 8\r\nConnection: close\r\n\
+             Content-Type: application/json\r\nWWW-Authenticate: Bearer\r\n\r\n{\"a\":1}\n"
+        );
+    }
+}
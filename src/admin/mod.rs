@@ -0,0 +1,30 @@
+//! Local HTTP/JSON admin API (`admin` feature).
+//!
+//! [`AdminServer`] exposes a [`FileResolver`](crate::FileResolver) to
+//! tooling that cannot link Rust. It listens on a loopback TCP port or a
+//! Unix socket and requires `Authorization: Bearer <token>` on every
+//! request. Bodies are JSON: configs use the
+//! [`ResolverConfig`](crate::ResolverConfig) fields and states use the
+//! [`DomainStatus`](crate::DomainStatus) representation.
+//!
+//! | Request | Response |
+//! |---------|----------|
+//! | `GET /v1/domains` | `200 {"domains": [..]}` |
+//! | `POST /v1/domains[?permanent=true]` with a config | `201 {"domain": .., "status": {..}}` |
+//! | `GET /v1/domains/{domain}` | `200 {"domain": .., "status": {..}}` |
+//! | `DELETE /v1/domains/{domain}` | `204` |
+//! | `POST /v1/cleanup` | `200 {"removed": n}` |
+//!
+//! Failures return `{"error": {"code": .., "message": ..}}` with a 4xx or
+//! 5xx status.
+//!
+//! ```rust,ignore
+//! let resolver = Arc::new(FileResolver::new("myapp"));
+//! let handle = AdminServer::new(resolver, token).spawn("127.0.0.1:5380".parse::<SocketAddr>()?)?;
+//! // curl -H "Authorization: Bearer $TOKEN" http://127.0.0.1:5380/v1/domains
+//! ```
+
+mod http;
+mod server;
+
+pub use server::{AdminAddr, AdminHandle, AdminServer};
@@ -0,0 +1,704 @@
+//! Listener, authentication and routing of the admin API.
+
+use std::fmt;
+use std::io::{self, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::os::fd::{AsRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use serde_json::json;
+
+use super::http::{ParseError, Request, Response, read_request, write_response};
+use crate::config::{ResolverConfig, validate_domain};
+use crate::error::{ResolverError, Result};
+use crate::file_resolver::FileResolver;
+use crate::util::{bind_unix_socket, wait_readable};
+
+/// Default permissions of a Unix listening socket: owner only.
+const DEFAULT_SOCKET_MODE: u32 = 0o600;
+
+/// How often the accept loop checks whether it has been shut down.
+const STOP_POLL: Duration = Duration::from_millis(100);
+
+/// How long a client may take to send its request or read the response.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Default for [`AdminServer::max_connections`].
+const DEFAULT_MAX_CONNECTIONS: usize = 64;
+
+/// Where an [`AdminServer`] listens.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AdminAddr {
+    /// A TCP address; must be a loopback address.
+    Tcp(SocketAddr),
+    /// A Unix domain socket path.
+    Unix(PathBuf),
+}
+
+impl From<SocketAddr> for AdminAddr {
+    fn from(addr: SocketAddr) -> Self {
+        Self::Tcp(addr)
+    }
+}
+
+impl From<PathBuf> for AdminAddr {
+    fn from(path: PathBuf) -> Self {
+        Self::Unix(path)
+    }
+}
+
+impl From<&Path> for AdminAddr {
+    fn from(path: &Path) -> Self {
+        Self::Unix(path.to_path_buf())
+    }
+}
+
+impl fmt::Display for AdminAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(addr) => write!(f, "http://{addr}"),
+            Self::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// Serves [`FileResolver`] state and operations as HTTP/JSON.
+///
+/// Every request must carry `Authorization: Bearer <token>`. TCP listeners
+/// are restricted to loopback addresses; each connection serves a single
+/// request on its own thread, up to
+/// [`max_connections`](Self::max_connections) at once. See the
+/// [module docs](super) for the endpoints.
+pub struct AdminServer {
+    resolver: Arc<FileResolver>,
+    token: String,
+    socket_mode: u32,
+    max_connections: usize,
+    /// Connections currently being served.
+    active: AtomicUsize,
+}
+
+impl AdminServer {
+    /// Creates a server performing operations through `resolver`, accepting
+    /// requests that present `token`.
+    #[must_use]
+    pub fn new(resolver: Arc<FileResolver>, token: impl Into<String>) -> Self {
+        Self {
+            resolver,
+            token: token.into(),
+            socket_mode: DEFAULT_SOCKET_MODE,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            active: AtomicUsize::new(0),
+        }
+    }
+
+    /// Sets the permissions of a Unix socket file (default: `0o600`).
+    #[must_use]
+    pub const fn socket_mode(mut self, mode: u32) -> Self {
+        self.socket_mode = mode;
+        self
+    }
+
+    /// Limits how many connections are served at once (default: 64).
+    /// Connections over the limit get `503 Service Unavailable`.
+    #[must_use]
+    pub const fn max_connections(mut self, max: usize) -> Self {
+        self.max_connections = max;
+        self
+    }
+
+    /// Binds `addr` and serves requests on the current thread forever.
+    ///
+    /// Failing to accept a client (e.g. out of file descriptors) is logged
+    /// and does not stop the server.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ResolverError::Io`] if the address is not loopback, the
+    /// token is empty, or binding fails.
+    pub fn serve(self, addr: impl Into<AdminAddr>) -> Result<()> {
+        let (listener, _) = self.bind(&addr.into())?;
+        let server = Arc::new(self);
+        loop {
+            match listener.accept() {
+                Ok(stream) => server.spawn_connection(stream),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    tracing::warn!(error = %e, "Failed to accept admin client");
+                    // Back off so a persistent error such as EMFILE does
+                    // not spin.
+                    std::thread::sleep(STOP_POLL);
+                }
+            }
+        }
+    }
+
+    /// Binds `addr` and serves requests on a background thread until the
+    /// returned handle is shut down or dropped.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`serve`](Self::serve), or [`ResolverError::Io`] if the
+    /// thread cannot be spawned.
+    pub fn spawn(self, addr: impl Into<AdminAddr>) -> Result<AdminHandle> {
+        let (listener, addr) = self.bind(&addr.into())?;
+        let server = Arc::new(self);
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = Arc::clone(&stop);
+        listener.set_nonblocking(true)?;
+        let thread = std::thread::Builder::new()
+            .name("resolver-admin".into())
+            .spawn(move || {
+                while !thread_stop.load(Ordering::Acquire) {
+                    match wait_readable(&listener, STOP_POLL) {
+                        Ok(false) => continue,
+                        Ok(true) => {}
+                        Err(e) => {
+                            tracing::error!(error = %e, "Admin listener failed");
+                            break;
+                        }
+                    }
+                    match listener.accept() {
+                        Ok(stream) => server.spawn_connection(stream),
+                        Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                        Err(e) => tracing::warn!(error = %e, "Failed to accept admin client"),
+                    }
+                }
+            })?;
+        Ok(AdminHandle {
+            addr,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// Binds the listener, returning it with its actual address.
+    fn bind(&self, addr: &AdminAddr) -> Result<(Listener, AdminAddr)> {
+        if self.token.is_empty() {
+            return Err(invalid_input("admin token must not be empty"));
+        }
+        let bound = match addr {
+            AdminAddr::Tcp(addr) => {
+                if !addr.ip().is_loopback() {
+                    return Err(invalid_input(format!(
+                        "admin API only listens on loopback addresses, not {}",
+                        addr.ip()
+                    )));
+                }
+                let listener = TcpListener::bind(addr)?;
+                let local = listener.local_addr()?;
+                (Listener::Tcp(listener), AdminAddr::Tcp(local))
+            }
+            AdminAddr::Unix(path) => (
+                Listener::Unix(bind_unix_socket(path, self.socket_mode)?),
+                AdminAddr::Unix(path.clone()),
+            ),
+        };
+        tracing::info!(addr = %bound.1, "Resolver admin API listening");
+        Ok(bound)
+    }
+
+    fn spawn_connection(self: &Arc<Self>, stream: Stream) {
+        if self.active.fetch_add(1, Ordering::AcqRel) >= self.max_connections {
+            self.active.fetch_sub(1, Ordering::AcqRel);
+            tracing::warn!(
+                max = self.max_connections,
+                "Refusing admin client: too many connections"
+            );
+            let result = match stream {
+                Stream::Tcp(s) => refuse(&s),
+                Stream::Unix(s) => refuse(&s),
+            };
+            if let Err(e) = result {
+                tracing::debug!(error = %e, "Failed to refuse admin client");
+            }
+            return;
+        }
+        let server = Arc::clone(self);
+        let spawned = std::thread::Builder::new()
+            .name("resolver-admin-conn".into())
+            .spawn(move || {
+                let result = match stream {
+                    Stream::Tcp(s) => server.handle_connection(&s),
+                    Stream::Unix(s) => server.handle_connection(&s),
+                };
+                if let Err(e) = result {
+                    tracing::debug!(error = %e, "Admin connection ended with error");
+                }
+                server.active.fetch_sub(1, Ordering::AcqRel);
+            });
+        if let Err(e) = spawned {
+            self.active.fetch_sub(1, Ordering::AcqRel);
+            tracing::warn!(error = %e, "Failed to spawn admin connection thread");
+        }
+    }
+
+    fn handle_connection<S>(&self, stream: &S) -> io::Result<()>
+    where
+        S: Connection,
+        for<'a> &'a S: Read + Write,
+    {
+        // Accepted sockets inherit O_NONBLOCK on macOS.
+        stream.configure()?;
+        let response = match read_request(&mut BufReader::new(stream)) {
+            Ok(Some(request)) => self.route(&request),
+            Ok(None) => return Ok(()),
+            Err(ParseError::Io(e)) => return Err(e),
+            Err(e @ ParseError::TooLarge) => error_response(413, "too_large", &e.to_string()),
+            Err(e) => error_response(400, "bad_request", &e.to_string()),
+        };
+        let mut writer = stream;
+        write_response(&mut writer, &response)
+    }
+
+    fn route(&self, request: &Request) -> Response {
+        if !self.authorized(request) {
+            let mut response =
+                error_response(401, "unauthorized", "missing or invalid bearer token");
+            response.challenge = true;
+            return response;
+        }
+        tracing::debug!(method = %request.method, path = %request.path, "Admin request");
+        let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+        let result = match (request.method.as_str(), segments.as_slice()) {
+            ("GET", ["v1", "domains"]) => self.list(),
+            ("POST", ["v1", "domains"]) => self.register(request),
+            ("GET", ["v1", "domains", domain]) => self.status(domain),
+            ("DELETE", ["v1", "domains", domain]) => self.unregister(domain),
+            ("POST", ["v1", "cleanup"]) => self.cleanup(),
+            (_, ["v1", "domains" | "cleanup"] | ["v1", "domains", _]) => {
+                return error_response(405, "method_not_allowed", "method not allowed");
+            }
+            _ => return error_response(404, "not_found", "no such endpoint"),
+        };
+        result.unwrap_or_else(|e| resolver_error_response(&e))
+    }
+
+    fn authorized(&self, request: &Request) -> bool {
+        request
+            .header("authorization")
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|token| constant_time_eq(token.trim().as_bytes(), self.token.as_bytes()))
+    }
+
+    fn list(&self) -> Result<Response> {
+        Ok(Response::json(
+            200,
+            json!({ "domains": self.resolver.list()? }),
+        ))
+    }
+
+    fn register(&self, request: &Request) -> Result<Response> {
+        let config: ResolverConfig = serde_json::from_slice(&request.body)
+            .map_err(|e| ResolverError::InvalidConfig(e.to_string()))?;
+        config.validate()?;
+        if request.query_flag("permanent") {
+            self.resolver.register_permanent(&config)?;
+        } else {
+            self.resolver.register(&config)?;
+        }
+        let status = self.resolver.status(&config.domain)?;
+        Ok(Response::json(
+            201,
+            json!({ "domain": config.domain, "status": status }),
+        ))
+    }
+
+    fn status(&self, domain: &str) -> Result<Response> {
+        validate_domain(domain)?;
+        let status = self.resolver.status(domain)?;
+        Ok(Response::json(
+            200,
+            json!({ "domain": domain, "status": status }),
+        ))
+    }
+
+    fn unregister(&self, domain: &str) -> Result<Response> {
+        validate_domain(domain)?;
+        self.resolver.unregister(domain)?;
+        Ok(Response::empty(204))
+    }
+
+    fn cleanup(&self) -> Result<Response> {
+        Ok(Response::json(
+            200,
+            json!({ "removed": self.resolver.cleanup_orphaned()? }),
+        ))
+    }
+}
+
+fn invalid_input(message: impl Into<String>) -> ResolverError {
+    io::Error::new(io::ErrorKind::InvalidInput, message.into()).into()
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+fn error_response(status: u16, code: &str, message: &str) -> Response {
+    Response::json(
+        status,
+        json!({ "error": { "code": code, "message": message } }),
+    )
+}
+
+/// Answers `503` on the accept thread. The request is read first, briefly,
+/// so closing with it unread does not reset the connection before the
+/// client sees the response.
+fn refuse<S>(stream: &S) -> io::Result<()>
+where
+    S: Connection,
+    for<'a> &'a S: Read + Write,
+{
+    stream.configure_refusal()?;
+    let _ = read_request(&mut BufReader::new(stream));
+    let mut writer = stream;
+    write_response(
+        &mut writer,
+        &error_response(503, "unavailable", "too many connections"),
+    )
+}
+
+/// Maps a resolver error to an HTTP status and a `snake_case` error code.
+fn resolver_error_response(e: &ResolverError) -> Response {
+    let (status, code) = match e {
+        ResolverError::Io(io) if io.kind() == io::ErrorKind::PermissionDenied => {
+            (500, "permission_denied")
+        }
+        ResolverError::Io(_) => (500, "io"),
+        ResolverError::DirNotFound { .. } => (500, "dir_not_found"),
+        ResolverError::NotManaged { .. } => (409, "not_managed"),
+        ResolverError::OwnedByOther { .. } => (409, "owned_by_other"),
+        ResolverError::NotOwner { .. } => (409, "not_owner"),
+        ResolverError::NotFound { .. } => (404, "not_found"),
+        ResolverError::Locked { .. } => (409, "locked"),
+        ResolverError::InvalidConfig(_) => (400, "invalid_config"),
+        ResolverError::Unauthorized(_) => (403, "unauthorized"),
+        ResolverError::Unreachable { .. } => (502, "unreachable"),
+        ResolverError::Signing(_) | ResolverError::Protocol(_) => (500, "internal"),
+    };
+    error_response(status, code, &e.to_string())
+}
+
+enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+enum Stream {
+    Tcp(TcpStream),
+    Unix(UnixStream),
+}
+
+impl Listener {
+    fn accept(&self) -> io::Result<Stream> {
+        Ok(match self {
+            Self::Tcp(l) => Stream::Tcp(l.accept()?.0),
+            Self::Unix(l) => Stream::Unix(l.accept()?.0),
+        })
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        match self {
+            Self::Tcp(l) => l.set_nonblocking(nonblocking),
+            Self::Unix(l) => l.set_nonblocking(nonblocking),
+        }
+    }
+}
+
+impl AsRawFd for Listener {
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            Self::Tcp(l) => l.as_raw_fd(),
+            Self::Unix(l) => l.as_raw_fd(),
+        }
+    }
+}
+
+/// Per-connection socket setup shared by TCP and Unix streams.
+trait Connection {
+    fn set_timeouts(&self, read: Duration, write: Duration) -> io::Result<()>;
+
+    fn configure(&self) -> io::Result<()> {
+        self.set_timeouts(CLIENT_TIMEOUT, CLIENT_TIMEOUT)
+    }
+
+    /// Short timeouts, as refusals are answered on the accept thread.
+    fn configure_refusal(&self) -> io::Result<()> {
+        self.set_timeouts(STOP_POLL, STOP_POLL)
+    }
+}
+
+impl Connection for TcpStream {
+    fn set_timeouts(&self, read: Duration, write: Duration) -> io::Result<()> {
+        self.set_nonblocking(false)?;
+        self.set_read_timeout(Some(read))?;
+        self.set_write_timeout(Some(write))
+    }
+}
+
+impl Connection for UnixStream {
+    fn set_timeouts(&self, read: Duration, write: Duration) -> io::Result<()> {
+        self.set_nonblocking(false)?;
+        self.set_read_timeout(Some(read))?;
+        self.set_write_timeout(Some(write))
+    }
+}
+
+/// Handle to an [`AdminServer`] running in the background.
+///
+/// Shutting down (or dropping) stops accepting connections and removes a
+/// Unix socket file.
+#[derive(Debug)]
+pub struct AdminHandle {
+    addr: AdminAddr,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl AdminHandle {
+    /// Address the server listens on, with the actual port if port 0 was
+    /// requested.
+    #[must_use]
+    pub const fn addr(&self) -> &AdminAddr {
+        &self.addr
+    }
+
+    /// Stops the server and waits for its accept loop to exit.
+    pub fn shutdown(mut self) {
+        self.stop_accepting();
+    }
+
+    fn stop_accepting(&mut self) {
+        let Some(thread) = self.thread.take() else {
+            return;
+        };
+        self.stop.store(true, Ordering::Release);
+        let _ = thread.join();
+        if let AdminAddr::Unix(path) = &self.addr {
+            if let Err(e) = std::fs::remove_file(path) {
+                tracing::debug!(path = %path.display(), error = %e, "Failed to remove admin socket");
+            }
+        }
+    }
+}
+
+impl Drop for AdminHandle {
+    fn drop(&mut self) {
+        self.stop_accepting();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufRead;
+
+    const TOKEN: &str = "s3cret";
+
+    struct Fixture {
+        handle: AdminHandle,
+        resolver_dir: tempfile::TempDir,
+    }
+
+    fn start() -> Fixture {
+        let resolver_dir = tempfile::tempdir().unwrap();
+        let resolver = Arc::new(FileResolver::new("test").dir(resolver_dir.path()));
+        let handle = AdminServer::new(resolver, TOKEN)
+            .spawn(SocketAddr::from(([127, 0, 0, 1], 0)))
+            .unwrap();
+        Fixture {
+            handle,
+            resolver_dir,
+        }
+    }
+
+    /// Sends one request and returns the status code and parsed JSON body.
+    fn send(
+        mut stream: impl Read + Write,
+        method: &str,
+        target: &str,
+        token: Option<&str>,
+        body: &str,
+    ) -> (u16, Option<serde_json::Value>) {
+        let auth = token
+            .map(|t| format!("Authorization: Bearer {t}\r\n"))
+            .unwrap_or_default();
+        write!(
+            stream,
+            "{method} {target} HTTP/1.1\r\nHost: localhost\r\n{auth}Content-Length: {}\r\n\r\n{body}",
+            body.len()
+        )
+        .unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut status_line = String::new();
+        reader.read_line(&mut status_line).unwrap();
+        let status = status_line.split(' ').nth(1).unwrap().parse().unwrap();
+        let mut rest = String::new();
+        reader.read_to_string(&mut rest).unwrap();
+        let (_, body) = rest.split_once("\r\n\r\n").unwrap();
+        (status, serde_json::from_str(body).ok())
+    }
+
+    fn tcp(fx: &Fixture, method: &str, target: &str, body: &str) -> (u16, serde_json::Value) {
+        let AdminAddr::Tcp(addr) = fx.handle.addr() else {
+            unreachable!()
+        };
+        let (status, body) = send(
+            TcpStream::connect(addr).unwrap(),
+            method,
+            target,
+            Some(TOKEN),
+            body,
+        );
+        (status, body.unwrap_or(serde_json::Value::Null))
+    }
+
+    #[test]
+    fn register_status_list_unregister() {
+        let fx = start();
+        let config =
+            r#"{"domain":"a.local","nameserver":"127.0.0.1","port":5553,"search_order":1}"#;
+
+        let (status, body) = tcp(&fx, "POST", "/v1/domains", config);
+        assert_eq!(status, 201);
+        assert_eq!(body["status"]["state"], "managed_ephemeral");
+        let (status, _) = tcp(
+            &fx,
+            "POST",
+            "/v1/domains?permanent=true",
+            &config.replace("a.", "b."),
+        );
+        assert_eq!(status, 201);
+
+        let (status, body) = tcp(&fx, "GET", "/v1/domains", "");
+        assert_eq!(status, 200);
+        let mut domains: Vec<String> = serde_json::from_value(body["domains"].clone()).unwrap();
+        domains.sort();
+        assert_eq!(domains, ["a.local", "b.local"]);
+        let (_, body) = tcp(&fx, "GET", "/v1/domains/b.local", "");
+        assert_eq!(
+            body,
+            json!({"domain": "b.local", "status": {"state": "managed_permanent"}})
+        );
+
+        assert_eq!(tcp(&fx, "DELETE", "/v1/domains/a.local", "").0, 204);
+        assert!(!fx.resolver_dir.path().join("a.local").exists());
+        let (_, body) = tcp(&fx, "POST", "/v1/cleanup", "");
+        assert_eq!(body, json!({"removed": 0}));
+    }
+
+    #[test]
+    fn errors_map_to_statuses() {
+        let fx = start();
+        std::fs::write(
+            fx.resolver_dir.path().join("c.local"),
+            "nameserver 1.1.1.1\n",
+        )
+        .unwrap();
+
+        let (status, body) = tcp(&fx, "DELETE", "/v1/domains/c.local", "");
+        assert_eq!(status, 409);
+        assert_eq!(body["error"]["code"], "not_managed");
+        let bad = r#"{"domain":"../x","nameserver":"127.0.0.1","port":53,"search_order":1}"#;
+        assert_eq!(tcp(&fx, "POST", "/v1/domains", bad).0, 400);
+        assert_eq!(tcp(&fx, "POST", "/v1/domains", "not json").0, 400);
+        assert_eq!(tcp(&fx, "PUT", "/v1/domains", "").0, 405);
+        assert_eq!(tcp(&fx, "GET", "/v2", "").0, 404);
+    }
+
+    #[test]
+    fn requires_bearer_token() {
+        let fx = start();
+        let AdminAddr::Tcp(addr) = fx.handle.addr() else {
+            unreachable!()
+        };
+        for token in [None, Some("wrong")] {
+            let (status, body) = send(
+                TcpStream::connect(addr).unwrap(),
+                "GET",
+                "/v1/domains",
+                token,
+                "",
+            );
+            assert_eq!(status, 401);
+            assert_eq!(body.unwrap()["error"]["code"], "unauthorized");
+        }
+    }
+
+    #[test]
+    fn connections_over_the_limit_get_503() {
+        let resolver_dir = tempfile::tempdir().unwrap();
+        let resolver = Arc::new(FileResolver::new("test").dir(resolver_dir.path()));
+        let handle = AdminServer::new(resolver, TOKEN)
+            .max_connections(1)
+            .spawn(SocketAddr::from(([127, 0, 0, 1], 0)))
+            .unwrap();
+        let AdminAddr::Tcp(addr) = handle.addr() else {
+            unreachable!()
+        };
+        let list = || {
+            send(
+                TcpStream::connect(addr).unwrap(),
+                "GET",
+                "/v1/domains",
+                Some(TOKEN),
+                "",
+            )
+        };
+
+        // Holds the only slot until dropped.
+        let idle = TcpStream::connect(addr).unwrap();
+        let (status, body) = list();
+        assert_eq!(status, 503);
+        assert_eq!(body.unwrap()["error"]["code"], "unavailable");
+
+        drop(idle);
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while list().0 != 200 {
+            assert!(std::time::Instant::now() < deadline, "slot never freed");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    }
+
+    #[test]
+    fn refuses_non_loopback_and_empty_token() {
+        let resolver = Arc::new(FileResolver::new("test").dir("/nonexistent"));
+        let public = SocketAddr::from(([0, 0, 0, 0], 0));
+        assert!(
+            AdminServer::new(Arc::clone(&resolver), TOKEN)
+                .spawn(public)
+                .is_err()
+        );
+        let local = SocketAddr::from(([127, 0, 0, 1], 0));
+        assert!(AdminServer::new(resolver, "").spawn(local).is_err());
+    }
+
+    #[test]
+    fn serves_unix_socket() {
+        let resolver_dir = tempfile::tempdir().unwrap();
+        let socket_dir = tempfile::tempdir_in("/tmp").unwrap();
+        let path = socket_dir.path().join("admin.sock");
+        let resolver = Arc::new(FileResolver::new("test").dir(resolver_dir.path()));
+        let handle = AdminServer::new(resolver, TOKEN)
+            .spawn(path.as_path())
+            .unwrap();
+
+        let (status, body) = send(
+            UnixStream::connect(&path).unwrap(),
+            "GET",
+            "/v1/domains",
+            Some(TOKEN),
+            "",
+        );
+        assert_eq!(status, 200);
+        assert_eq!(body.unwrap(), json!({"domains": []}));
+        handle.shutdown();
+        assert!(!path.exists());
+    }
+}
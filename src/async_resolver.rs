@@ -0,0 +1,192 @@
+//! Async wrapper for use inside a tokio runtime (`tokio` feature).
+//!
+//! [`FileResolver`] does blocking filesystem I/O. [`AsyncFileResolver`] runs
+//! each call on tokio's blocking thread pool so it never stalls a runtime
+//! worker. Semantics and errors are identical to the blocking methods.
+
+use std::sync::Arc;
+
+use crate::config::ResolverConfig;
+use crate::error::{ResolverError, Result};
+use crate::file_resolver::FileResolver;
+
+/// Async counterpart of [`FileResolver`].
+///
+/// Cheap to clone; clones share the same underlying resolver (and therefore
+/// the same held ownership locks).
+///
+/// ```rust,ignore
+/// use macos_resolver::{AsyncFileResolver, FileResolver, ResolverConfig};
+///
+/// let resolver = AsyncFileResolver::new(FileResolver::new("myapp"));
+/// resolver
+///     .register(&ResolverConfig::new("myapp.local", "127.0.0.1", 5553))
+///     .await?;
+/// ```
+#[derive(Clone)]
+pub struct AsyncFileResolver {
+    inner: Arc<FileResolver>,
+}
+
+impl AsyncFileResolver {
+    /// Wraps a configured [`FileResolver`].
+    #[must_use]
+    pub fn new(resolver: FileResolver) -> Self {
+        Self {
+            inner: Arc::new(resolver),
+        }
+    }
+
+    /// The wrapped blocking resolver, e.g. for use with
+    /// [`Watchdog`](crate::Watchdog).
+    #[must_use]
+    pub const fn blocking(&self) -> &Arc<FileResolver> {
+        &self.inner
+    }
+
+    /// Async version of [`FileResolver::register`].
+    ///
+    /// # Errors
+    ///
+    /// Same as [`FileResolver::register`].
+    pub async fn register(&self, config: &ResolverConfig) -> Result<()> {
+        let config = config.clone();
+        self.run(move |r| r.register(&config)).await
+    }
+
+    /// Async version of [`FileResolver::register_permanent`].
+    ///
+    /// # Errors
+    ///
+    /// Same as [`FileResolver::register_permanent`].
+    pub async fn register_permanent(&self, config: &ResolverConfig) -> Result<()> {
+        let config = config.clone();
+        self.run(move |r| r.register_permanent(&config)).await
+    }
+
+    /// Async version of [`FileResolver::unregister`].
+    ///
+    /// # Errors
+    ///
+    /// Same as [`FileResolver::unregister`].
+    pub async fn unregister(&self, domain: &str) -> Result<()> {
+        let domain = domain.to_string();
+        self.run(move |r| r.unregister(&domain)).await
+    }
+
+    /// Async version of [`FileResolver::list`].
+    ///
+    /// # Errors
+    ///
+    /// Same as [`FileResolver::list`].
+    pub async fn list(&self) -> Result<Vec<String>> {
+        self.run(FileResolver::list).await
+    }
+
+    /// Async version of [`FileResolver::is_registered`].
+    pub async fn is_registered(&self, domain: &str) -> bool {
+        let domain = domain.to_string();
+        self.run(move |r| Ok(r.is_registered(&domain)))
+            .await
+            .unwrap_or(false)
+    }
+
+    /// Async version of [`FileResolver::cleanup_orphaned`].
+    ///
+    /// # Errors
+    ///
+    /// Same as [`FileResolver::cleanup_orphaned`].
+    pub async fn cleanup_orphaned(&self) -> Result<usize> {
+        self.run(FileResolver::cleanup_orphaned).await
+    }
+
+    /// Runs `f` on the blocking pool, re-raising its panics.
+    async fn run<T, F>(&self, f: F) -> Result<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&FileResolver) -> Result<T> + Send + 'static,
+    {
+        let inner = Arc::clone(&self.inner);
+        match tokio::task::spawn_blocking(move || f(&inner)).await {
+            Ok(result) => result,
+            Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+            Err(e) => Err(ResolverError::Io(std::io::Error::other(e))),
+        }
+    }
+}
+
+impl From<FileResolver> for AsyncFileResolver {
+    fn from(resolver: FileResolver) -> Self {
+        Self::new(resolver)
+    }
+}
+
+impl From<Arc<FileResolver>> for AsyncFileResolver {
+    fn from(inner: Arc<FileResolver>) -> Self {
+        Self { inner }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::ManagedHeader;
+
+    fn setup() -> (tempfile::TempDir, AsyncFileResolver) {
+        let dir = tempfile::tempdir().unwrap();
+        let resolver = FileResolver::new("test").dir(dir.path());
+        (dir, AsyncFileResolver::new(resolver))
+    }
+
+    #[tokio::test]
+    async fn register_list_unregister() {
+        let (dir, resolver) = setup();
+        let config = ResolverConfig::new("a.local", "127.0.0.1", 5553);
+
+        resolver.register(&config).await.unwrap();
+        resolver
+            .register_permanent(&ResolverConfig::new("b.local", "127.0.0.1", 5553))
+            .await
+            .unwrap();
+        assert!(resolver.is_registered("a.local").await);
+
+        let mut domains = resolver.list().await.unwrap();
+        domains.sort();
+        assert_eq!(domains, ["a.local", "b.local"]);
+
+        resolver.unregister("a.local").await.unwrap();
+        assert!(!resolver.is_registered("a.local").await);
+        assert!(dir.path().join("b.local").exists());
+    }
+
+    #[tokio::test]
+    async fn errors_match_blocking_api() {
+        let (dir, resolver) = setup();
+        std::fs::write(dir.path().join("c.local"), "nameserver 1.1.1.1\n").unwrap();
+
+        assert!(matches!(
+            resolver.unregister("c.local").await,
+            Err(ResolverError::NotManaged { .. })
+        ));
+        resolver.unregister("missing.local").await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn cleanup_removes_dead_owner() {
+        let dir = tempfile::tempdir().unwrap();
+        // Pid 4242 stands in for an owner that has exited.
+        let resolver = AsyncFileResolver::new(
+            FileResolver::new("test")
+                .dir(dir.path())
+                .liveness(|h: &ManagedHeader| h.pid != Some(4242)),
+        );
+        std::fs::write(
+            dir.path().join("dead.local"),
+            "# managed by test (v=2, pid=4242)\nnameserver 127.0.0.1\n",
+        )
+        .unwrap();
+
+        assert_eq!(resolver.cleanup_orphaned().await.unwrap(), 1);
+        assert!(!dir.path().join("dead.local").exists());
+    }
+}
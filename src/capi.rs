@@ -0,0 +1,486 @@
+//! C ABI for Swift and Objective-C callers (`capi` feature).
+//!
+//! Build a linkable library with
+//! `cargo rustc --release --features capi --crate-type cdylib` (or
+//! `staticlib`) and include `include/macos_resolver.h`. Every fallible call
+//! returns an [`MrStatus`]; on failure, [`mr_last_error_message`] describes
+//! the error on the calling thread.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString, c_char};
+use std::panic::{AssertUnwindSafe, catch_unwind};
+
+use crate::config::{ResolverConfig, validate_domain};
+use crate::error::ResolverError;
+use crate::file_resolver::FileResolver;
+
+/// Opaque resolver handle owned by the C caller.
+pub struct MrResolver(FileResolver);
+
+/// Result of a C API call. `MR_OK` is zero; errors mirror [`ResolverError`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MrStatus {
+    /// Success.
+    MrOk = 0,
+    /// [`ResolverError::Io`] other than permission denied.
+    MrErrIo = 1,
+    /// [`ResolverError::Io`] with `PermissionDenied` (not running as root).
+    MrErrPermissionDenied = 2,
+    /// [`ResolverError::DirNotFound`].
+    MrErrDirNotFound = 3,
+    /// [`ResolverError::NotManaged`].
+    MrErrNotManaged = 4,
+    /// [`ResolverError::OwnedByOther`].
+    MrErrOwnedByOther = 5,
+    /// [`ResolverError::NotOwner`].
+    MrErrNotOwner = 6,
+    /// [`ResolverError::NotFound`].
+    MrErrNotFound = 7,
+    /// [`ResolverError::Locked`].
+    MrErrLocked = 8,
+    /// [`ResolverError::InvalidConfig`].
+    MrErrInvalidConfig = 9,
+    /// Any other [`ResolverError`].
+    MrErrOther = 10,
+    /// A required pointer argument was null.
+    MrErrNullArgument = 11,
+    /// A string argument was not valid UTF-8.
+    MrErrInvalidUtf8 = 12,
+    /// The call panicked; the handle should not be used further.
+    MrErrPanic = 13,
+    /// [`ResolverError::Unreachable`].
+    MrErrUnreachable = 14,
+}
+
+impl From<&ResolverError> for MrStatus {
+    fn from(e: &ResolverError) -> Self {
+        match e {
+            ResolverError::Io(io) if io.kind() == std::io::ErrorKind::PermissionDenied => {
+                Self::MrErrPermissionDenied
+            }
+            ResolverError::Io(_) => Self::MrErrIo,
+            ResolverError::DirNotFound { .. } => Self::MrErrDirNotFound,
+            ResolverError::NotManaged { .. } => Self::MrErrNotManaged,
+            ResolverError::OwnedByOther { .. } => Self::MrErrOwnedByOther,
+            ResolverError::NotOwner { .. } => Self::MrErrNotOwner,
+            ResolverError::NotFound { .. } => Self::MrErrNotFound,
+            ResolverError::Locked { .. } => Self::MrErrLocked,
+            ResolverError::InvalidConfig(_) => Self::MrErrInvalidConfig,
+            ResolverError::Unreachable { .. } => Self::MrErrUnreachable,
+            _ => Self::MrErrOther,
+        }
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: impl Into<Vec<u8>>) {
+    let message = CString::new(message).unwrap_or_else(|_| c"(message contained NUL)".into());
+    LAST_ERROR.with(|slot| *slot.borrow_mut() = Some(message));
+}
+
+/// Argument errors detected before calling into the resolver.
+enum Failure {
+    Resolver(ResolverError),
+    Status(MrStatus, &'static str),
+}
+
+impl From<ResolverError> for Failure {
+    fn from(e: ResolverError) -> Self {
+        Self::Resolver(e)
+    }
+}
+
+/// Runs `f`, translating errors and panics into a status and the
+/// thread's last error message.
+fn guard(f: impl FnOnce() -> Result<(), Failure>) -> MrStatus {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => {
+            LAST_ERROR.with(|slot| slot.borrow_mut().take());
+            MrStatus::MrOk
+        }
+        Ok(Err(Failure::Resolver(e))) => {
+            set_last_error(e.to_string());
+            MrStatus::from(&e)
+        }
+        Ok(Err(Failure::Status(status, message))) => {
+            set_last_error(message);
+            status
+        }
+        Err(_) => {
+            set_last_error("panic inside macos-resolver");
+            MrStatus::MrErrPanic
+        }
+    }
+}
+
+/// Borrows a C string argument as UTF-8.
+///
+/// # Safety
+///
+/// `ptr` must be null or point to a NUL-terminated string that outlives
+/// the returned reference.
+unsafe fn str_arg<'a>(ptr: *const c_char) -> Result<&'a str, Failure> {
+    if ptr.is_null() {
+        return Err(Failure::Status(
+            MrStatus::MrErrNullArgument,
+            "string argument is null",
+        ));
+    }
+    // SAFETY: non-null and NUL-terminated per the caller's contract.
+    unsafe { CStr::from_ptr(ptr) }.to_str().map_err(|_| {
+        Failure::Status(
+            MrStatus::MrErrInvalidUtf8,
+            "string argument is not valid UTF-8",
+        )
+    })
+}
+
+/// Borrows the resolver behind a handle.
+///
+/// # Safety
+///
+/// `ptr` must be null or a live handle from [`mr_resolver_new`].
+unsafe fn resolver_arg<'a>(ptr: *const MrResolver) -> Result<&'a FileResolver, Failure> {
+    // SAFETY: null or a live handle per the caller's contract.
+    unsafe { ptr.as_ref() }
+        .map(|handle| &handle.0)
+        .ok_or(Failure::Status(
+            MrStatus::MrErrNullArgument,
+            "resolver handle is null",
+        ))
+}
+
+/// Creates a resolver handle for `prefix` (see [`FileResolver::new`]). If
+/// `dir` is non-null it overrides the resolver directory.
+///
+/// Returns null and sets the last error message if an argument is invalid.
+/// Free the handle with [`mr_resolver_free`].
+///
+/// # Safety
+///
+/// `prefix` must be a NUL-terminated string; `dir` must be null or one.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mr_resolver_new(
+    prefix: *const c_char,
+    dir: *const c_char,
+) -> *mut MrResolver {
+    let mut handle = std::ptr::null_mut();
+    guard(|| {
+        // SAFETY: forwarded from this function's contract.
+        let prefix = unsafe { str_arg(prefix) }?;
+        let mut resolver = FileResolver::new(prefix);
+        if !dir.is_null() {
+            // SAFETY: forwarded from this function's contract.
+            resolver = resolver.dir(unsafe { str_arg(dir) }?);
+        }
+        handle = Box::into_raw(Box::new(MrResolver(resolver)));
+        Ok(())
+    });
+    handle
+}
+
+/// Frees a handle from [`mr_resolver_new`]. Null is ignored.
+///
+/// Registrations made through the handle stay in place; their files are
+/// cleaned up by a later [`mr_cleanup_orphaned`] once this process exits.
+///
+/// # Safety
+///
+/// `resolver` must be null or a handle that has not been freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mr_resolver_free(resolver: *mut MrResolver) {
+    if !resolver.is_null() {
+        // SAFETY: the handle came from `Box::into_raw` and is freed once.
+        drop(unsafe { Box::from_raw(resolver) });
+    }
+}
+
+/// Builds a config from C arguments.
+///
+/// # Safety
+///
+/// `domain` and `nameserver` must be null or NUL-terminated strings.
+unsafe fn config_arg(
+    domain: *const c_char,
+    nameserver: *const c_char,
+    port: u16,
+    search_order: u32,
+) -> Result<ResolverConfig, Failure> {
+    // SAFETY: forwarded from this function's contract.
+    let (domain, nameserver) = unsafe { (str_arg(domain)?, str_arg(nameserver)?) };
+    let config = ResolverConfig::new(domain, nameserver, port).with_search_order(search_order);
+    config.validate()?;
+    Ok(config)
+}
+
+/// Registers a domain bound to this process (see [`FileResolver::register`]).
+///
+/// # Safety
+///
+/// `resolver` must be a live handle; `domain` and `nameserver` must be
+/// NUL-terminated strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mr_register(
+    resolver: *const MrResolver,
+    domain: *const c_char,
+    nameserver: *const c_char,
+    port: u16,
+    search_order: u32,
+) -> MrStatus {
+    guard(|| {
+        // SAFETY: forwarded from this function's contract.
+        let (resolver, config) = unsafe {
+            (
+                resolver_arg(resolver)?,
+                config_arg(domain, nameserver, port, search_order)?,
+            )
+        };
+        Ok(resolver.register(&config)?)
+    })
+}
+
+/// Registers a domain that survives this process (see
+/// [`FileResolver::register_permanent`]).
+///
+/// # Safety
+///
+/// Same as [`mr_register`].
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mr_register_permanent(
+    resolver: *const MrResolver,
+    domain: *const c_char,
+    nameserver: *const c_char,
+    port: u16,
+    search_order: u32,
+) -> MrStatus {
+    guard(|| {
+        // SAFETY: forwarded from this function's contract.
+        let (resolver, config) = unsafe {
+            (
+                resolver_arg(resolver)?,
+                config_arg(domain, nameserver, port, search_order)?,
+            )
+        };
+        Ok(resolver.register_permanent(&config)?)
+    })
+}
+
+/// Removes a managed domain (see [`FileResolver::unregister`]).
+///
+/// # Safety
+///
+/// `resolver` must be a live handle; `domain` must be a NUL-terminated
+/// string.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mr_unregister(
+    resolver: *const MrResolver,
+    domain: *const c_char,
+) -> MrStatus {
+    guard(|| {
+        // SAFETY: forwarded from this function's contract.
+        let (resolver, domain) = unsafe { (resolver_arg(resolver)?, str_arg(domain)?) };
+        validate_domain(domain)?;
+        Ok(resolver.unregister(domain)?)
+    })
+}
+
+/// Lists managed domains (see [`FileResolver::list`]).
+///
+/// On success `*out_domains` points to `*out_len` NUL-terminated strings;
+/// release them with [`mr_string_array_free`].
+///
+/// # Safety
+///
+/// `resolver` must be a live handle; `out_domains` and `out_len` must be
+/// valid for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mr_list(
+    resolver: *const MrResolver,
+    out_domains: *mut *mut *mut c_char,
+    out_len: *mut usize,
+) -> MrStatus {
+    guard(|| {
+        // SAFETY: forwarded from this function's contract.
+        let resolver = unsafe { resolver_arg(resolver) }?;
+        if out_domains.is_null() || out_len.is_null() {
+            return Err(Failure::Status(
+                MrStatus::MrErrNullArgument,
+                "output pointer is null",
+            ));
+        }
+        let domains: Box<[*mut c_char]> = resolver
+            .list()?
+            .into_iter()
+            .filter_map(|d| CString::new(d).ok())
+            .map(CString::into_raw)
+            .collect();
+        let len = domains.len();
+        // SAFETY: both outputs are non-null and writable per the contract.
+        unsafe {
+            *out_len = len;
+            *out_domains = Box::into_raw(domains).cast();
+        }
+        Ok(())
+    })
+}
+
+/// Frees an array returned by [`mr_list`]. Null is ignored.
+///
+/// # Safety
+///
+/// `domains` and `len` must be exactly as returned by [`mr_list`], and the
+/// array must not be freed twice.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mr_string_array_free(domains: *mut *mut c_char, len: usize) {
+    if domains.is_null() {
+        return;
+    }
+    // SAFETY: the array and its strings were leaked by `mr_list` with this
+    // length.
+    let domains = unsafe { Box::from_raw(std::ptr::slice_from_raw_parts_mut(domains, len)) };
+    for &domain in &domains {
+        // SAFETY: each string came from `CString::into_raw`.
+        drop(unsafe { CString::from_raw(domain) });
+    }
+}
+
+/// Removes orphaned files (see [`FileResolver::cleanup_orphaned`]). If
+/// `out_removed` is non-null it receives the number of files removed.
+///
+/// # Safety
+///
+/// `resolver` must be a live handle; `out_removed` must be null or valid
+/// for writes.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn mr_cleanup_orphaned(
+    resolver: *const MrResolver,
+    out_removed: *mut usize,
+) -> MrStatus {
+    guard(|| {
+        // SAFETY: forwarded from this function's contract.
+        let removed = unsafe { resolver_arg(resolver) }?.cleanup_orphaned()?;
+        if !out_removed.is_null() {
+            // SAFETY: non-null and writable per the contract.
+            unsafe { *out_removed = removed };
+        }
+        Ok(())
+    })
+}
+
+/// Message of the last failed call on this thread, or null after a
+/// successful call.
+///
+/// The string stays valid until the next API call on the same thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn mr_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|slot| {
+        slot.borrow()
+            .as_ref()
+            .map_or(std::ptr::null(), |message| message.as_ptr())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn last_error() -> String {
+        let ptr = mr_last_error_message();
+        assert!(!ptr.is_null());
+        // SAFETY: non-null messages are NUL-terminated and live until the
+        // next call on this thread.
+        unsafe { CStr::from_ptr(ptr) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn lifecycle_through_c_abi() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir_c = CString::new(dir.path().to_str().unwrap()).unwrap();
+        unsafe {
+            let resolver = mr_resolver_new(c"test".as_ptr(), dir_c.as_ptr());
+            assert!(!resolver.is_null());
+
+            let status = mr_register(
+                resolver,
+                c"a.local".as_ptr(),
+                c"127.0.0.1".as_ptr(),
+                5553,
+                1,
+            );
+            assert_eq!(status, MrStatus::MrOk);
+            assert!(mr_last_error_message().is_null());
+            let status = mr_register_permanent(
+                resolver,
+                c"b.local".as_ptr(),
+                c"127.0.0.1".as_ptr(),
+                5553,
+                1,
+            );
+            assert_eq!(status, MrStatus::MrOk);
+
+            let mut domains = std::ptr::null_mut();
+            let mut len = 0;
+            assert_eq!(
+                mr_list(resolver, &raw mut domains, &raw mut len),
+                MrStatus::MrOk
+            );
+            let mut listed: Vec<String> = std::slice::from_raw_parts(domains, len)
+                .iter()
+                .map(|&d| CStr::from_ptr(d).to_string_lossy().into_owned())
+                .collect();
+            listed.sort();
+            assert_eq!(listed, ["a.local", "b.local"]);
+            mr_string_array_free(domains, len);
+
+            let mut removed = usize::MAX;
+            assert_eq!(
+                mr_cleanup_orphaned(resolver, &raw mut removed),
+                MrStatus::MrOk
+            );
+            assert_eq!(removed, 0);
+            assert_eq!(mr_unregister(resolver, c"a.local".as_ptr()), MrStatus::MrOk);
+            assert!(!dir.path().join("a.local").exists());
+            mr_resolver_free(resolver);
+        }
+    }
+
+    #[test]
+    fn errors_map_to_status_codes() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("c.local"), "nameserver 1.1.1.1\n").unwrap();
+        let dir_c = CString::new(dir.path().to_str().unwrap()).unwrap();
+        unsafe {
+            let resolver = mr_resolver_new(c"test".as_ptr(), dir_c.as_ptr());
+
+            assert_eq!(
+                mr_unregister(resolver, c"c.local".as_ptr()),
+                MrStatus::MrErrNotManaged
+            );
+            assert!(last_error().contains("c.local"));
+            assert_eq!(
+                mr_register(resolver, c"../x".as_ptr(), c"127.0.0.1".as_ptr(), 53, 1),
+                MrStatus::MrErrInvalidConfig
+            );
+            assert_eq!(
+                mr_unregister(resolver, std::ptr::null()),
+                MrStatus::MrErrNullArgument
+            );
+            assert_eq!(
+                mr_unregister(std::ptr::null(), c"c.local".as_ptr()),
+                MrStatus::MrErrNullArgument
+            );
+            assert_eq!(
+                mr_unregister(resolver, c"\xff".as_ptr()),
+                MrStatus::MrErrInvalidUtf8
+            );
+            assert!(mr_resolver_new(std::ptr::null(), std::ptr::null()).is_null());
+            mr_resolver_free(resolver);
+        }
+    }
+}
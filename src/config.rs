@@ -1,5 +1,10 @@
 //! Resolver entry configuration.
 
+use std::net::{IpAddr, SocketAddr, TcpListener, UdpSocket};
+
+use crate::error::{ResolverError, Result};
+use crate::util::bind_udp_tcp;
+
 /// Configuration for a single `/etc/resolver/<domain>` entry.
 ///
 /// # Example
@@ -14,7 +19,8 @@
 /// assert_eq!(config.port, 5553);
 /// assert_eq!(config.search_order, 10);
 /// ```
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ResolverConfig {
     /// Domain suffix (e.g., `"myapp.local"`).
     /// Becomes the filename under `/etc/resolver/`.
@@ -43,12 +49,186 @@ impl ResolverConfig {
         }
     }
 
+    /// Creates a config on a free port of `nameserver`, keeping UDP and TCP
+    /// sockets bound to that port so nothing else can take it before the
+    /// server starts.
+    ///
+    /// Register the config and hand the sockets to the server (e.g.
+    /// `DnsServer::spawn_on`) while still holding them; there is no window
+    /// in which the port written to `/etc/resolver` is unbound.
+    ///
+    /// ```no_run
+    /// use macos_resolver::{FileResolver, ResolverConfig};
+    ///
+    /// let (config, reserved) = ResolverConfig::with_free_port("myapp.local", "127.0.0.1")?;
+    /// FileResolver::new("myapp").register(&config)?;
+    /// let (udp, tcp) = reserved.into_sockets();
+    /// // serve on `udp` and `tcp`
+    /// # Ok::<(), macos_resolver::ResolverError>(())
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ResolverError::InvalidConfig`] if `nameserver` is not an IP
+    /// address, or [`ResolverError::Io`] if no port could be bound.
+    pub fn with_free_port(
+        domain: impl Into<String>,
+        nameserver: impl Into<String>,
+    ) -> Result<(Self, ReservedPort)> {
+        let mut config = Self::new(domain, nameserver, 0);
+        let (udp, tcp) = bind_udp_tcp(config.socket_addr()?)?;
+        config.port = udp.local_addr()?.port();
+        tracing::debug!(domain = %config.domain, port = config.port, "Reserved free DNS port");
+        Ok((config, ReservedPort { udp, tcp }))
+    }
+
     /// Overrides the search order.
     #[must_use]
     pub const fn with_search_order(mut self, order: u32) -> Self {
         self.search_order = order;
         self
     }
+
+    /// Checks that the config is safe to write from untrusted input.
+    ///
+    /// The domain must be a DNS name (letters, digits, `-` and `_` in
+    /// non-empty dot-separated labels) so it cannot escape the resolver
+    /// directory, and the nameserver must be an IP address.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ResolverError::InvalidConfig`] describing the first problem.
+    pub fn validate(&self) -> Result<()> {
+        validate_domain(&self.domain)?;
+        if self.nameserver.parse::<std::net::IpAddr>().is_err() {
+            return Err(ResolverError::InvalidConfig(format!(
+                "nameserver is not an IP address: {:?}",
+                self.nameserver
+            )));
+        }
+        Ok(())
+    }
+
+    /// The address macOS sends the domain's queries to.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ResolverError::InvalidConfig`] if the nameserver is not an
+    /// IP address.
+    pub fn socket_addr(&self) -> Result<SocketAddr> {
+        let ip: IpAddr = self.nameserver.parse().map_err(|_| {
+            ResolverError::InvalidConfig(format!(
+                "nameserver is not an IP address: {:?}",
+                self.nameserver
+            ))
+        })?;
+        Ok(SocketAddr::new(ip, self.port))
+    }
+
+    /// Parses the directives of a resolver file for `domain`.
+    ///
+    /// Comment lines and directives other than `nameserver`, `port` and
+    /// `search_order` are ignored. If several nameservers are listed, the
+    /// first one is used. `port` defaults to 53 and `search_order` to 1 when
+    /// absent, matching how macOS reads the file.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ResolverError::InvalidConfig`] if there is no `nameserver`
+    /// or a `port` / `search_order` value is not a valid number.
+    pub fn parse(domain: impl Into<String>, content: &str) -> Result<Self> {
+        let mut nameserver = None;
+        let mut port = 53;
+        let mut search_order = 1;
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            let (Some(key), value) = (words.next(), words.next()) else {
+                continue;
+            };
+            let invalid = || ResolverError::InvalidConfig(format!("invalid directive: {line}"));
+            match key {
+                "nameserver" if nameserver.is_none() => {
+                    nameserver = Some(value.ok_or_else(invalid)?.to_string());
+                }
+                "port" => port = value.and_then(|v| v.parse().ok()).ok_or_else(invalid)?,
+                "search_order" => {
+                    search_order = value.and_then(|v| v.parse().ok()).ok_or_else(invalid)?;
+                }
+                _ => {}
+            }
+        }
+        let nameserver =
+            nameserver.ok_or_else(|| ResolverError::InvalidConfig("missing nameserver".into()))?;
+        Ok(Self {
+            domain: domain.into(),
+            nameserver,
+            port,
+            search_order,
+        })
+    }
+}
+
+/// UDP socket and TCP listener bound to the port of a config created by
+/// [`ResolverConfig::with_free_port`]. The port stays reserved until both
+/// are dropped.
+#[derive(Debug)]
+pub struct ReservedPort {
+    udp: UdpSocket,
+    tcp: TcpListener,
+}
+
+impl ReservedPort {
+    /// The reserved port.
+    #[must_use]
+    pub fn port(&self) -> u16 {
+        self.local_addr().port()
+    }
+
+    /// The address both sockets are bound to.
+    ///
+    /// # Panics
+    ///
+    /// Never in practice: a bound socket always has a local address.
+    #[must_use]
+    pub fn local_addr(&self) -> SocketAddr {
+        self.udp
+            .local_addr()
+            .expect("bound socket has a local address")
+    }
+
+    /// Hands over the sockets, e.g. to `DnsServer::spawn_on`.
+    #[must_use]
+    pub fn into_sockets(self) -> (UdpSocket, TcpListener) {
+        (self.udp, self.tcp)
+    }
+}
+
+/// Checks that `domain` is a DNS name usable as a resolver file name.
+///
+/// # Errors
+///
+/// Returns [`ResolverError::InvalidConfig`] if it is not.
+pub fn validate_domain(domain: &str) -> Result<()> {
+    let valid = !domain.is_empty()
+        && domain.len() <= 253
+        && domain.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && label
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+        });
+    if valid {
+        Ok(())
+    } else {
+        Err(ResolverError::InvalidConfig(format!(
+            "invalid domain: {domain:?}"
+        )))
+    }
 }
 
 #[cfg(test)]
@@ -69,4 +249,74 @@ mod tests {
         let c = ResolverConfig::new("x.local", "127.0.0.1", 53).with_search_order(10);
         assert_eq!(c.search_order, 10);
     }
+
+    #[test]
+    fn with_free_port_keeps_port_bound() {
+        let (c, reserved) = ResolverConfig::with_free_port("x.local", "127.0.0.1").unwrap();
+        assert_ne!(c.port, 0);
+        assert_eq!(reserved.port(), c.port);
+        assert_eq!(c.socket_addr().unwrap(), reserved.local_addr());
+        // Nobody else can take the port while it is reserved.
+        assert!(UdpSocket::bind(reserved.local_addr()).is_err());
+        assert!(TcpListener::bind(reserved.local_addr()).is_err());
+
+        let (udp, tcp) = reserved.into_sockets();
+        assert_eq!(tcp.local_addr().unwrap(), udp.local_addr().unwrap());
+        assert!(ResolverConfig::with_free_port("x.local", "localhost").is_err());
+    }
+
+    #[test]
+    fn parse_reads_directives() {
+        let c = ResolverConfig::parse(
+            "x.local",
+            "# managed by app (v=2)\nnameserver 127.0.0.1\nnameserver 10.0.0.1\nport 5553\nsearch_order 3\ntimeout 5\n",
+        )
+        .unwrap();
+        assert_eq!(
+            c,
+            ResolverConfig::new("x.local", "127.0.0.1", 5553).with_search_order(3)
+        );
+    }
+
+    #[test]
+    fn parse_applies_defaults() {
+        let c = ResolverConfig::parse("x.local", "nameserver 1.1.1.1\n").unwrap();
+        assert_eq!((c.port, c.search_order), (53, 1));
+    }
+
+    #[test]
+    fn parse_rejects_invalid_content() {
+        assert!(ResolverConfig::parse("x.local", "port 53\n").is_err());
+        assert!(ResolverConfig::parse("x.local", "nameserver 1.1.1.1\nport abc\n").is_err());
+        assert!(ResolverConfig::parse("x.local", "nameserver\n").is_err());
+    }
+
+    #[test]
+    fn validate_rejects_unsafe_input() {
+        assert!(
+            ResolverConfig::new("my-app.local", "::1", 53)
+                .validate()
+                .is_ok()
+        );
+        for domain in [
+            "",
+            "../passwd",
+            "a/b",
+            ".local",
+            "a..b",
+            "x\nnameserver 1.1.1.1",
+        ] {
+            assert!(
+                ResolverConfig::new(domain, "127.0.0.1", 53)
+                    .validate()
+                    .is_err(),
+                "{domain:?}"
+            );
+        }
+        assert!(
+            ResolverConfig::new("a.local", "127.0.0.1\nport 1", 53)
+                .validate()
+                .is_err()
+        );
+    }
 }
@@ -0,0 +1,896 @@
+//! DNS wire format (RFC 1035), covering what the embedded server, the
+//! forwarding proxy and reachability probes need.
+//!
+//! Names are plain strings without the trailing dot (the root is `""`) and
+//! compare case-insensitively via [`names_equal`]. Encoding never compresses
+//! names; decoding follows compression pointers.
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, UdpSocket};
+use std::time::{Duration, Instant};
+
+/// Largest message sent over UDP to clients without EDNS.
+pub const MAX_UDP_SIZE: usize = 512;
+
+/// Class `IN`.
+pub const CLASS_IN: u16 = 1;
+
+/// Class `ANY` (in questions).
+pub const CLASS_ANY: u16 = 255;
+
+/// Maximum compression pointers followed while reading one name.
+const MAX_POINTERS: usize = 32;
+
+/// A message that could not be encoded or decoded.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+#[error("malformed DNS message: {0}")]
+pub struct WireError(&'static str);
+
+/// Record type of a question or resource record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum RecordType {
+    /// IPv4 address.
+    A,
+    /// Authoritative nameserver.
+    Ns,
+    /// Canonical name (alias).
+    Cname,
+    /// Start of authority.
+    Soa,
+    /// Text strings.
+    Txt,
+    /// IPv6 address.
+    Aaaa,
+    /// Any type (questions only).
+    Any,
+    /// Any other type, by code.
+    Other(u16),
+}
+
+impl RecordType {
+    /// The type's numeric code.
+    #[must_use]
+    pub const fn code(self) -> u16 {
+        match self {
+            Self::A => 1,
+            Self::Ns => 2,
+            Self::Cname => 5,
+            Self::Soa => 6,
+            Self::Txt => 16,
+            Self::Aaaa => 28,
+            Self::Any => 255,
+            Self::Other(code) => code,
+        }
+    }
+
+    /// The type with numeric code `code`.
+    #[must_use]
+    pub const fn from_code(code: u16) -> Self {
+        match code {
+            1 => Self::A,
+            2 => Self::Ns,
+            5 => Self::Cname,
+            6 => Self::Soa,
+            16 => Self::Txt,
+            28 => Self::Aaaa,
+            255 => Self::Any,
+            code => Self::Other(code),
+        }
+    }
+}
+
+impl fmt::Display for RecordType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::A => f.write_str("A"),
+            Self::Ns => f.write_str("NS"),
+            Self::Cname => f.write_str("CNAME"),
+            Self::Soa => f.write_str("SOA"),
+            Self::Txt => f.write_str("TXT"),
+            Self::Aaaa => f.write_str("AAAA"),
+            Self::Any => f.write_str("ANY"),
+            Self::Other(code) => write!(f, "TYPE{code}"),
+        }
+    }
+}
+
+/// Response code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rcode {
+    /// No error.
+    NoError,
+    /// The server could not parse the query.
+    FormErr,
+    /// The server failed to process the query.
+    ServFail,
+    /// The name does not exist.
+    NxDomain,
+    /// The kind of query is not supported.
+    NotImp,
+    /// The server refuses to answer.
+    Refused,
+    /// Any other code.
+    Other(u8),
+}
+
+impl Rcode {
+    /// The code's numeric value.
+    #[must_use]
+    pub const fn code(self) -> u8 {
+        match self {
+            Self::NoError => 0,
+            Self::FormErr => 1,
+            Self::ServFail => 2,
+            Self::NxDomain => 3,
+            Self::NotImp => 4,
+            Self::Refused => 5,
+            Self::Other(code) => code,
+        }
+    }
+
+    /// The response code with numeric value `code`.
+    #[must_use]
+    pub const fn from_code(code: u8) -> Self {
+        match code {
+            0 => Self::NoError,
+            1 => Self::FormErr,
+            2 => Self::ServFail,
+            3 => Self::NxDomain,
+            4 => Self::NotImp,
+            5 => Self::Refused,
+            code => Self::Other(code),
+        }
+    }
+}
+
+/// Data of an `SOA` record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Soa {
+    /// Primary nameserver.
+    pub mname: String,
+    /// Responsible mailbox, as a name.
+    pub rname: String,
+    /// Zone serial number.
+    pub serial: u32,
+    /// Secondary refresh interval, in seconds.
+    pub refresh: u32,
+    /// Secondary retry interval, in seconds.
+    pub retry: u32,
+    /// Secondary expiry, in seconds.
+    pub expire: u32,
+    /// TTL of negative answers, in seconds.
+    pub minimum: u32,
+}
+
+/// Typed data of a resource record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RData {
+    /// IPv4 address.
+    A(Ipv4Addr),
+    /// IPv6 address.
+    Aaaa(Ipv6Addr),
+    /// Alias target.
+    Cname(String),
+    /// Nameserver name.
+    Ns(String),
+    /// Character strings, each at most 255 bytes.
+    Txt(Vec<String>),
+    /// Start of authority.
+    Soa(Soa),
+    /// Raw data of any other type.
+    Other {
+        /// Numeric record type.
+        rtype: u16,
+        /// Undecoded data.
+        data: Vec<u8>,
+    },
+}
+
+impl RData {
+    /// Type of a record carrying this data.
+    #[must_use]
+    pub const fn record_type(&self) -> RecordType {
+        match self {
+            Self::A(_) => RecordType::A,
+            Self::Aaaa(_) => RecordType::Aaaa,
+            Self::Cname(_) => RecordType::Cname,
+            Self::Ns(_) => RecordType::Ns,
+            Self::Txt(_) => RecordType::Txt,
+            Self::Soa(_) => RecordType::Soa,
+            Self::Other { rtype, .. } => RecordType::from_code(*rtype),
+        }
+    }
+
+    /// A `TXT` record with a single string.
+    #[must_use]
+    pub fn txt(text: impl Into<String>) -> Self {
+        Self::Txt(vec![text.into()])
+    }
+}
+
+impl From<Ipv4Addr> for RData {
+    fn from(ip: Ipv4Addr) -> Self {
+        Self::A(ip)
+    }
+}
+
+impl From<Ipv6Addr> for RData {
+    fn from(ip: Ipv6Addr) -> Self {
+        Self::Aaaa(ip)
+    }
+}
+
+impl From<IpAddr> for RData {
+    fn from(ip: IpAddr) -> Self {
+        match ip {
+            IpAddr::V4(ip) => Self::A(ip),
+            IpAddr::V6(ip) => Self::Aaaa(ip),
+        }
+    }
+}
+
+/// A resource record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Record {
+    /// Owner name.
+    pub name: String,
+    /// Class, normally [`CLASS_IN`].
+    pub class: u16,
+    /// Time to live, in seconds.
+    pub ttl: u32,
+    /// Type and data.
+    pub data: RData,
+}
+
+impl Record {
+    /// Creates an `IN` record.
+    #[must_use]
+    pub fn new(name: impl Into<String>, ttl: u32, data: RData) -> Self {
+        Self {
+            name: name.into(),
+            class: CLASS_IN,
+            ttl,
+            data,
+        }
+    }
+}
+
+/// An entry of the question section.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Question {
+    /// Queried name.
+    pub name: String,
+    /// Queried type.
+    pub qtype: RecordType,
+    /// Queried class, normally [`CLASS_IN`].
+    pub qclass: u16,
+}
+
+/// A DNS query or response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Message {
+    /// Transaction ID, echoed by responses.
+    pub id: u16,
+    /// Whether this is a response.
+    pub response: bool,
+    /// Operation code; 0 is a standard query.
+    pub opcode: u8,
+    /// Whether the responder is authoritative for the name.
+    pub authoritative: bool,
+    /// Whether the message was truncated to fit a UDP datagram.
+    pub truncated: bool,
+    /// Whether the client wants recursion.
+    pub recursion_desired: bool,
+    /// Whether the server offers recursion.
+    pub recursion_available: bool,
+    /// Response code.
+    pub rcode: Rcode,
+    /// Question section.
+    pub questions: Vec<Question>,
+    /// Answer section.
+    pub answers: Vec<Record>,
+    /// Authority section.
+    pub authority: Vec<Record>,
+    /// Additional section.
+    pub additional: Vec<Record>,
+}
+
+impl Message {
+    /// A standard recursive query for `name` and `qtype`.
+    #[must_use]
+    pub fn query(id: u16, name: impl Into<String>, qtype: RecordType) -> Self {
+        Self {
+            id,
+            response: false,
+            opcode: 0,
+            authoritative: false,
+            truncated: false,
+            recursion_desired: true,
+            recursion_available: false,
+            rcode: Rcode::NoError,
+            questions: vec![Question {
+                name: name.into(),
+                qtype,
+                qclass: CLASS_IN,
+            }],
+            answers: Vec::new(),
+            authority: Vec::new(),
+            additional: Vec::new(),
+        }
+    }
+
+    /// An empty `NOERROR` response to this query, echoing its ID, opcode,
+    /// recursion flag and questions.
+    #[must_use]
+    pub fn reply(&self) -> Self {
+        Self {
+            opcode: self.opcode,
+            recursion_desired: self.recursion_desired,
+            questions: self.questions.clone(),
+            ..Self::response(self.id, Rcode::NoError)
+        }
+    }
+
+    /// An empty response with only an ID and response code, for queries too
+    /// malformed to echo.
+    #[must_use]
+    pub const fn response(id: u16, rcode: Rcode) -> Self {
+        Self {
+            id,
+            response: true,
+            opcode: 0,
+            authoritative: false,
+            truncated: false,
+            recursion_desired: false,
+            recursion_available: false,
+            rcode,
+            questions: Vec::new(),
+            answers: Vec::new(),
+            authority: Vec::new(),
+            additional: Vec::new(),
+        }
+    }
+
+    /// Encodes the message.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`WireError`] if a name, string or section is too long for
+    /// the wire format.
+    pub fn encode(&self) -> Result<Vec<u8>, WireError> {
+        let mut out = Vec::with_capacity(MAX_UDP_SIZE);
+        out.extend_from_slice(&self.id.to_be_bytes());
+        out.push(
+            u8::from(self.response) << 7
+                | (self.opcode & 0x0f) << 3
+                | u8::from(self.authoritative) << 2
+                | u8::from(self.truncated) << 1
+                | u8::from(self.recursion_desired),
+        );
+        out.push(u8::from(self.recursion_available) << 7 | self.rcode.code() & 0x0f);
+        for len in [
+            self.questions.len(),
+            self.answers.len(),
+            self.authority.len(),
+            self.additional.len(),
+        ] {
+            let len = u16::try_from(len).map_err(|_| WireError("too many records"))?;
+            out.extend_from_slice(&len.to_be_bytes());
+        }
+        for question in &self.questions {
+            write_name(&mut out, &question.name)?;
+            out.extend_from_slice(&question.qtype.code().to_be_bytes());
+            out.extend_from_slice(&question.qclass.to_be_bytes());
+        }
+        for record in self
+            .answers
+            .iter()
+            .chain(&self.authority)
+            .chain(&self.additional)
+        {
+            write_record(&mut out, record)?;
+        }
+        Ok(out)
+    }
+
+    /// Encodes the message for a UDP datagram of at most `max` bytes. If it
+    /// does not fit, the record sections are dropped and the truncation flag
+    /// set, telling the client to retry over TCP.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`encode`](Self::encode).
+    pub fn encode_udp(&self, max: usize) -> Result<Vec<u8>, WireError> {
+        let full = self.encode()?;
+        if full.len() <= max {
+            return Ok(full);
+        }
+        Self {
+            truncated: true,
+            answers: Vec::new(),
+            authority: Vec::new(),
+            additional: Vec::new(),
+            ..self.clone()
+        }
+        .encode()
+    }
+
+    /// Decodes a message.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`WireError`] if `buf` is not a well-formed message.
+    pub fn decode(buf: &[u8]) -> Result<Self, WireError> {
+        let mut r = Reader { buf, pos: 0 };
+        let id = r.u16()?;
+        let flags = r.u16()?;
+        let counts = [r.u16()?, r.u16()?, r.u16()?, r.u16()?];
+        let [hi, lo] = flags.to_be_bytes();
+        let mut message = Self {
+            id,
+            response: hi & 0x80 != 0,
+            opcode: (hi >> 3) & 0x0f,
+            authoritative: hi & 0x04 != 0,
+            truncated: hi & 0x02 != 0,
+            recursion_desired: hi & 0x01 != 0,
+            recursion_available: lo & 0x80 != 0,
+            rcode: Rcode::from_code(lo & 0x0f),
+            questions: Vec::new(),
+            answers: Vec::new(),
+            authority: Vec::new(),
+            additional: Vec::new(),
+        };
+        for _ in 0..counts[0] {
+            message.questions.push(Question {
+                name: r.name()?,
+                qtype: RecordType::from_code(r.u16()?),
+                qclass: r.u16()?,
+            });
+        }
+        for (count, section) in [
+            (counts[1], &mut message.answers),
+            (counts[2], &mut message.authority),
+            (counts[3], &mut message.additional),
+        ] {
+            for _ in 0..count {
+                section.push(r.record()?);
+            }
+        }
+        Ok(message)
+    }
+}
+
+/// Compares two names case-insensitively, ignoring a trailing dot.
+#[must_use]
+pub fn names_equal(a: &str, b: &str) -> bool {
+    a.trim_end_matches('.')
+        .eq_ignore_ascii_case(b.trim_end_matches('.'))
+}
+
+/// Whether `name` equals `zone` or lies below it (case-insensitive).
+#[must_use]
+pub fn in_zone(name: &str, zone: &str) -> bool {
+    let name = name.trim_end_matches('.');
+    let zone = zone.trim_end_matches('.');
+    zone.is_empty()
+        || name.eq_ignore_ascii_case(zone)
+        || name.len() > zone.len()
+            && name.as_bytes()[name.len() - zone.len() - 1] == b'.'
+            && name[name.len() - zone.len()..].eq_ignore_ascii_case(zone)
+}
+
+/// Reads one length-prefixed message from a TCP stream.
+///
+/// # Errors
+///
+/// Returns the stream's error; `UnexpectedEof` if it closes mid-message.
+pub fn read_tcp(reader: &mut impl Read) -> io::Result<Vec<u8>> {
+    let mut len = [0; 2];
+    reader.read_exact(&mut len)?;
+    let mut buf = vec![0; usize::from(u16::from_be_bytes(len))];
+    reader.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+/// Writes one length-prefixed message to a TCP stream.
+///
+/// # Errors
+///
+/// Returns `InvalidInput` if `message` exceeds 65535 bytes, or the stream's
+/// error.
+pub fn write_tcp(writer: &mut impl Write, message: &[u8]) -> io::Result<()> {
+    let len = u16::try_from(message.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "DNS message too long"))?;
+    let mut framed = Vec::with_capacity(message.len() + 2);
+    framed.extend_from_slice(&len.to_be_bytes());
+    framed.extend_from_slice(message);
+    writer.write_all(&framed)?;
+    writer.flush()
+}
+
+/// Sends `query` to `server` over UDP and returns the first reply carrying
+/// the same ID.
+///
+/// # Errors
+///
+/// Returns `TimedOut`/`WouldBlock` if no reply arrives within `timeout`,
+/// `InvalidInput` if `query` has no ID, or the socket's error (e.g.
+/// `ConnectionRefused` if nothing listens on the port).
+pub fn exchange_udp(server: SocketAddr, query: &[u8], timeout: Duration) -> io::Result<Vec<u8>> {
+    let id = query
+        .get(..2)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "DNS query too short"))?;
+    let local: SocketAddr = if server.is_ipv4() {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    };
+    let socket = UdpSocket::bind(local)?;
+    socket.connect(server)?;
+    socket.send(query)?;
+    let deadline = Instant::now() + timeout;
+    let mut buf = vec![0; 65535];
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+        socket.set_read_timeout(Some(remaining))?;
+        let len = socket.recv(&mut buf)?;
+        if buf.get(..2) == Some(id) {
+            buf.truncate(len);
+            return Ok(buf);
+        }
+    }
+}
+
+/// Sends `query` to `server` over TCP and returns the reply.
+///
+/// # Errors
+///
+/// Returns `TimedOut`/`WouldBlock` if connecting or the reply takes longer
+/// than `timeout`, or the stream's error.
+pub fn exchange_tcp(server: SocketAddr, query: &[u8], timeout: Duration) -> io::Result<Vec<u8>> {
+    let mut stream = TcpStream::connect_timeout(&server, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+    write_tcp(&mut stream, query)?;
+    read_tcp(&mut stream)
+}
+
+fn write_name(out: &mut Vec<u8>, name: &str) -> Result<(), WireError> {
+    let start = out.len();
+    for label in name.split('.').filter(|l| !l.is_empty()) {
+        let len = u8::try_from(label.len())
+            .ok()
+            .filter(|&len| len <= 63)
+            .ok_or(WireError("label longer than 63 bytes"))?;
+        out.push(len);
+        out.extend_from_slice(label.as_bytes());
+    }
+    out.push(0);
+    if out.len() - start > 255 {
+        return Err(WireError("name longer than 255 bytes"));
+    }
+    Ok(())
+}
+
+fn write_record(out: &mut Vec<u8>, record: &Record) -> Result<(), WireError> {
+    write_name(out, &record.name)?;
+    out.extend_from_slice(&record.data.record_type().code().to_be_bytes());
+    out.extend_from_slice(&record.class.to_be_bytes());
+    out.extend_from_slice(&record.ttl.to_be_bytes());
+    let len_at = out.len();
+    out.extend_from_slice(&[0, 0]);
+    match &record.data {
+        RData::A(ip) => out.extend_from_slice(&ip.octets()),
+        RData::Aaaa(ip) => out.extend_from_slice(&ip.octets()),
+        RData::Cname(name) | RData::Ns(name) => write_name(out, name)?,
+        RData::Txt(strings) => {
+            for s in strings {
+                let len = u8::try_from(s.len()).map_err(|_| WireError("TXT string too long"))?;
+                out.push(len);
+                out.extend_from_slice(s.as_bytes());
+            }
+        }
+        RData::Soa(soa) => {
+            write_name(out, &soa.mname)?;
+            write_name(out, &soa.rname)?;
+            for value in [soa.serial, soa.refresh, soa.retry, soa.expire, soa.minimum] {
+                out.extend_from_slice(&value.to_be_bytes());
+            }
+        }
+        RData::Other { data, .. } => out.extend_from_slice(data),
+    }
+    let len = u16::try_from(out.len() - len_at - 2).map_err(|_| WireError("record too long"))?;
+    out[len_at..len_at + 2].copy_from_slice(&len.to_be_bytes());
+    Ok(())
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], WireError> {
+        let bytes = self
+            .buf
+            .get(self.pos..self.pos + len)
+            .ok_or(WireError("unexpected end of message"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, WireError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, WireError> {
+        let b = self.bytes(2)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, WireError> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    /// Reads a possibly compressed name.
+    fn name(&mut self) -> Result<String, WireError> {
+        let mut labels: Vec<String> = Vec::new();
+        // Length on the wire, counting each label's length octet and the
+        // terminating root label.
+        let mut wire_len = 1;
+        let mut pos = self.pos;
+        let mut resume = None;
+        let mut pointers = 0;
+        loop {
+            let len = *self
+                .buf
+                .get(pos)
+                .ok_or(WireError("unexpected end of message"))?;
+            match len {
+                0 => {
+                    pos += 1;
+                    break;
+                }
+                len if len & 0xc0 == 0xc0 => {
+                    let low = *self
+                        .buf
+                        .get(pos + 1)
+                        .ok_or(WireError("unexpected end of message"))?;
+                    pointers += 1;
+                    if pointers > MAX_POINTERS {
+                        return Err(WireError("compression loop"));
+                    }
+                    resume.get_or_insert(pos + 2);
+                    pos = usize::from(len & 0x3f) << 8 | usize::from(low);
+                }
+                len if len & 0xc0 == 0 => {
+                    let label = self
+                        .buf
+                        .get(pos + 1..pos + 1 + usize::from(len))
+                        .ok_or(WireError("unexpected end of message"))?;
+                    // A dot inside a label would read back as a separator.
+                    if label.contains(&b'.') {
+                        return Err(WireError("label contains a dot"));
+                    }
+                    wire_len += 1 + label.len();
+                    if wire_len > 255 {
+                        return Err(WireError("name longer than 255 bytes"));
+                    }
+                    labels.push(String::from_utf8_lossy(label).into_owned());
+                    pos += 1 + usize::from(len);
+                }
+                _ => return Err(WireError("unsupported label type")),
+            }
+        }
+        self.pos = resume.unwrap_or(pos);
+        Ok(labels.join("."))
+    }
+
+    fn record(&mut self) -> Result<Record, WireError> {
+        let name = self.name()?;
+        let rtype = self.u16()?;
+        let class = self.u16()?;
+        let ttl = self.u32()?;
+        let len = usize::from(self.u16()?);
+        let end = self.pos + len;
+        if end > self.buf.len() {
+            return Err(WireError("unexpected end of message"));
+        }
+        let data = match RecordType::from_code(rtype) {
+            RecordType::A => {
+                let b: [u8; 4] = self
+                    .bytes(len)?
+                    .try_into()
+                    .map_err(|_| WireError("bad A record"))?;
+                RData::A(b.into())
+            }
+            RecordType::Aaaa => {
+                let b: [u8; 16] = self
+                    .bytes(len)?
+                    .try_into()
+                    .map_err(|_| WireError("bad AAAA record"))?;
+                RData::Aaaa(b.into())
+            }
+            RecordType::Cname => RData::Cname(self.name()?),
+            RecordType::Ns => RData::Ns(self.name()?),
+            RecordType::Txt => {
+                let mut strings = Vec::new();
+                while self.pos < end {
+                    let len = usize::from(self.u8()?);
+                    strings.push(String::from_utf8_lossy(self.bytes(len)?).into_owned());
+                }
+                RData::Txt(strings)
+            }
+            RecordType::Soa => RData::Soa(Soa {
+                mname: self.name()?,
+                rname: self.name()?,
+                serial: self.u32()?,
+                refresh: self.u32()?,
+                retry: self.u32()?,
+                expire: self.u32()?,
+                minimum: self.u32()?,
+            }),
+            _ => RData::Other {
+                rtype,
+                data: self.bytes(len)?.to_vec(),
+            },
+        };
+        if self.pos != end {
+            return Err(WireError("record data length mismatch"));
+        }
+        Ok(Record {
+            name,
+            class,
+            ttl,
+            data,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> Message {
+        let mut m = Message::query(0x1234, "api.myapp.local", RecordType::A).reply();
+        m.authoritative = true;
+        m.answers = vec![
+            Record::new(
+                "api.myapp.local",
+                60,
+                RData::Cname("web.myapp.local".into()),
+            ),
+            Record::new("web.myapp.local", 60, Ipv4Addr::LOCALHOST.into()),
+            Record::new("web.myapp.local", 60, Ipv6Addr::LOCALHOST.into()),
+            Record::new(
+                "web.myapp.local",
+                30,
+                RData::Txt(vec!["a".into(), "b c".into()]),
+            ),
+        ];
+        m.authority = vec![Record::new(
+            "myapp.local",
+            60,
+            RData::Soa(Soa {
+                mname: "myapp.local".into(),
+                rname: "hostmaster.myapp.local".into(),
+                serial: 7,
+                refresh: 3600,
+                retry: 600,
+                expire: 86400,
+                minimum: 60,
+            }),
+        )];
+        m.additional = vec![Record::new(
+            "",
+            0,
+            RData::Other {
+                rtype: 41,
+                data: vec![],
+            },
+        )];
+        m
+    }
+
+    #[test]
+    fn round_trips_all_record_types() {
+        let message = sample();
+        let decoded = Message::decode(&message.encode().unwrap()).unwrap();
+        assert_eq!(decoded, message);
+    }
+
+    #[test]
+    fn decodes_compressed_names() {
+        // Response to "a.local A" whose answer name points at the question.
+        let mut buf = vec![0, 1, 0x81, 0x80, 0, 1, 0, 1, 0, 0, 0, 0];
+        buf.extend_from_slice(b"\x01a\x05local\x00\x00\x01\x00\x01");
+        buf.extend_from_slice(&[0xc0, 12, 0, 1, 0, 1, 0, 0, 0, 60, 0, 4, 10, 0, 0, 1]);
+        let message = Message::decode(&buf).unwrap();
+        assert_eq!(message.questions[0].name, "a.local");
+        assert_eq!(
+            message.answers,
+            [Record::new(
+                "a.local",
+                60,
+                Ipv4Addr::new(10, 0, 0, 1).into()
+            )]
+        );
+        assert!(message.recursion_available);
+    }
+
+    #[test]
+    fn rejects_malformed_messages() {
+        assert!(Message::decode(&[0; 5]).is_err());
+        // A question name that points at itself.
+        let mut looped = vec![0, 1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+        looped.extend_from_slice(&[0xc0, 12, 0, 1, 0, 1]);
+        assert_eq!(Message::decode(&looped), Err(WireError("compression loop")));
+        let long_label = Message::query(1, "a".repeat(64), RecordType::A);
+        assert!(long_label.encode().is_err());
+    }
+
+    #[test]
+    fn name_limits_apply_to_wire_bytes() {
+        let header = [0, 1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+        let question = |labels: &[&[u8]]| {
+            let mut wire = header.to_vec();
+            for label in labels {
+                wire.push(u8::try_from(label.len()).unwrap());
+                wire.extend_from_slice(label);
+            }
+            wire.extend_from_slice(&[0, 0, 1, 0, 1]);
+            wire
+        };
+
+        // 255 bytes on the wire, even though the lossy text is longer.
+        let high = [0xff; 63];
+        let max = question(&[&high, &high, &high, &[0xff; 61]]);
+        let decoded = Message::decode(&max).unwrap();
+        assert!(decoded.questions[0].name.len() > 253);
+        let over = question(&[&high, &high, &high, &[0xff; 62]]);
+        assert_eq!(
+            Message::decode(&over),
+            Err(WireError("name longer than 255 bytes"))
+        );
+
+        let dotted = question(&[b"a.b", b"test"]);
+        assert_eq!(
+            Message::decode(&dotted),
+            Err(WireError("label contains a dot"))
+        );
+    }
+
+    #[test]
+    fn truncates_for_udp() {
+        let mut message = sample();
+        message.answers = (0..40)
+            .map(|i| Record::new("t.myapp.local", 60, RData::txt(format!("record {i}"))))
+            .collect();
+        let udp = Message::decode(&message.encode_udp(MAX_UDP_SIZE).unwrap()).unwrap();
+        assert!(udp.truncated);
+        assert!(udp.answers.is_empty());
+        assert_eq!(udp.questions, message.questions);
+    }
+
+    #[test]
+    fn tcp_framing() {
+        let mut buf = Vec::new();
+        write_tcp(&mut buf, b"abc").unwrap();
+        assert_eq!(buf, b"\x00\x03abc");
+        assert_eq!(read_tcp(&mut buf.as_slice()).unwrap(), b"abc");
+    }
+
+    #[test]
+    fn zone_membership() {
+        assert!(in_zone("A.MyApp.local.", "myapp.local"));
+        assert!(in_zone("myapp.local", "myapp.local"));
+        assert!(!in_zone("notmyapp.local", "myapp.local"));
+        assert!(names_equal("x.local.", "X.LOCAL"));
+    }
+}
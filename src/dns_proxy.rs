@@ -0,0 +1,772 @@
+//! Forwarding DNS proxy with per-domain upstreams (`dns-proxy` feature).
+//!
+//! [`DnsProxy`] listens where a registered [`ResolverConfig`] points macOS
+//! and forwards each query to the upstreams routed for the longest matching
+//! domain suffix, e.g. a container's DNS for `svc.myapp.local` and a VPN
+//! resolver for `corp.example`. Routes can be changed while it runs.
+//!
+//! ```rust,ignore
+//! let config = ResolverConfig::new("myapp.local", "127.0.0.1", 5553);
+//! let proxy = DnsProxy::new()
+//!     .route("myapp.local", ["172.17.0.2:53".parse()?])
+//!     .fallback(["10.8.0.1:53".parse()?])
+//!     .spawn_for(&config)?;
+//! resolver.register(&config)?;
+//! proxy.set_route("myapp.local", ["172.17.0.3:53".parse()?]); // container moved
+//! ```
+
+use std::collections::BTreeMap;
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::config::ResolverConfig;
+use crate::dns::{Message, Rcode, exchange_tcp, exchange_udp, in_zone, read_tcp, write_tcp};
+use crate::error::Result;
+use crate::util::{bind_udp_tcp, wait_readable};
+
+/// Default time to wait for each upstream before trying the next.
+pub const DEFAULT_UPSTREAM_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Default number of threads forwarding UDP queries.
+pub const DEFAULT_UDP_WORKERS: usize = 16;
+
+/// Default for [`DnsProxy::max_tcp_connections`].
+pub const DEFAULT_MAX_TCP_CONNECTIONS: usize = 64;
+
+/// UDP queries that may wait for a free worker, per worker.
+const UDP_QUEUE_PER_WORKER: usize = 4;
+
+/// How often the serving threads check whether they have been shut down.
+const STOP_POLL: Duration = Duration::from_millis(100);
+
+/// How long a TCP client may stay idle between queries.
+const TCP_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Domain suffix → upstreams, shared between a proxy and its handle.
+type Routes = Arc<RwLock<BTreeMap<String, Vec<SocketAddr>>>>;
+
+/// A UDP query waiting for a worker: the packet and who sent it.
+type UdpJob = (Vec<u8>, SocketAddr);
+
+/// Forwarding DNS proxy.
+///
+/// For each query, the upstreams of the longest matching route are tried in
+/// order, then the [`fallback`](Self::fallback) upstreams. An upstream that
+/// times out, fails, or answers `SERVFAIL`/`REFUSED` is skipped; if none
+/// answers, the client gets `SERVFAIL`. Names matching no route go straight
+/// to the fallback, or are refused if there is none. Queries received over
+/// TCP are forwarded over TCP; truncated UDP answers are passed on so the
+/// client retries over TCP.
+///
+/// UDP queries are forwarded by a fixed pool of
+/// [`udp_workers`](Self::udp_workers) with a short queue in front; when the
+/// queue is full, as when an upstream stops answering under load, further
+/// queries are answered with `SERVFAIL` straight away. TCP clients are
+/// served up to [`max_tcp_connections`](Self::max_tcp_connections) at once.
+#[derive(Debug, Clone)]
+pub struct DnsProxy {
+    routes: Routes,
+    fallback: Vec<SocketAddr>,
+    timeout: Duration,
+    udp_workers: usize,
+    max_tcp_connections: usize,
+}
+
+impl Default for DnsProxy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DnsProxy {
+    /// Creates a proxy with no routes and no fallback.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            routes: Routes::default(),
+            fallback: Vec::new(),
+            timeout: DEFAULT_UPSTREAM_TIMEOUT,
+            udp_workers: DEFAULT_UDP_WORKERS,
+            max_tcp_connections: DEFAULT_MAX_TCP_CONNECTIONS,
+        }
+    }
+
+    /// Forwards queries for `suffix` and its subdomains to `upstreams`, in
+    /// order. Replaces an existing route for the same suffix.
+    #[must_use]
+    pub fn route(self, suffix: &str, upstreams: impl IntoIterator<Item = SocketAddr>) -> Self {
+        set_route(&self.routes, suffix, upstreams);
+        self
+    }
+
+    /// Upstreams tried after a route's own, and for names matching no route.
+    #[must_use]
+    pub fn fallback(mut self, upstreams: impl IntoIterator<Item = SocketAddr>) -> Self {
+        self.fallback = upstreams.into_iter().collect();
+        self
+    }
+
+    /// Sets how long to wait for each upstream (default: 2 seconds).
+    #[must_use]
+    pub const fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Sets how many UDP queries are forwarded at once (default: 16, at
+    /// least 1).
+    #[must_use]
+    pub fn udp_workers(mut self, workers: usize) -> Self {
+        self.udp_workers = workers.max(1);
+        self
+    }
+
+    /// Limits how many TCP clients are served at once (default: 64).
+    /// Connections over the limit are closed.
+    #[must_use]
+    pub const fn max_tcp_connections(mut self, max: usize) -> Self {
+        self.max_tcp_connections = max;
+        self
+    }
+
+    /// Forwards `query` over UDP (or TCP if `tcp` is set) and returns the
+    /// answer, or a `SERVFAIL`/`REFUSED` response if no upstream answers.
+    #[must_use]
+    pub fn forward(&self, query: &Message, tcp: bool) -> Message {
+        let fail = |rcode| Message {
+            rcode,
+            ..query.reply()
+        };
+        let Ok(packet) = query.encode() else {
+            return fail(Rcode::FormErr);
+        };
+        match self.forward_packet(query, &packet, tcp) {
+            Some(answer) => Message::decode(&answer).unwrap_or_else(|_| fail(Rcode::ServFail)),
+            None if self.upstreams_for(query).is_empty() => fail(Rcode::Refused),
+            None => fail(Rcode::ServFail),
+        }
+    }
+
+    /// Binds UDP and TCP on `addr` and serves on background threads until
+    /// the returned handle is shut down or dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ResolverError::Io`](crate::ResolverError::Io) if a socket cannot be bound or a thread
+    /// cannot be spawned.
+    pub fn spawn(self, addr: SocketAddr) -> Result<DnsProxyHandle> {
+        let (udp, tcp) = bind_udp_tcp(addr)?;
+        self.spawn_on(udp, tcp)
+    }
+
+    /// Serves on `config.nameserver:config.port`, where macOS sends the
+    /// domain's queries once the config is registered.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ResolverError::InvalidConfig`](crate::ResolverError::InvalidConfig) if the nameserver is not an
+    /// IP address, otherwise as [`spawn`](Self::spawn).
+    pub fn spawn_for(self, config: &ResolverConfig) -> Result<DnsProxyHandle> {
+        self.spawn(config.socket_addr()?)
+    }
+
+    /// Serves on already-bound sockets.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ResolverError::Io`](crate::ResolverError::Io) if the sockets cannot be configured or a
+    /// thread cannot be spawned.
+    pub fn spawn_on(self, udp: UdpSocket, tcp: TcpListener) -> Result<DnsProxyHandle> {
+        let addr = udp.local_addr()?;
+        udp.set_read_timeout(Some(STOP_POLL))?;
+        tcp.set_nonblocking(true)?;
+        let proxy = Arc::new(self);
+        // Dropping the handle on a failed spawn stops the threads already
+        // running.
+        let mut handle = DnsProxyHandle {
+            addr,
+            routes: Arc::clone(&proxy.routes),
+            stop: Arc::new(AtomicBool::new(false)),
+            threads: Vec::with_capacity(proxy.udp_workers + 2),
+        };
+
+        let udp = Arc::new(udp);
+        let (jobs, queue) = std::sync::mpsc::sync_channel(proxy.udp_workers * UDP_QUEUE_PER_WORKER);
+        let queue = Arc::new(Mutex::new(queue));
+        for _ in 0..proxy.udp_workers {
+            let (worker_proxy, socket, queue, stop) = (
+                Arc::clone(&proxy),
+                Arc::clone(&udp),
+                Arc::clone(&queue),
+                Arc::clone(&handle.stop),
+            );
+            handle.threads.push(
+                std::thread::Builder::new()
+                    .name("resolver-proxy-query".into())
+                    .spawn(move || worker_proxy.forward_udp(&socket, &queue, &stop))?,
+            );
+        }
+        let stop = Arc::clone(&handle.stop);
+        handle.threads.push(
+            std::thread::Builder::new()
+                .name("resolver-proxy-udp".into())
+                .spawn(move || serve_udp(&udp, &jobs, &stop))?,
+        );
+        let stop = Arc::clone(&handle.stop);
+        handle.threads.push(
+            std::thread::Builder::new()
+                .name("resolver-proxy-tcp".into())
+                .spawn(move || proxy.serve_tcp(&tcp, &stop))?,
+        );
+        tracing::info!(%addr, "DNS proxy listening");
+        Ok(handle)
+    }
+
+    /// Route upstreams for the query's name followed by the fallback.
+    fn upstreams_for(&self, query: &Message) -> Vec<SocketAddr> {
+        let routed = query.questions.first().and_then(|q| {
+            let routes = self.routes.read().unwrap_or_else(PoisonError::into_inner);
+            routes
+                .iter()
+                .filter(|(suffix, _)| in_zone(&q.name, suffix))
+                .max_by_key(|(suffix, _)| suffix.len())
+                .map(|(_, upstreams)| upstreams.clone())
+        });
+        let mut upstreams = routed.unwrap_or_default();
+        for upstream in &self.fallback {
+            if !upstreams.contains(upstream) {
+                upstreams.push(*upstream);
+            }
+        }
+        upstreams
+    }
+
+    /// Tries each upstream in turn, returning the first usable answer.
+    fn forward_packet(&self, query: &Message, packet: &[u8], tcp: bool) -> Option<Vec<u8>> {
+        for upstream in self.upstreams_for(query) {
+            let result = if tcp {
+                exchange_tcp(upstream, packet, self.timeout)
+            } else {
+                exchange_udp(upstream, packet, self.timeout)
+            };
+            match result.map(|answer| (Message::decode(&answer), answer)) {
+                Ok((Ok(reply), answer))
+                    if reply.response
+                        && !matches!(reply.rcode, Rcode::ServFail | Rcode::Refused) =>
+                {
+                    return Some(answer);
+                }
+                Ok((Ok(reply), _)) => {
+                    tracing::debug!(%upstream, rcode = ?reply.rcode, "Upstream declined, trying next");
+                }
+                Ok((Err(e), _)) => {
+                    tracing::debug!(%upstream, error = %e, "Malformed upstream answer, trying next");
+                }
+                Err(e) => {
+                    tracing::debug!(%upstream, error = %e, "Upstream failed, trying next");
+                }
+            }
+        }
+        None
+    }
+
+    /// Answers one client packet.
+    fn respond(&self, packet: &[u8], tcp: bool) -> Option<Vec<u8>> {
+        let query = match Message::decode(packet) {
+            Ok(query) if !query.response => query,
+            Err(e) if packet.len() >= 2 => {
+                tracing::debug!(error = %e, "Malformed DNS query");
+                return Message::response(
+                    u16::from_be_bytes([packet[0], packet[1]]),
+                    Rcode::FormErr,
+                )
+                .encode()
+                .ok();
+            }
+            // Stray responses and packets too short to answer.
+            Ok(_) | Err(_) => return None,
+        };
+        tracing::debug!(questions = ?query.questions, tcp, "Forwarding DNS query");
+        if let Some(answer) = self.forward_packet(&query, packet, tcp) {
+            return Some(answer);
+        }
+        let rcode = if self.upstreams_for(&query).is_empty() {
+            Rcode::Refused
+        } else {
+            tracing::warn!(questions = ?query.questions, "No upstream answered");
+            Rcode::ServFail
+        };
+        Message {
+            rcode,
+            ..query.reply()
+        }
+        .encode()
+        .ok()
+    }
+
+    /// Forwards queued UDP queries until the proxy is stopped.
+    fn forward_udp(&self, socket: &UdpSocket, queue: &Mutex<Receiver<UdpJob>>, stop: &AtomicBool) {
+        while !stop.load(Ordering::Acquire) {
+            let job = queue
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .recv_timeout(STOP_POLL);
+            let (packet, peer) = match job {
+                Ok(job) => job,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+            // Queries still queued at shutdown are dropped.
+            if stop.load(Ordering::Acquire) {
+                break;
+            }
+            if let Some(answer) = self.respond(&packet, false) {
+                send_udp(socket, &answer, peer);
+            }
+        }
+    }
+
+    fn serve_tcp(self: Arc<Self>, listener: &TcpListener, stop: &AtomicBool) {
+        let active = Arc::new(AtomicUsize::new(0));
+        while !stop.load(Ordering::Acquire) {
+            match wait_readable(listener, STOP_POLL) {
+                Ok(false) => continue,
+                Ok(true) => {}
+                Err(e) => {
+                    tracing::error!(error = %e, "DNS proxy TCP listener failed");
+                    break;
+                }
+            }
+            match listener.accept() {
+                Ok((stream, peer)) => {
+                    if active.fetch_add(1, Ordering::AcqRel) >= self.max_tcp_connections {
+                        active.fetch_sub(1, Ordering::AcqRel);
+                        tracing::debug!(%peer, "Closing DNS proxy TCP client: too many connections");
+                        continue;
+                    }
+                    let (proxy, connections) = (Arc::clone(&self), Arc::clone(&active));
+                    let spawned = std::thread::Builder::new()
+                        .name("resolver-proxy-conn".into())
+                        .spawn(move || {
+                            if let Err(e) = proxy.handle_tcp(stream) {
+                                tracing::debug!(error = %e, "DNS proxy TCP connection ended with error");
+                            }
+                            connections.fetch_sub(1, Ordering::AcqRel);
+                        });
+                    if let Err(e) = spawned {
+                        active.fetch_sub(1, Ordering::AcqRel);
+                        tracing::warn!(error = %e, "Failed to spawn DNS proxy connection thread");
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => tracing::warn!(error = %e, "Failed to accept DNS proxy client"),
+            }
+        }
+    }
+
+    fn handle_tcp(&self, mut stream: TcpStream) -> io::Result<()> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(TCP_IDLE_TIMEOUT))?;
+        loop {
+            let packet = match read_tcp(&mut stream) {
+                Ok(packet) => packet,
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(e) => return Err(e),
+            };
+            match self.respond(&packet, true) {
+                Some(answer) => write_tcp(&mut stream, &answer)?,
+                None => return Ok(()),
+            }
+        }
+    }
+}
+
+/// Receives UDP queries and queues them for the workers, answering
+/// `SERVFAIL` when the queue is full.
+fn serve_udp(socket: &UdpSocket, jobs: &SyncSender<UdpJob>, stop: &AtomicBool) {
+    let mut buf = [0; 4096];
+    while !stop.load(Ordering::Acquire) {
+        let (len, peer) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                continue;
+            }
+            Err(e) => {
+                tracing::debug!(error = %e, "DNS proxy UDP receive failed");
+                continue;
+            }
+        };
+        match jobs.try_send((buf[..len].to_vec(), peer)) {
+            Ok(()) => {}
+            Err(TrySendError::Full((packet, peer))) => {
+                tracing::debug!(%peer, "DNS proxy UDP queue full, answering SERVFAIL");
+                if let Some(answer) = overloaded(&packet) {
+                    send_udp(socket, &answer, peer);
+                }
+            }
+            Err(TrySendError::Disconnected(_)) => break,
+        }
+    }
+}
+
+/// `SERVFAIL` for a query that could not be queued; `None` for packets that
+/// are not queries.
+fn overloaded(packet: &[u8]) -> Option<Vec<u8>> {
+    let query = Message::decode(packet)
+        .ok()
+        .filter(|query| !query.response)?;
+    Message {
+        rcode: Rcode::ServFail,
+        ..query.reply()
+    }
+    .encode()
+    .ok()
+}
+
+fn send_udp(socket: &UdpSocket, answer: &[u8], peer: SocketAddr) {
+    if let Err(e) = socket.send_to(answer, peer) {
+        tracing::debug!(%peer, error = %e, "DNS proxy UDP send failed");
+    }
+}
+
+fn set_route(routes: &Routes, suffix: &str, upstreams: impl IntoIterator<Item = SocketAddr>) {
+    let suffix = suffix.trim_end_matches('.').to_ascii_lowercase();
+    let upstreams = upstreams.into_iter().collect();
+    routes
+        .write()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert(suffix, upstreams);
+}
+
+/// Handle to a [`DnsProxy`] running in the background.
+///
+/// Shutting down (or dropping) stops both transports.
+#[derive(Debug)]
+pub struct DnsProxyHandle {
+    addr: SocketAddr,
+    routes: Routes,
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl DnsProxyHandle {
+    /// Address both transports listen on.
+    #[must_use]
+    pub const fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Adds or replaces the route for `suffix`; applies to the next query.
+    pub fn set_route(&self, suffix: &str, upstreams: impl IntoIterator<Item = SocketAddr>) {
+        set_route(&self.routes, suffix, upstreams);
+    }
+
+    /// Removes the route for `suffix`, returning whether it existed.
+    #[must_use]
+    pub fn remove_route(&self, suffix: &str) -> bool {
+        let suffix = suffix.trim_end_matches('.').to_ascii_lowercase();
+        self.routes
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&suffix)
+            .is_some()
+    }
+
+    /// Stops the proxy and waits for its listening threads to exit.
+    pub fn shutdown(mut self) {
+        self.stop_serving();
+    }
+
+    fn stop_serving(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for DnsProxyHandle {
+    fn drop(&mut self) {
+        self.stop_serving();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dns::{RData, Record, RecordType};
+    use std::net::Ipv4Addr;
+
+    const TIMEOUT: Duration = Duration::from_millis(300);
+
+    /// A loopback upstream answering every A query with `answer`, or with
+    /// `rcode` and no records if `answer` is `None`.
+    struct StandIn {
+        addr: SocketAddr,
+        stop: Arc<AtomicBool>,
+        threads: Vec<JoinHandle<()>>,
+    }
+
+    impl StandIn {
+        fn answering(answer: Ipv4Addr) -> Self {
+            Self::start(Some(answer), Rcode::NoError)
+        }
+
+        fn failing(rcode: Rcode) -> Self {
+            Self::start(None, rcode)
+        }
+
+        fn start(answer: Option<Ipv4Addr>, rcode: Rcode) -> Self {
+            let (udp, tcp) = bind_udp_tcp("127.0.0.1:0".parse().unwrap()).unwrap();
+            let addr = udp.local_addr().unwrap();
+            let stop = Arc::new(AtomicBool::new(false));
+            let reply = move |packet: &[u8]| {
+                let query = Message::decode(packet).unwrap();
+                let mut reply = query.reply();
+                reply.rcode = rcode;
+                if let Some(ip) = answer {
+                    reply.answers.push(Record::new(
+                        query.questions[0].name.clone(),
+                        60,
+                        RData::A(ip),
+                    ));
+                }
+                reply.encode().unwrap()
+            };
+            udp.set_read_timeout(Some(STOP_POLL)).unwrap();
+            tcp.set_nonblocking(true).unwrap();
+            let udp_stop = Arc::clone(&stop);
+            let udp_thread = std::thread::spawn(move || {
+                let mut buf = [0; 512];
+                while !udp_stop.load(Ordering::Acquire) {
+                    if let Ok((len, peer)) = udp.recv_from(&mut buf) {
+                        udp.send_to(&reply(&buf[..len]), peer).unwrap();
+                    }
+                }
+            });
+            let tcp_stop = Arc::clone(&stop);
+            let tcp_thread = std::thread::spawn(move || {
+                while !tcp_stop.load(Ordering::Acquire) {
+                    if let Ok((mut stream, _)) = tcp.accept() {
+                        stream.set_nonblocking(false).unwrap();
+                        let packet = read_tcp(&mut stream).unwrap();
+                        write_tcp(&mut stream, &reply(&packet)).unwrap();
+                    } else {
+                        std::thread::sleep(Duration::from_millis(10));
+                    }
+                }
+            });
+            Self {
+                addr,
+                stop,
+                threads: vec![udp_thread, tcp_thread],
+            }
+        }
+    }
+
+    impl Drop for StandIn {
+        fn drop(&mut self) {
+            self.stop.store(true, Ordering::Release);
+            for thread in self.threads.drain(..) {
+                let _ = thread.join();
+            }
+        }
+    }
+
+    /// A bound UDP port that never answers.
+    fn silent() -> (UdpSocket, SocketAddr) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = socket.local_addr().unwrap();
+        (socket, addr)
+    }
+
+    fn answer_of(reply: &Message) -> Option<Ipv4Addr> {
+        reply.answers.iter().find_map(|r| match r.data {
+            RData::A(ip) => Some(ip),
+            _ => None,
+        })
+    }
+
+    fn ask(addr: SocketAddr, name: &str, tcp: bool) -> Message {
+        let query = Message::query(9, name, RecordType::A).encode().unwrap();
+        let answer = if tcp {
+            exchange_tcp(addr, &query, Duration::from_secs(5))
+        } else {
+            exchange_udp(addr, &query, Duration::from_secs(5))
+        };
+        Message::decode(&answer.unwrap()).unwrap()
+    }
+
+    #[test]
+    fn routes_by_longest_suffix() {
+        let containers = StandIn::answering(Ipv4Addr::new(172, 17, 0, 2));
+        let vpn = StandIn::answering(Ipv4Addr::new(10, 8, 0, 1));
+        let special = StandIn::answering(Ipv4Addr::new(172, 17, 0, 9));
+        let proxy = DnsProxy::new()
+            .route("myapp.local", [containers.addr])
+            .route("db.myapp.local", [special.addr])
+            .route("corp.example", [vpn.addr])
+            .timeout(TIMEOUT)
+            .spawn("127.0.0.1:0".parse().unwrap())
+            .unwrap();
+        let addr = proxy.local_addr();
+
+        assert_eq!(
+            answer_of(&ask(addr, "web.myapp.local", false)),
+            Some(Ipv4Addr::new(172, 17, 0, 2))
+        );
+        assert_eq!(
+            answer_of(&ask(addr, "x.db.myapp.local", false)),
+            Some(Ipv4Addr::new(172, 17, 0, 9))
+        );
+        assert_eq!(
+            answer_of(&ask(addr, "git.corp.example", true)),
+            Some(Ipv4Addr::new(10, 8, 0, 1))
+        );
+        let unrouted = ask(addr, "example.com", false);
+        assert_eq!(unrouted.rcode, Rcode::Refused);
+        assert_eq!(unrouted.id, 9);
+    }
+
+    #[test]
+    fn falls_back_after_timeouts_and_failures() {
+        let (_silent, silent_addr) = silent();
+        let refusing = StandIn::failing(Rcode::Refused);
+        let fallback = StandIn::answering(Ipv4Addr::new(10, 8, 0, 1));
+        let proxy = DnsProxy::new()
+            .route("myapp.local", [silent_addr, refusing.addr])
+            .fallback([fallback.addr])
+            .timeout(TIMEOUT);
+
+        let query = Message::query(1, "web.myapp.local", RecordType::A);
+        assert_eq!(
+            answer_of(&proxy.forward(&query, false)),
+            Some(Ipv4Addr::new(10, 8, 0, 1))
+        );
+        // Unrouted names use the fallback directly.
+        let query = Message::query(2, "example.com", RecordType::A);
+        assert_eq!(
+            answer_of(&proxy.forward(&query, true)),
+            Some(Ipv4Addr::new(10, 8, 0, 1))
+        );
+
+        let (_silent, silent_addr) = silent();
+        let stranded = DnsProxy::new()
+            .route("myapp.local", [silent_addr])
+            .timeout(TIMEOUT);
+        let reply = stranded.forward(&Message::query(3, "web.myapp.local", RecordType::A), false);
+        assert_eq!(reply.rcode, Rcode::ServFail);
+    }
+
+    #[test]
+    fn routes_update_while_running() {
+        let old = StandIn::answering(Ipv4Addr::new(172, 17, 0, 2));
+        let new = StandIn::answering(Ipv4Addr::new(172, 17, 0, 3));
+        let proxy = DnsProxy::new()
+            .route("myapp.local", [old.addr])
+            .timeout(TIMEOUT)
+            .spawn("127.0.0.1:0".parse().unwrap())
+            .unwrap();
+        let addr = proxy.local_addr();
+
+        proxy.set_route("myapp.local", [new.addr]);
+        assert_eq!(
+            answer_of(&ask(addr, "web.myapp.local", false)),
+            Some(Ipv4Addr::new(172, 17, 0, 3))
+        );
+        assert!(proxy.remove_route("myapp.local"));
+        assert_eq!(ask(addr, "web.myapp.local", false).rcode, Rcode::Refused);
+    }
+
+    #[test]
+    fn flood_with_silent_upstream_is_answered_servfail() {
+        let (_silent, silent_addr) = silent();
+        let working = StandIn::answering(Ipv4Addr::new(10, 8, 0, 1));
+        let proxy = DnsProxy::new()
+            .route("myapp.local", [silent_addr])
+            .route("corp.example", [working.addr])
+            .timeout(TIMEOUT)
+            .udp_workers(2)
+            .spawn("127.0.0.1:0".parse().unwrap())
+            .unwrap();
+        let addr = proxy.local_addr();
+
+        let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+        client.set_read_timeout(Some(TIMEOUT / 2)).unwrap();
+        for id in 0..100 {
+            let query = Message::query(id, "web.myapp.local", RecordType::A);
+            client.send_to(&query.encode().unwrap(), addr).unwrap();
+        }
+        // Only the workers and their queue wait on the upstream; everything
+        // else is refused before the upstream timeout.
+        let mut buf = [0; 512];
+        let mut servfails = 0;
+        while let Ok(len) = client.recv(&mut buf) {
+            if Message::decode(&buf[..len]).unwrap().rcode == Rcode::ServFail {
+                servfails += 1;
+            }
+        }
+        assert!(servfails >= 80, "only {servfails} queries refused");
+
+        // Other routes are served again once the backlog has timed out.
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        loop {
+            let reply = ask(addr, "vpn.corp.example", false);
+            if reply.rcode != Rcode::ServFail {
+                assert_eq!(answer_of(&reply), Some(Ipv4Addr::new(10, 8, 0, 1)));
+                break;
+            }
+            assert!(
+                std::time::Instant::now() < deadline,
+                "proxy stayed overloaded"
+            );
+            std::thread::sleep(TIMEOUT / 3);
+        }
+    }
+
+    #[test]
+    fn closes_tcp_connections_over_the_limit() {
+        let upstream = StandIn::answering(Ipv4Addr::new(172, 17, 0, 2));
+        let proxy = DnsProxy::new()
+            .route("myapp.local", [upstream.addr])
+            .timeout(TIMEOUT)
+            .max_tcp_connections(1)
+            .spawn("127.0.0.1:0".parse().unwrap())
+            .unwrap();
+        let addr = proxy.local_addr();
+        let query = Message::query(5, "web.myapp.local", RecordType::A)
+            .encode()
+            .unwrap();
+
+        // Holds the only slot until dropped.
+        let idle = TcpStream::connect(addr).unwrap();
+        let mut refused = TcpStream::connect(addr).unwrap();
+        refused
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let _ = write_tcp(&mut refused, &query);
+        assert!(read_tcp(&mut refused).is_err());
+        // UDP is unaffected.
+        assert_eq!(
+            answer_of(&ask(addr, "web.myapp.local", false)),
+            Some(Ipv4Addr::new(172, 17, 0, 2))
+        );
+
+        drop(idle);
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while exchange_tcp(addr, &query, Duration::from_secs(5)).is_err() {
+            assert!(std::time::Instant::now() < deadline, "slot never freed");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    }
+}
@@ -0,0 +1,800 @@
+//! Embedded authoritative DNS server (`dns-server` feature).
+//!
+//! [`DnsServer`] answers queries for the domains of one or more
+//! [`ResolverConfig`]s from a [`Records`] table that can be changed while
+//! the server runs, so an app can register `myapp.local` and serve it
+//! without a separate DNS daemon.
+//!
+//! ```rust,ignore
+//! let config = ResolverConfig::new("myapp.local", "127.0.0.1", 5553);
+//! let records = Records::new();
+//! records.add("myapp.local", Ipv4Addr::LOCALHOST);
+//! records.add("*.myapp.local", Ipv4Addr::LOCALHOST);
+//! let server = DnsServer::for_config(&config, records.clone()).spawn_for(&config)?;
+//! resolver.register(&config)?;
+//! records.add("api.myapp.local", RData::txt("v=1")); // served immediately
+//! ```
+
+use std::collections::BTreeMap;
+use std::io;
+use std::net::{SocketAddr, TcpListener, TcpStream, UdpSocket};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, PoisonError, RwLock};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::config::ResolverConfig;
+use crate::dns::{
+    CLASS_ANY, CLASS_IN, MAX_UDP_SIZE, Message, RData, Rcode, Record, RecordType, Soa, in_zone,
+    read_tcp, write_tcp,
+};
+use crate::error::Result;
+use crate::util::{bind_udp_tcp, wait_readable};
+
+/// TTL of records added without an explicit one, and of negative answers.
+pub const DEFAULT_TTL: u32 = 60;
+
+/// Default for [`DnsServer::max_tcp_connections`].
+pub const DEFAULT_MAX_TCP_CONNECTIONS: usize = 64;
+
+/// How often the serving threads check whether they have been shut down.
+const STOP_POLL: Duration = Duration::from_millis(100);
+
+/// How long a TCP client may stay idle between queries.
+const TCP_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Most CNAME links followed within the table for one answer.
+const MAX_CNAME_CHAIN: usize = 8;
+
+/// Shared, runtime-updatable record table.
+///
+/// Clones share the same table. Names are matched case-insensitively; a
+/// leading `*.` label makes a wildcard that answers for names below it
+/// that have no records of their own.
+#[derive(Debug, Clone, Default)]
+pub struct Records {
+    inner: Arc<RwLock<Table>>,
+}
+
+#[derive(Debug, Default)]
+struct Table {
+    names: BTreeMap<String, Vec<Record>>,
+    /// Bumped on every change; used as the SOA serial.
+    serial: u32,
+}
+
+impl Records {
+    /// Creates an empty table.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a record at `name` with [`DEFAULT_TTL`].
+    pub fn add(&self, name: &str, data: impl Into<RData>) {
+        self.add_with_ttl(name, DEFAULT_TTL, data);
+    }
+
+    /// Adds a record at `name` with the given TTL.
+    pub fn add_with_ttl(&self, name: &str, ttl: u32, data: impl Into<RData>) {
+        let key = normalize(name);
+        self.update(|table| {
+            table
+                .names
+                .entry(key.clone())
+                .or_default()
+                .push(Record::new(key, ttl, data.into()));
+        });
+    }
+
+    /// Replaces all records at `name` with `data`, using [`DEFAULT_TTL`].
+    pub fn set(&self, name: &str, data: impl IntoIterator<Item = RData>) {
+        let key = normalize(name);
+        let records: Vec<Record> = data
+            .into_iter()
+            .map(|d| Record::new(key.clone(), DEFAULT_TTL, d))
+            .collect();
+        self.update(|table| {
+            if records.is_empty() {
+                table.names.remove(&key);
+            } else {
+                table.names.insert(key, records);
+            }
+        });
+    }
+
+    /// Removes all records at `name`, returning whether there were any.
+    #[must_use]
+    pub fn remove(&self, name: &str) -> bool {
+        let key = normalize(name);
+        let mut removed = false;
+        self.update(|table| removed = table.names.remove(&key).is_some());
+        removed
+    }
+
+    /// Removes every record.
+    pub fn clear(&self) {
+        self.update(|table| table.names.clear());
+    }
+
+    /// Records stored at exactly `name` (wildcards are not expanded).
+    #[must_use]
+    pub fn get(&self, name: &str) -> Vec<Record> {
+        self.read(|table| table.names.get(&normalize(name)).cloned())
+            .unwrap_or_default()
+    }
+
+    fn update(&self, f: impl FnOnce(&mut Table)) {
+        let mut table = self.inner.write().unwrap_or_else(PoisonError::into_inner);
+        f(&mut table);
+        table.serial = table.serial.wrapping_add(1);
+    }
+
+    fn read<T>(&self, f: impl FnOnce(&Table) -> T) -> T {
+        f(&self.inner.read().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+impl Table {
+    /// Records answering for `name`, expanding the closest wildcard with the
+    /// queried name as owner.
+    fn lookup(&self, name: &str) -> Option<Vec<Record>> {
+        if let Some(records) = self.names.get(name) {
+            return Some(records.clone());
+        }
+        if self.has_descendants(name) {
+            return Some(Vec::new());
+        }
+        let mut ancestor = name;
+        while let Some((_, parent)) = ancestor.split_once('.') {
+            if let Some(records) = self.names.get(&format!("*.{parent}")) {
+                return Some(
+                    records
+                        .iter()
+                        .map(|r| Record {
+                            name: name.to_string(),
+                            ..r.clone()
+                        })
+                        .collect(),
+                );
+            }
+            if self.names.contains_key(parent) || self.has_descendants(parent) {
+                // The closest existing ancestor has no wildcard.
+                return None;
+            }
+            ancestor = parent;
+        }
+        None
+    }
+
+    /// Whether any name, wildcards included, lies strictly below `name`, so
+    /// that `name` exists even without records of its own.
+    fn has_descendants(&self, name: &str) -> bool {
+        let suffix = format!(".{name}");
+        self.names.keys().any(|key| key.ends_with(&suffix))
+    }
+}
+
+fn normalize(name: &str) -> String {
+    name.trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// Authoritative DNS server for a set of zones.
+///
+/// Queries for names outside every zone are refused. Within a zone, missing
+/// names get `NXDOMAIN` and missing types an empty answer, both with a
+/// synthesized SOA record in the authority section. CNAMEs are followed
+/// within the table.
+#[derive(Debug, Clone)]
+pub struct DnsServer {
+    records: Records,
+    zones: Vec<String>,
+    max_tcp_connections: usize,
+}
+
+impl DnsServer {
+    /// Creates a server answering from `records`; add zones with
+    /// [`zone`](Self::zone).
+    #[must_use]
+    pub const fn new(records: Records) -> Self {
+        Self {
+            records,
+            zones: Vec::new(),
+            max_tcp_connections: DEFAULT_MAX_TCP_CONNECTIONS,
+        }
+    }
+
+    /// Creates a server authoritative for `config.domain`.
+    #[must_use]
+    pub fn for_config(config: &ResolverConfig, records: Records) -> Self {
+        Self::new(records).zone(&config.domain)
+    }
+
+    /// Makes the server authoritative for `domain` and its subdomains.
+    #[must_use]
+    pub fn zone(mut self, domain: &str) -> Self {
+        self.zones.push(normalize(domain));
+        self
+    }
+
+    /// Limits how many TCP clients are served at once (default: 64).
+    /// Connections over the limit are closed; clients retry or fall back
+    /// to UDP.
+    #[must_use]
+    pub const fn max_tcp_connections(mut self, max: usize) -> Self {
+        self.max_tcp_connections = max;
+        self
+    }
+
+    /// The record table the server answers from.
+    #[must_use]
+    pub const fn records(&self) -> &Records {
+        &self.records
+    }
+
+    /// Builds the response to `query`.
+    #[must_use]
+    pub fn answer(&self, query: &Message) -> Message {
+        let mut reply = query.reply();
+        if query.response {
+            reply.rcode = Rcode::FormErr;
+            return reply;
+        }
+        if query.opcode != 0 {
+            reply.rcode = Rcode::NotImp;
+            return reply;
+        }
+        let [question] = query.questions.as_slice() else {
+            reply.rcode = Rcode::FormErr;
+            return reply;
+        };
+        let name = normalize(&question.name);
+        let Some(zone) = self.zone_of(&name) else {
+            reply.rcode = Rcode::Refused;
+            return reply;
+        };
+        reply.authoritative = true;
+        if question.qclass != CLASS_IN && question.qclass != CLASS_ANY {
+            return reply;
+        }
+
+        let table = self
+            .records
+            .inner
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        let qtype = question.qtype;
+        let mut current = name;
+        for _ in 0..=MAX_CNAME_CHAIN {
+            let records = table.lookup(&current);
+            let mut matching: Vec<Record> = records
+                .iter()
+                .flatten()
+                .filter(|r| qtype == RecordType::Any || r.data.record_type() == qtype)
+                .cloned()
+                .collect();
+            if current == zone
+                && matches!(qtype, RecordType::Soa | RecordType::Any)
+                && !matching.iter().any(|r| matches!(r.data, RData::Soa(_)))
+            {
+                matching.push(soa_record(zone, table.serial));
+            }
+            let cname = records.iter().flatten().find_map(|r| match &r.data {
+                RData::Cname(target) if matching.is_empty() => Some((r.clone(), target.clone())),
+                _ => None,
+            });
+
+            if let Some((record, target)) = cname {
+                reply.answers.push(record);
+                current = normalize(&target);
+                if self.zone_of(&current) == Some(zone) {
+                    continue;
+                }
+                break;
+            }
+            if records.is_none() && current != zone {
+                reply.rcode = Rcode::NxDomain;
+            }
+            if matching.is_empty() {
+                reply.authority.push(soa_record(zone, table.serial));
+            }
+            reply.answers.extend(matching);
+            break;
+        }
+        drop(table);
+        reply
+    }
+
+    /// Binds UDP and TCP on `addr` and serves on background threads until
+    /// the returned handle is shut down or dropped. With port 0, both
+    /// transports share the same free port.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ResolverError::Io`](crate::ResolverError::Io) if a socket cannot be bound or a thread
+    /// cannot be spawned.
+    pub fn spawn(self, addr: SocketAddr) -> Result<DnsServerHandle> {
+        let (udp, tcp) = bind_udp_tcp(addr)?;
+        self.spawn_on(udp, tcp)
+    }
+
+    /// Serves on `config.nameserver:config.port`, where macOS will send the
+    /// domain's queries once the config is registered.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ResolverError::InvalidConfig`](crate::ResolverError::InvalidConfig) if the nameserver is not an
+    /// IP address, otherwise as [`spawn`](Self::spawn).
+    pub fn spawn_for(self, config: &ResolverConfig) -> Result<DnsServerHandle> {
+        self.spawn(config.socket_addr()?)
+    }
+
+    /// Serves on already-bound sockets.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ResolverError::Io`](crate::ResolverError::Io) if the sockets cannot be configured or a
+    /// thread cannot be spawned.
+    pub fn spawn_on(self, udp: UdpSocket, tcp: TcpListener) -> Result<DnsServerHandle> {
+        let addr = udp.local_addr()?;
+        udp.set_read_timeout(Some(STOP_POLL))?;
+        tcp.set_nonblocking(true)?;
+        let server = Arc::new(self);
+        // Dropping the handle on a failed spawn stops the threads already
+        // running.
+        let mut handle = DnsServerHandle {
+            addr,
+            records: server.records.clone(),
+            stop: Arc::new(AtomicBool::new(false)),
+            threads: Vec::with_capacity(2),
+        };
+
+        let (udp_server, stop) = (Arc::clone(&server), Arc::clone(&handle.stop));
+        handle.threads.push(
+            std::thread::Builder::new()
+                .name("resolver-dns-udp".into())
+                .spawn(move || udp_server.serve_udp(&udp, &stop))?,
+        );
+        let stop = Arc::clone(&handle.stop);
+        handle.threads.push(
+            std::thread::Builder::new()
+                .name("resolver-dns-tcp".into())
+                .spawn(move || server.serve_tcp(&tcp, &stop))?,
+        );
+        tracing::info!(%addr, "Embedded DNS server listening");
+        Ok(handle)
+    }
+
+    /// Longest zone containing `name`.
+    fn zone_of(&self, name: &str) -> Option<&str> {
+        self.zones
+            .iter()
+            .filter(|zone| in_zone(name, zone))
+            .max_by_key(|zone| zone.len())
+            .map(String::as_str)
+    }
+
+    /// Decodes a query and encodes the answer, or a `FORMERR` echoing the ID
+    /// if the query is malformed. Returns `None` if nothing can be sent, and
+    /// for responses, so two servers never bounce packets off each other.
+    fn respond(&self, packet: &[u8], max: usize) -> Option<Vec<u8>> {
+        let is_response = packet.get(2).is_some_and(|flags| flags & 0x80 != 0);
+        let reply = match Message::decode(packet) {
+            Ok(query) if !query.response => {
+                tracing::debug!(questions = ?query.questions, "DNS query");
+                self.answer(&query)
+            }
+            Err(e) if packet.len() >= 2 && !is_response => {
+                tracing::debug!(error = %e, "Malformed DNS query");
+                Message::response(u16::from_be_bytes([packet[0], packet[1]]), Rcode::FormErr)
+            }
+            // Stray responses and packets too short to answer.
+            Ok(_) | Err(_) => return None,
+        };
+        match reply.encode_udp(max) {
+            Ok(bytes) => Some(bytes),
+            Err(e) => {
+                tracing::warn!(error = %e, "Failed to encode DNS response");
+                None
+            }
+        }
+    }
+
+    fn serve_udp(&self, socket: &UdpSocket, stop: &AtomicBool) {
+        let mut buf = [0; 4096];
+        while !stop.load(Ordering::Acquire) {
+            let (len, peer) = match socket.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    continue;
+                }
+                Err(e) => {
+                    tracing::debug!(error = %e, "DNS UDP receive failed");
+                    continue;
+                }
+            };
+            if let Some(response) = self.respond(&buf[..len], MAX_UDP_SIZE) {
+                if let Err(e) = socket.send_to(&response, peer) {
+                    tracing::debug!(%peer, error = %e, "DNS UDP send failed");
+                }
+            }
+        }
+    }
+
+    fn serve_tcp(self: Arc<Self>, listener: &TcpListener, stop: &AtomicBool) {
+        let active = Arc::new(AtomicUsize::new(0));
+        while !stop.load(Ordering::Acquire) {
+            match wait_readable(listener, STOP_POLL) {
+                Ok(false) => continue,
+                Ok(true) => {}
+                Err(e) => {
+                    tracing::error!(error = %e, "DNS TCP listener failed");
+                    break;
+                }
+            }
+            match listener.accept() {
+                Ok((stream, peer)) => {
+                    if active.fetch_add(1, Ordering::AcqRel) >= self.max_tcp_connections {
+                        active.fetch_sub(1, Ordering::AcqRel);
+                        tracing::debug!(%peer, "Closing DNS TCP client: too many connections");
+                        continue;
+                    }
+                    let (server, connections) = (Arc::clone(&self), Arc::clone(&active));
+                    let spawned = std::thread::Builder::new()
+                        .name("resolver-dns-conn".into())
+                        .spawn(move || {
+                            if let Err(e) = server.handle_tcp(stream) {
+                                tracing::debug!(error = %e, "DNS TCP connection ended with error");
+                            }
+                            connections.fetch_sub(1, Ordering::AcqRel);
+                        });
+                    if let Err(e) = spawned {
+                        active.fetch_sub(1, Ordering::AcqRel);
+                        tracing::warn!(error = %e, "Failed to spawn DNS connection thread");
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => tracing::warn!(error = %e, "Failed to accept DNS client"),
+            }
+        }
+    }
+
+    fn handle_tcp(&self, mut stream: TcpStream) -> io::Result<()> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(TCP_IDLE_TIMEOUT))?;
+        loop {
+            let packet = match read_tcp(&mut stream) {
+                Ok(packet) => packet,
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(e) => return Err(e),
+            };
+            match self.respond(&packet, usize::from(u16::MAX)) {
+                Some(response) => write_tcp(&mut stream, &response)?,
+                None => return Ok(()),
+            }
+        }
+    }
+}
+
+/// SOA record synthesized for a zone apex.
+fn soa_record(zone: &str, serial: u32) -> Record {
+    let hostmaster = if zone.is_empty() {
+        "hostmaster".to_string()
+    } else {
+        format!("hostmaster.{zone}")
+    };
+    Record::new(
+        zone,
+        DEFAULT_TTL,
+        RData::Soa(Soa {
+            mname: zone.to_string(),
+            rname: hostmaster,
+            serial,
+            refresh: 3600,
+            retry: 600,
+            expire: 86400,
+            minimum: DEFAULT_TTL,
+        }),
+    )
+}
+
+/// Handle to a [`DnsServer`] running in the background.
+///
+/// Shutting down (or dropping) stops both transports. Records can be changed
+/// through [`records`](Self::records) while the server runs.
+#[derive(Debug)]
+pub struct DnsServerHandle {
+    addr: SocketAddr,
+    records: Records,
+    stop: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl DnsServerHandle {
+    /// Address both transports listen on.
+    #[must_use]
+    pub const fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The record table the server answers from.
+    #[must_use]
+    pub const fn records(&self) -> &Records {
+        &self.records
+    }
+
+    /// Stops the server and waits for its threads to exit.
+    pub fn shutdown(mut self) {
+        self.stop_serving();
+    }
+
+    fn stop_serving(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for DnsServerHandle {
+    fn drop(&mut self) {
+        self.stop_serving();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    fn server() -> DnsServer {
+        let records = Records::new();
+        records.add("myapp.local", Ipv4Addr::LOCALHOST);
+        records.add("web.myapp.local", Ipv4Addr::new(127, 0, 0, 2));
+        records.add("web.myapp.local", Ipv6Addr::LOCALHOST);
+        records.add("api.myapp.local", RData::Cname("web.myapp.local".into()));
+        records.add("ext.myapp.local", RData::Cname("example.com".into()));
+        records.add("*.dev.myapp.local", Ipv4Addr::new(127, 0, 0, 3));
+        records.add("x.deep.myapp.local", RData::txt("hello"));
+        DnsServer::for_config(
+            &ResolverConfig::new("myapp.local", "127.0.0.1", 5553),
+            records,
+        )
+    }
+
+    fn ask(server: &DnsServer, name: &str, qtype: RecordType) -> Message {
+        server.answer(&Message::query(7, name, qtype))
+    }
+
+    fn data(reply: &Message) -> Vec<RData> {
+        reply.answers.iter().map(|r| r.data.clone()).collect()
+    }
+
+    #[test]
+    fn answers_address_and_text_records() {
+        let s = server();
+        let reply = ask(&s, "WEB.myapp.local.", RecordType::A);
+        assert!(reply.authoritative);
+        assert_eq!(reply.rcode, Rcode::NoError);
+        assert_eq!(data(&reply), [RData::A(Ipv4Addr::new(127, 0, 0, 2))]);
+        assert_eq!(
+            data(&ask(&s, "web.myapp.local", RecordType::Aaaa)),
+            [RData::Aaaa(Ipv6Addr::LOCALHOST)]
+        );
+        assert_eq!(
+            data(&ask(&s, "x.deep.myapp.local", RecordType::Txt)),
+            [RData::txt("hello")]
+        );
+        assert_eq!(ask(&s, "web.myapp.local", RecordType::Any).answers.len(), 2);
+    }
+
+    #[test]
+    fn expands_wildcards() {
+        let s = server();
+        let reply = ask(&s, "a.b.dev.myapp.local", RecordType::A);
+        assert_eq!(reply.answers[0].name, "a.b.dev.myapp.local");
+        assert_eq!(data(&reply), [RData::A(Ipv4Addr::new(127, 0, 0, 3))]);
+        // The wildcard does not cover its parent, which exists but is empty.
+        let parent = ask(&s, "dev.myapp.local", RecordType::A);
+        assert_eq!(parent.rcode, Rcode::NoError);
+        assert!(parent.answers.is_empty());
+    }
+
+    #[test]
+    fn follows_cnames() {
+        let s = server();
+        let reply = ask(&s, "api.myapp.local", RecordType::A);
+        assert_eq!(
+            data(&reply),
+            [
+                RData::Cname("web.myapp.local".into()),
+                RData::A(Ipv4Addr::new(127, 0, 0, 2))
+            ]
+        );
+        // Targets outside the zone are left to the client.
+        assert_eq!(
+            data(&ask(&s, "ext.myapp.local", RecordType::A)),
+            [RData::Cname("example.com".into())]
+        );
+        assert_eq!(
+            data(&ask(&s, "api.myapp.local", RecordType::Cname)),
+            [RData::Cname("web.myapp.local".into())]
+        );
+    }
+
+    #[test]
+    fn negative_answers_carry_soa() {
+        let s = server();
+        let missing = ask(&s, "nope.myapp.local", RecordType::A);
+        assert_eq!(missing.rcode, Rcode::NxDomain);
+        assert!(missing.answers.is_empty());
+        assert!(matches!(missing.authority[0].data, RData::Soa(_)));
+
+        let nodata = ask(&s, "web.myapp.local", RecordType::Txt);
+        assert_eq!(nodata.rcode, Rcode::NoError);
+        assert!(nodata.answers.is_empty());
+        assert_eq!(nodata.authority.len(), 1);
+        // Empty non-terminal: exists, but has no records.
+        assert_eq!(
+            ask(&s, "deep.myapp.local", RecordType::A).rcode,
+            Rcode::NoError
+        );
+
+        let apex = ask(&s, "myapp.local", RecordType::Soa);
+        assert!(matches!(apex.answers[0].data, RData::Soa(_)));
+
+        let outside = ask(&s, "example.com", RecordType::A);
+        assert_eq!(outside.rcode, Rcode::Refused);
+        assert!(!outside.authoritative);
+    }
+
+    #[test]
+    fn updates_are_visible_immediately() {
+        let s = server();
+        let records = s.records().clone();
+        records.set("web.myapp.local", [RData::A(Ipv4Addr::new(10, 0, 0, 1))]);
+        assert_eq!(
+            data(&ask(&s, "web.myapp.local", RecordType::A)),
+            [RData::A(Ipv4Addr::new(10, 0, 0, 1))]
+        );
+        assert!(records.remove("web.myapp.local"));
+        assert_eq!(
+            ask(&s, "web.myapp.local", RecordType::A).rcode,
+            Rcode::NxDomain
+        );
+        assert_eq!(records.get("myapp.local").len(), 1);
+    }
+
+    fn udp_query(addr: SocketAddr, query: &Message) -> Message {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        socket.send_to(&query.encode().unwrap(), addr).unwrap();
+        let mut buf = [0; 4096];
+        let len = socket.recv(&mut buf).unwrap();
+        Message::decode(&buf[..len]).unwrap()
+    }
+
+    fn tcp_query(addr: SocketAddr, query: &Message) -> Message {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        write_tcp(&mut stream, &query.encode().unwrap()).unwrap();
+        Message::decode(&read_tcp(&mut stream).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn serves_udp_and_tcp_on_loopback() {
+        let handle = server().spawn("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = handle.local_addr();
+
+        let reply = udp_query(addr, &Message::query(42, "web.myapp.local", RecordType::A));
+        assert_eq!(reply.id, 42);
+        assert_eq!(data(&reply), [RData::A(Ipv4Addr::new(127, 0, 0, 2))]);
+
+        for i in 0..40 {
+            handle
+                .records()
+                .add("big.myapp.local", RData::txt(format!("record number {i}")));
+        }
+        let query = Message::query(43, "big.myapp.local", RecordType::Txt);
+        let truncated = udp_query(addr, &query);
+        assert!(truncated.truncated);
+        assert!(truncated.answers.is_empty());
+        let full = tcp_query(addr, &query);
+        assert!(!full.truncated);
+        assert_eq!(full.answers.len(), 40);
+
+        handle.shutdown();
+    }
+
+    #[test]
+    fn answers_malformed_queries_with_formerr() {
+        let handle = server().spawn("127.0.0.1:0".parse().unwrap()).unwrap();
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        socket
+            .send_to(&[0xab, 0xcd, 1, 0, 0, 1, 0, 0], handle.local_addr())
+            .unwrap();
+        let mut buf = [0; 512];
+        let len = socket.recv(&mut buf).unwrap();
+        let reply = Message::decode(&buf[..len]).unwrap();
+        assert_eq!(reply.id, 0xabcd);
+        assert_eq!(reply.rcode, Rcode::FormErr);
+    }
+
+    #[test]
+    fn ignores_responses() {
+        let handle = server().spawn("127.0.0.1:0".parse().unwrap()).unwrap();
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_millis(300)))
+            .unwrap();
+        let response = Message::query(7, "web.myapp.local", RecordType::A).reply();
+        socket
+            .send_to(&response.encode().unwrap(), handle.local_addr())
+            .unwrap();
+        // A malformed packet with the response bit set is not answered either.
+        socket
+            .send_to(&[0xab, 0xcd, 0x80, 0, 0, 1, 0, 0], handle.local_addr())
+            .unwrap();
+        let mut buf = [0; 512];
+        let err = socket.recv(&mut buf).unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+        ));
+
+        // Queries are still answered afterwards.
+        let reply = udp_query(
+            handle.local_addr(),
+            &Message::query(8, "web.myapp.local", RecordType::A),
+        );
+        assert_eq!(reply.id, 8);
+    }
+
+    #[test]
+    fn closes_tcp_connections_over_the_limit() {
+        let handle = server()
+            .max_tcp_connections(1)
+            .spawn("127.0.0.1:0".parse().unwrap())
+            .unwrap();
+        let addr = handle.local_addr();
+        let query = Message::query(5, "web.myapp.local", RecordType::A);
+
+        // Holds the only slot until dropped.
+        let idle = TcpStream::connect(addr).unwrap();
+        let mut refused = TcpStream::connect(addr).unwrap();
+        refused
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let _ = write_tcp(&mut refused, &query.encode().unwrap());
+        assert!(read_tcp(&mut refused).is_err());
+        // UDP is unaffected.
+        assert_eq!(udp_query(addr, &query).id, 5);
+
+        drop(idle);
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        loop {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            let _ = write_tcp(&mut stream, &query.encode().unwrap());
+            if read_tcp(&mut stream).is_ok() {
+                break;
+            }
+            assert!(std::time::Instant::now() < deadline, "slot never freed");
+            std::thread::sleep(Duration::from_millis(20));
+        }
+    }
+}
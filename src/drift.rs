@@ -0,0 +1,95 @@
+//! Drift detection between managed files and the expected configuration.
+
+use crate::config::ResolverConfig;
+use std::fmt;
+
+/// A resolver directive compared by [`FileResolver::verify`](crate::FileResolver::verify).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DriftField {
+    /// The `nameserver` directive.
+    Nameserver,
+    /// The `port` directive.
+    Port,
+    /// The `search_order` directive.
+    SearchOrder,
+}
+
+impl fmt::Display for DriftField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Nameserver => "nameserver",
+            Self::Port => "port",
+            Self::SearchOrder => "search_order",
+        })
+    }
+}
+
+/// One directive whose on-disk value differs from the expected value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldDrift {
+    /// Domain of the drifted file.
+    pub domain: String,
+    /// The differing directive.
+    pub field: DriftField,
+    /// Expected value.
+    pub expected: String,
+    /// Value found on disk.
+    pub actual: String,
+}
+
+/// Differences between the managed files on disk and an expected set of
+/// configurations, as returned by
+/// [`FileResolver::verify`](crate::FileResolver::verify).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DriftReport {
+    /// Expected domains without a managed file.
+    pub missing: Vec<String>,
+    /// Managed domains that are not expected.
+    pub extra: Vec<String>,
+    /// Directives that differ from the expected configuration.
+    pub changed: Vec<FieldDrift>,
+    /// Expected domains whose managed file has unreadable directives.
+    pub corrupt: Vec<String>,
+}
+
+impl DriftReport {
+    /// Returns `true` if the files on disk match the expected set exactly.
+    #[must_use]
+    pub fn is_clean(&self) -> bool {
+        self.missing.is_empty()
+            && self.extra.is_empty()
+            && self.changed.is_empty()
+            && self.corrupt.is_empty()
+    }
+
+    /// Appends the field-by-field differences between `expected` and `actual`.
+    pub(crate) fn compare(&mut self, expected: &ResolverConfig, actual: &ResolverConfig) {
+        let fields = [
+            (
+                DriftField::Nameserver,
+                expected.nameserver.clone(),
+                actual.nameserver.clone(),
+            ),
+            (
+                DriftField::Port,
+                expected.port.to_string(),
+                actual.port.to_string(),
+            ),
+            (
+                DriftField::SearchOrder,
+                expected.search_order.to_string(),
+                actual.search_order.to_string(),
+            ),
+        ];
+        for (field, expected_value, actual_value) in fields {
+            if expected_value != actual_value {
+                self.changed.push(FieldDrift {
+                    domain: expected.domain.clone(),
+                    field,
+                    expected: expected_value,
+                    actual: actual_value,
+                });
+            }
+        }
+    }
+}
@@ -26,9 +26,64 @@ pub enum ResolverError {
         domain: String,
     },
 
+    /// The resolver file is managed by a different marker.
+    #[error("resolver file for {domain} is managed by another owner ({marker})")]
+    OwnedByOther {
+        /// The domain whose file is owned by someone else.
+        domain: String,
+        /// The other owner's marker.
+        marker: String,
+    },
+
+    /// The managed file is not bound to the expected owner PID.
+    #[error("resolver file for {domain} is not owned by this process (pid={pid:?})")]
+    NotOwner {
+        /// The domain whose file has a different owner.
+        domain: String,
+        /// The PID recorded in the file, if any.
+        pid: Option<u32>,
+    },
+
+    /// The domain has no resolver file.
+    #[error("resolver file not found: {domain}")]
+    NotFound {
+        /// The domain without a file.
+        domain: String,
+    },
+
+    /// The domain's ownership lock is held by another live owner.
+    #[error("resolver file locked by another owner: {domain}")]
+    Locked {
+        /// The locked domain.
+        domain: String,
+    },
+
     /// Invalid configuration values.
     #[error("invalid config: {0}")]
     InvalidConfig(String),
+
+    /// Signing a configuration profile failed.
+    #[error("profile signing failed: {0}")]
+    Signing(String),
+
+    /// A privileged helper's policy refused the request.
+    #[error("not authorized: {0}")]
+    Unauthorized(String),
+
+    /// A [`Preflight`](crate::Preflight) check found that the domain's
+    /// nameserver does not answer.
+    #[error("nameserver for {domain} is not answering: {reason}")]
+    Unreachable {
+        /// The domain being registered.
+        domain: String,
+        /// What the check observed, per transport.
+        reason: String,
+    },
+
+    /// A privileged helper sent or received a malformed or unsupported
+    /// message, or reported an error with no local equivalent.
+    #[error("helper protocol error: {0}")]
+    Protocol(String),
 }
 
 impl ResolverError {
@@ -1,13 +1,27 @@
 //! File-based `/etc/resolver/` management.
 //!
-//! Each file written by this module contains a caller-defined marker prefix
-//! (e.g. `# managed by myapp`) with an optional PID, enabling safe ownership
-//! checks and orphan cleanup.
+//! Each file written by this module starts with a caller-defined marker
+//! (e.g. `# managed by myapp`) with an optional PID and lease expiry, enabling
+//! safe ownership checks and orphan cleanup. See [`crate::header`] for the
+//! exact header grammar.
 
-use crate::config::ResolverConfig;
+use crate::clock::{Clock, SystemClock, unix_secs};
+use crate::config::{ReservedPort, ResolverConfig};
+use crate::dns::RecordType;
+use crate::drift::DriftReport;
 use crate::error::{ResolverError, Result};
-use crate::util::is_process_alive;
+use crate::header::{FORMAT_VERSION, ManagedHeader, foreign_marker};
+use crate::liveness::{DefaultLiveness, Liveness};
+use crate::mobileconfig::MobileConfig;
+use crate::preflight::{Preflight, ProbeReport};
+use crate::status::DomainStatus;
+use crate::util::{current_uid, hostname, try_lock_file, write_atomic};
+use crate::watch::Changes;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
 
 /// Default macOS resolver directory.
 const DEFAULT_RESOLVER_DIR: &str = "/etc/resolver";
@@ -25,7 +39,42 @@ const DEFAULT_RESOLVER_DIR: &str = "/etc/resolver";
 /// If the process exits without calling [`unregister`](Self::unregister),
 /// the file persists. On next startup, call
 /// [`cleanup_orphaned`](Self::cleanup_orphaned) to remove files whose
-/// creating PID is no longer running.
+/// creating PID is no longer running. How an owner is judged alive can be
+/// replaced with [`liveness`](Self::liveness).
+///
+/// # Leases
+///
+/// [`register_with_lease`](Self::register_with_lease) additionally records an
+/// expiry time. Once it passes, [`cleanup_orphaned`](Self::cleanup_orphaned)
+/// removes the file even if the creating process is still alive, unless the
+/// lease was extended with [`renew`](Self::renew).
+///
+/// # Ownership handoff
+///
+/// When a launcher registers domains and then hands off to a long-running
+/// process with a different PID, call [`transfer`](Self::transfer) or
+/// [`transfer_all_from`](Self::transfer_all_from) before the launcher exits
+/// so the files are not cleaned up as orphans.
+///
+/// Files are always rewritten atomically (write to a temporary file, then
+/// rename), so readers never see a partially written file.
+///
+/// # Metadata
+///
+/// Besides ownership data, every header records when, by which user, on
+/// which host and by which crate version the file was written, plus an
+/// optional [`app_version`](Self::app_version) and caller-provided
+/// [`tag`](Self::tag)s. Read it back with [`entry`](Self::entry) or
+/// [`entries`](Self::entries).
+///
+/// # Lock-based ownership
+///
+/// PIDs can be reused and differ across PID namespaces. With
+/// [`lock_dir`](Self::lock_dir) set, each registration also takes an `flock`
+/// on `<lock_dir>/<domain>.lock` and holds it until
+/// [`unregister`](Self::unregister) or until this `FileResolver` is dropped.
+/// [`cleanup_orphaned`](Self::cleanup_orphaned) then removes such a file only
+/// if its lock is free, regardless of the recorded PID.
 ///
 /// # Permissions
 ///
@@ -45,6 +94,56 @@ pub struct FileResolver {
     resolver_dir: PathBuf,
     /// Marker prefix, e.g. `"myapp"`.
     marker: String,
+    /// Time source for lease expiry.
+    clock: Arc<dyn Clock>,
+    /// Decides whether the owner of a managed file is still alive.
+    liveness: Arc<dyn Liveness>,
+    /// Directory for per-registration lock files; `None` uses PID liveness.
+    lock_dir: Option<PathBuf>,
+    /// Locks held for registrations made by this instance, keyed by domain.
+    held_locks: Mutex<HashMap<String, File>>,
+    /// Application version recorded in new headers.
+    app_version: Option<String>,
+    /// Tags recorded in new headers.
+    tags: BTreeMap<String, String>,
+    /// Reachability check run before process-bound registrations.
+    preflight: Option<Preflight>,
+}
+
+/// What [`FileResolver::adopt`] or [`FileResolver::release`] changed in a
+/// resolver file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChangeReport {
+    /// Domain of the changed file.
+    pub domain: String,
+    /// Header line that was added.
+    pub header_added: Option<String>,
+    /// Header line that was removed.
+    pub header_removed: Option<String>,
+    /// Directive lines that were removed.
+    pub lines_removed: Vec<String>,
+    /// Directive lines that were added.
+    pub lines_added: Vec<String>,
+}
+
+impl ChangeReport {
+    /// Returns `true` if nothing was changed.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.header_added.is_none()
+            && self.header_removed.is_none()
+            && self.lines_removed.is_empty()
+            && self.lines_added.is_empty()
+    }
+}
+
+/// A managed resolver file and the metadata in its header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManagedEntry {
+    /// Domain (the file name under the resolver directory).
+    pub domain: String,
+    /// Parsed header.
+    pub header: ManagedHeader,
 }
 
 impl FileResolver {
@@ -60,10 +159,7 @@ impl FileResolver {
         let env_key = format!("{}_RESOLVER_DIR", to_env_prefix(prefix));
         let resolver_dir = std::env::var(env_key)
             .map_or_else(|_| PathBuf::from(DEFAULT_RESOLVER_DIR), PathBuf::from);
-        Self {
-            resolver_dir,
-            marker: format!("# managed by {prefix}"),
-        }
+        Self::from_parts(resolver_dir, format!("# managed by {prefix}"))
     }
 
     /// Creates a resolver with an exact marker string (written as-is).
@@ -71,9 +167,20 @@ impl FileResolver {
     /// Use this when you need full control over the marker comment.
     #[must_use]
     pub fn with_marker(marker: impl Into<String>) -> Self {
+        Self::from_parts(PathBuf::from(DEFAULT_RESOLVER_DIR), marker.into())
+    }
+
+    fn from_parts(resolver_dir: PathBuf, marker: String) -> Self {
         Self {
-            resolver_dir: PathBuf::from(DEFAULT_RESOLVER_DIR),
-            marker: marker.into(),
+            resolver_dir,
+            marker,
+            clock: Arc::new(SystemClock),
+            liveness: Arc::new(DefaultLiveness),
+            lock_dir: None,
+            held_locks: Mutex::new(HashMap::new()),
+            app_version: None,
+            tags: BTreeMap::new(),
+            preflight: None,
         }
     }
 
@@ -84,6 +191,63 @@ impl FileResolver {
         self
     }
 
+    /// Overrides the clock used to stamp and check lease expiry (useful for testing).
+    #[must_use]
+    pub fn clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Overrides how [`cleanup_orphaned`](Self::cleanup_orphaned) decides
+    /// whether a file's owner is still alive.
+    ///
+    /// Defaults to [`DefaultLiveness`], which checks the recorded lock file
+    /// or PID.
+    #[must_use]
+    pub fn liveness(mut self, liveness: impl Liveness + 'static) -> Self {
+        self.liveness = Arc::new(liveness);
+        self
+    }
+
+    /// Records the application's version in new headers.
+    #[must_use]
+    pub fn app_version(mut self, version: impl Into<String>) -> Self {
+        self.app_version = Some(version.into());
+        self
+    }
+
+    /// Adds a key/value tag recorded in new headers.
+    #[must_use]
+    pub fn tag(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.tags.insert(key.into(), value.into());
+        self
+    }
+
+    /// Checks that the nameserver answers before each
+    /// [`register`](Self::register) or
+    /// [`register_with_lease`](Self::register_with_lease), failing with
+    /// [`ResolverError::Unreachable`] instead of writing a file that would
+    /// make lookups hang.
+    ///
+    /// Permanent entries are not checked, since they are usually installed
+    /// before the server first runs.
+    #[must_use]
+    pub const fn preflight(mut self, preflight: Preflight) -> Self {
+        self.preflight = Some(preflight);
+        self
+    }
+
+    /// Proves ownership of new registrations with lock files in `lock_dir`.
+    ///
+    /// The directory must not be the resolver directory itself, since macOS
+    /// would treat every lock file as a resolver entry. A location under
+    /// `/var/run` is a good fit for a root daemon.
+    #[must_use]
+    pub fn lock_dir(mut self, lock_dir: impl Into<PathBuf>) -> Self {
+        self.lock_dir = Some(lock_dir.into());
+        self
+    }
+
     /// Returns the resolver directory path.
     #[must_use]
     pub fn resolver_dir(&self) -> &Path {
@@ -96,30 +260,51 @@ impl FileResolver {
         &self.marker
     }
 
+    /// Watches the resolver directory for added, removed and modified files.
+    ///
+    /// Reports every file, managed or not, classified by owner. See
+    /// [`Changes`] for details.
+    #[must_use]
+    pub fn changes(&self) -> Changes {
+        Changes::new(&self.resolver_dir, &self.marker)
+    }
+
+    /// Starts an encrypted DNS profile attributed to this instance's marker.
+    ///
+    /// See [`MobileConfig`] for domains that need DNS-over-HTTPS or
+    /// DNS-over-TLS, which `/etc/resolver` files cannot express.
+    #[must_use]
+    pub fn mobileconfig(&self) -> MobileConfig {
+        MobileConfig::new(&self.marker)
+    }
+
     /// Writes `/etc/resolver/<domain>` with the given configuration.
     ///
-    /// The file contains a marker with the current PID for orphan detection.
+    /// The file contains a marker with the current PID (and lock file, if
+    /// [`lock_dir`](Self::lock_dir) is set) for orphan detection.
     /// Calling this again for the same domain overwrites the previous file.
     ///
     /// # Errors
     ///
     /// Returns [`ResolverError::Io`] if the directory cannot be created or
-    /// the file cannot be written.
+    /// the file cannot be written, [`ResolverError::Locked`] if another
+    /// live owner holds the domain's lock, or [`ResolverError::Unreachable`]
+    /// if a [`preflight`](Self::preflight) check is set and fails.
     pub fn register(&self, config: &ResolverConfig) -> Result<()> {
-        if !self.resolver_dir.exists() {
-            std::fs::create_dir_all(&self.resolver_dir)?;
-        }
+        self.check_reachable(config)?;
+        self.register_unchecked(config)
+    }
 
-        let path = self.resolver_path(&config.domain);
-        let pid = std::process::id();
-        let content = format!(
-            "{marker} (pid={pid})\nnameserver {ns}\nport {port}\nsearch_order {order}\n",
-            marker = self.marker,
-            ns = config.nameserver,
-            port = config.port,
-            order = config.search_order,
-        );
-        std::fs::write(&path, content)?;
+    fn register_unchecked(&self, config: &ResolverConfig) -> Result<()> {
+        let was_locked = self.holds_lock(&config.domain);
+        let header = ManagedHeader {
+            pid: Some(std::process::id()),
+            lock: self.acquire_lock(&config.domain)?,
+            ..self.new_header()
+        };
+        let path = self
+            .write_entry(config, &header)
+            .inspect_err(|_| self.abandon_lock(&config.domain, was_locked))?;
 
         tracing::info!(
             domain = %config.domain,
@@ -130,6 +315,26 @@ impl FileResolver {
         Ok(())
     }
 
+    /// Reserves a free port on `nameserver` and [registers](Self::register)
+    /// `domain` with it, returning the config and the bound sockets to serve
+    /// on. See [`ResolverConfig::with_free_port`].
+    ///
+    /// No [`preflight`](Self::preflight) check is made, since nothing serves
+    /// the new port yet.
+    ///
+    /// # Errors
+    ///
+    /// As [`ResolverConfig::with_free_port`] and [`register`](Self::register).
+    pub fn register_on_free_port(
+        &self,
+        domain: &str,
+        nameserver: &str,
+    ) -> Result<(ResolverConfig, ReservedPort)> {
+        let (config, reserved) = ResolverConfig::with_free_port(domain, nameserver)?;
+        self.register_unchecked(&config)?;
+        Ok((config, reserved))
+    }
+
     /// Writes `/etc/resolver/<domain>` as a permanent (static) entry.
     ///
     /// Unlike [`register`](Self::register), this does **not** embed a PID in
@@ -144,29 +349,109 @@ impl FileResolver {
     /// Returns [`ResolverError::Io`] if the directory cannot be created or
     /// the file cannot be written.
     pub fn register_permanent(&self, config: &ResolverConfig) -> Result<()> {
-        if !self.resolver_dir.exists() {
-            std::fs::create_dir_all(&self.resolver_dir)?;
+        let path = self.write_entry(config, &self.new_header())?;
+        self.release_lock(&config.domain);
+
+        tracing::info!(
+            domain = %config.domain,
+            port = config.port,
+            path = %path.display(),
+            "Registered permanent macOS DNS resolver"
+        );
+        Ok(())
+    }
+
+    /// Registers `config` on behalf of a helper client, recording `uid` as
+    /// the creator when known.
+    ///
+    /// With `pid` unset the entry is permanent. A `pid` other than the
+    /// current process's is recorded without a lock, so liveness follows
+    /// that process; the current PID registers as [`register`](Self::register)
+    /// does.
+    #[cfg(feature = "helper")]
+    pub(crate) fn register_on_behalf(
+        &self,
+        config: &ResolverConfig,
+        uid: Option<u32>,
+        pid: Option<u32>,
+    ) -> Result<()> {
+        let mut header = self.new_header();
+        if uid.is_some() {
+            header.uid = uid;
+        }
+        header.pid = pid;
+        let was_locked = self.holds_lock(&config.domain);
+        if pid == Some(std::process::id()) {
+            header.lock = self.acquire_lock(&config.domain)?;
+        }
+        let path = self
+            .write_entry(config, &header)
+            .inspect_err(|_| self.abandon_lock(&config.domain, was_locked))?;
+        if header.lock.is_none() {
+            self.release_lock(&config.domain);
         }
 
-        let path = self.resolver_path(&config.domain);
-        let content = format!(
-            "{marker}\nnameserver {ns}\nport {port}\nsearch_order {order}\n",
-            marker = self.marker,
-            ns = config.nameserver,
+        tracing::info!(
+            domain = %config.domain,
             port = config.port,
-            order = config.search_order,
+            uid = ?header.uid,
+            pid = ?header.pid,
+            path = %path.display(),
+            "Registered macOS DNS resolver on behalf of client"
         );
-        std::fs::write(&path, content)?;
+        Ok(())
+    }
+
+    /// Like [`register`](Self::register), but the marker also records an
+    /// expiry time `ttl` from now. [`cleanup_orphaned`](Self::cleanup_orphaned)
+    /// removes the file once the lease has expired, even if this process is
+    /// still alive. Extend the lease with [`renew`](Self::renew).
+    ///
+    /// # Errors
+    ///
+    /// As [`register`](Self::register).
+    pub fn register_with_lease(&self, config: &ResolverConfig, ttl: Duration) -> Result<()> {
+        self.check_reachable(config)?;
+        let was_locked = self.holds_lock(&config.domain);
+        let header = ManagedHeader {
+            pid: Some(std::process::id()),
+            expires: Some(self.expiry_after(ttl)),
+            lock: self.acquire_lock(&config.domain)?,
+            ..self.new_header()
+        };
+        let path = self
+            .write_entry(config, &header)
+            .inspect_err(|_| self.abandon_lock(&config.domain, was_locked))?;
 
         tracing::info!(
             domain = %config.domain,
             port = config.port,
+            ttl_secs = ttl.as_secs(),
             path = %path.display(),
-            "Registered permanent macOS DNS resolver"
+            "Registered leased macOS DNS resolver"
         );
         Ok(())
     }
 
+    /// Extends the lease of a managed resolver file to `ttl` from now.
+    ///
+    /// The recorded PID and all directives are kept. Renewing a file that has
+    /// no lease yet (including a permanent one) gives it one.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ResolverError::NotFound`] if the file does not exist,
+    /// [`ResolverError::NotManaged`] if it belongs to another tool, or
+    /// [`ResolverError::Io`] on I/O failure.
+    pub fn renew(&self, domain: &str, ttl: Duration) -> Result<()> {
+        let (path, content, mut header) = self.read_managed(domain)?;
+        header.expires = Some(self.expiry_after(ttl));
+        write_atomic(&path, with_header(&header.render(&self.marker), &content))?;
+
+        tracing::debug!(domain = %domain, ttl_secs = ttl.as_secs(), "Renewed resolver lease");
+        Ok(())
+    }
+
     /// Removes `/etc/resolver/<domain>`.
     ///
     /// Only removes files that contain the ownership marker. Files created
@@ -199,10 +484,211 @@ impl FileResolver {
         }
 
         std::fs::remove_file(&path)?;
+        self.release_lock(domain);
         tracing::info!(domain = %domain, "Unregistered macOS DNS resolver");
         Ok(())
     }
 
+    /// Turns a PID-bound managed file into a permanent one.
+    ///
+    /// Drops the recorded PID, lock file and lease so that
+    /// [`cleanup_orphaned`](Self::cleanup_orphaned) never removes the file,
+    /// exactly as if it had been written by
+    /// [`register_permanent`](Self::register_permanent). Directives and the
+    /// remaining metadata are left unchanged. Promoting a permanent file is a
+    /// no-op.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ResolverError::NotFound`] if the file does not exist,
+    /// [`ResolverError::NotManaged`] if it is not managed by this instance,
+    /// or [`ResolverError::Io`] on I/O failure.
+    pub fn promote(&self, domain: &str) -> Result<()> {
+        let (path, content, mut header) = self.read_managed(domain)?;
+        if header.pid.is_none() && header.lock.is_none() && header.expires.is_none() {
+            return Ok(());
+        }
+
+        header.pid = None;
+        header.lock = None;
+        header.expires = None;
+        write_atomic(&path, with_header(&header.render(&self.marker), &content))?;
+        self.release_lock(domain);
+
+        tracing::info!(domain = %domain, "Promoted resolver file to permanent");
+        Ok(())
+    }
+
+    /// Turns a permanent managed file into one bound to the current process.
+    ///
+    /// Records the current PID (and takes a lock if [`lock_dir`](Self::lock_dir)
+    /// is set), so the file is removed by
+    /// [`cleanup_orphaned`](Self::cleanup_orphaned) once this process is gone,
+    /// exactly as if it had been written by [`register`](Self::register).
+    /// Directives and metadata are left unchanged. Demoting a file that is
+    /// already bound to an owner is a no-op.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ResolverError::NotFound`] if the file does not exist,
+    /// [`ResolverError::NotManaged`] if it is not managed by this instance,
+    /// [`ResolverError::Locked`] if another live owner holds the domain's
+    /// lock, or [`ResolverError::Io`] on I/O failure.
+    pub fn demote(&self, domain: &str) -> Result<()> {
+        let (path, content, mut header) = self.read_managed(domain)?;
+        if header.pid.is_some() || header.lock.is_some() {
+            return Ok(());
+        }
+
+        let was_locked = self.holds_lock(domain);
+        header.pid = Some(std::process::id());
+        header.lock = self.acquire_lock(domain)?;
+        write_atomic(&path, with_header(&header.render(&self.marker), &content))
+            .inspect_err(|_| self.abandon_lock(domain, was_locked))?;
+
+        tracing::info!(domain = %domain, "Demoted resolver file to process-bound");
+        Ok(())
+    }
+
+    /// Hands a managed file owned by the current process over to `new_pid`.
+    ///
+    /// The recorded PID is atomically rewritten; directives and metadata are
+    /// unchanged. Because an `flock` cannot be handed to another process, a
+    /// lock-based registration becomes PID-bound to `new_pid` and the lock
+    /// held by this instance is released.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ResolverError::NotFound`] if the file does not exist,
+    /// [`ResolverError::NotManaged`] if it is not managed by this instance,
+    /// [`ResolverError::NotOwner`] if it is not bound to the current process,
+    /// or [`ResolverError::Io`] on I/O failure.
+    pub fn transfer(&self, domain: &str, new_pid: u32) -> Result<()> {
+        let (path, content, header) = self.read_managed(domain)?;
+        let pid = std::process::id();
+        if header.pid != Some(pid) {
+            return Err(ResolverError::NotOwner {
+                domain: domain.to_string(),
+                pid: header.pid,
+            });
+        }
+        self.rewrite_owner(domain, &path, &content, header, new_pid)
+    }
+
+    /// Hands every managed file bound to `old_pid` over to `new_pid`.
+    ///
+    /// Only files carrying this instance's marker and recorded PID `old_pid`
+    /// are touched; each is rewritten as in [`transfer`](Self::transfer).
+    /// Returns the transferred domains.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ResolverError::Io`] if the directory cannot be read or a
+    /// file cannot be rewritten. Files rewritten before the failure keep
+    /// their new owner.
+    pub fn transfer_all_from(&self, old_pid: u32, new_pid: u32) -> Result<Vec<String>> {
+        let mut transferred = Vec::new();
+        for entry in self.entries()? {
+            if entry.header.pid != Some(old_pid) {
+                continue;
+            }
+            let path = self.resolver_path(&entry.domain);
+            let content = std::fs::read_to_string(&path)?;
+            // Re-parse under the same read to avoid acting on a stale header.
+            let Some(header) = ManagedHeader::parse(&self.marker, &content) else {
+                continue;
+            };
+            if header.pid != Some(old_pid) {
+                continue;
+            }
+            self.rewrite_owner(&entry.domain, &path, &content, header, new_pid)?;
+            transferred.push(entry.domain);
+        }
+        Ok(transferred)
+    }
+
+    /// Takes ownership of an existing, unmanaged `/etc/resolver/<domain>`.
+    ///
+    /// Adds this instance's marker as a permanent header, so the file is
+    /// from then on listed, and can be unregistered, like any other managed
+    /// file. With `replacement = None` the file's existing directives are
+    /// preserved; otherwise they are replaced by those of `replacement`.
+    ///
+    /// Adopting a file that is already managed by this instance changes
+    /// nothing and returns an empty report.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ResolverError::NotFound`] if the file does not exist,
+    /// [`ResolverError::OwnedByOther`] if it carries another instance's
+    /// marker, or [`ResolverError::Io`] on I/O failure.
+    pub fn adopt(
+        &self,
+        domain: &str,
+        replacement: Option<&ResolverConfig>,
+    ) -> Result<ChangeReport> {
+        let (path, content) = self.read_existing(domain)?;
+        let mut report = ChangeReport {
+            domain: domain.to_string(),
+            ..ChangeReport::default()
+        };
+        if ManagedHeader::parse(&self.marker, &content).is_some() {
+            return Ok(report);
+        }
+        if let Some(marker) = foreign_marker(&self.marker, &content) {
+            return Err(ResolverError::OwnedByOther {
+                domain: domain.to_string(),
+                marker: marker.to_string(),
+            });
+        }
+
+        let header = self.new_header().render(&self.marker);
+        let body = match replacement {
+            Some(config) => {
+                let body = render_directives(config);
+                report.lines_removed = lines_missing_from(&content, &body);
+                report.lines_added = lines_missing_from(&body, &content);
+                body
+            }
+            None => content,
+        };
+        write_atomic(&path, format!("{header}\n{body}"))?;
+        report.header_added = Some(header);
+
+        tracing::info!(
+            domain = %domain,
+            replaced = replacement.is_some(),
+            "Adopted existing resolver file"
+        );
+        Ok(report)
+    }
+
+    /// Gives up ownership of a managed `/etc/resolver/<domain>` without
+    /// removing it.
+    ///
+    /// Strips this instance's header line and leaves the directives in place,
+    /// so the file keeps working but is no longer listed, unregistered or
+    /// cleaned up by this crate.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ResolverError::NotFound`] if the file does not exist,
+    /// [`ResolverError::NotManaged`] if it is not managed by this instance,
+    /// or [`ResolverError::Io`] on I/O failure.
+    pub fn release(&self, domain: &str) -> Result<ChangeReport> {
+        let (path, content, _) = self.read_managed(domain)?;
+        let (header, body) = content.split_once('\n').unwrap_or((&content, ""));
+        write_atomic(&path, body)?;
+        self.release_lock(domain);
+
+        tracing::info!(domain = %domain, "Released resolver file");
+        Ok(ChangeReport {
+            domain: domain.to_string(),
+            header_removed: Some(header.to_string()),
+            ..ChangeReport::default()
+        })
+    }
+
     /// Lists all domains with a managed resolver file.
     ///
     /// Returns an empty vec if the directory does not exist.
@@ -218,7 +704,7 @@ impl FileResolver {
         let mut domains = Vec::new();
         for entry in std::fs::read_dir(&self.resolver_dir)? {
             let path = entry?.path();
-            if path.is_file() && self.is_managed(&path) {
+            if !is_hidden(&path) && path.is_file() && self.is_managed(&path) {
                 if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
                     domains.push(name.to_string());
                 }
@@ -227,104 +713,547 @@ impl FileResolver {
         Ok(domains)
     }
 
-    /// Returns `true` if `domain` has a managed resolver file on disk.
-    #[must_use]
-    pub fn is_registered(&self, domain: &str) -> bool {
-        let path = self.resolver_path(domain);
-        path.exists() && self.is_managed(&path)
-    }
-
-    /// Removes resolver files whose creating PID is no longer running.
+    /// Lists all managed resolver files along with their header metadata.
     ///
-    /// Returns the number of files removed. Non-managed files and files
-    /// belonging to still-alive processes are left untouched.
-    /// Permanent files (no PID) are also left untouched.
+    /// Returns an empty vec if the directory does not exist.
     ///
     /// # Errors
     ///
     /// Returns [`ResolverError::Io`] if the directory cannot be read.
-    pub fn cleanup_orphaned(&self) -> Result<usize> {
+    pub fn entries(&self) -> Result<Vec<ManagedEntry>> {
         if !self.resolver_dir.exists() {
-            return Ok(0);
+            return Ok(Vec::new());
         }
 
-        let mut removed = 0;
+        let mut entries = Vec::new();
         for entry in std::fs::read_dir(&self.resolver_dir)? {
             let path = entry?.path();
-            if !path.is_file() || !self.is_managed(&path) {
+            if is_hidden(&path) || !path.is_file() {
                 continue;
             }
-
-            if let Some(pid) = self.extract_pid(&path) {
-                if !is_process_alive(pid) {
-                    let domain = path
-                        .file_name()
-                        .and_then(|n| n.to_str())
-                        .unwrap_or("unknown");
-                    tracing::info!(
-                        domain = %domain,
-                        pid = pid,
-                        "Removing orphaned resolver file (process dead)"
-                    );
-                    match std::fs::remove_file(&path) {
-                        Ok(()) => removed += 1,
-                        Err(e) => tracing::warn!(
-                            domain = %domain,
-                            error = %e,
-                            "Failed to remove orphaned resolver file"
-                        ),
-                    }
-                }
+            let Some(header) = self.read_header(&path) else {
+                continue;
+            };
+            if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
+                entries.push(ManagedEntry {
+                    domain: name.to_string(),
+                    header,
+                });
             }
         }
-        Ok(removed)
+        Ok(entries)
     }
 
-    fn resolver_path(&self, domain: &str) -> PathBuf {
-        self.resolver_dir.join(domain)
+    /// Returns the managed file for `domain` with its header metadata, or
+    /// `None` if there is no managed file for it.
+    #[must_use]
+    pub fn entry(&self, domain: &str) -> Option<ManagedEntry> {
+        let header = self.read_header(&self.resolver_path(domain))?;
+        Some(ManagedEntry {
+            domain: domain.to_string(),
+            header,
+        })
     }
 
-    /// Checks whether a file contains this instance's marker.
-    fn is_managed(&self, path: &Path) -> bool {
-        std::fs::read_to_string(path).is_ok_and(|c| c.contains(&self.marker))
+    /// Returns `true` if `domain` has a managed resolver file on disk.
+    #[must_use]
+    pub fn is_registered(&self, domain: &str) -> bool {
+        let path = self.resolver_path(domain);
+        path.exists() && self.is_managed(&path)
     }
 
-    /// Extracts the PID from `# managed by <app> (pid=<N>)`.
-    fn extract_pid(&self, path: &Path) -> Option<u32> {
-        let content = std::fs::read_to_string(path).ok()?;
-        for line in content.lines() {
-            if let Some(rest) = line.strip_prefix(self.marker.as_str()) {
-                let rest = rest.trim().strip_prefix("(pid=")?;
-                return rest.strip_suffix(')')?.parse().ok();
+    /// Reports the detailed state of `/etc/resolver/<domain>`.
+    ///
+    /// Unlike [`is_registered`](Self::is_registered), this distinguishes why
+    /// a domain is or is not usable: missing, owned by someone else, corrupt,
+    /// or managed with a dead owner.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ResolverError::Io`] if the file exists but cannot be read
+    /// (e.g. `PermissionDenied`).
+    pub fn status(&self, domain: &str) -> Result<DomainStatus> {
+        self.status_inner(domain, None)
+    }
+
+    /// Like [`status`](Self::status), but reports
+    /// [`DomainStatus::ContentMismatch`] if a managed file's directives differ
+    /// from `expected`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ResolverError::Io`] if the file exists but cannot be read.
+    pub fn status_against(&self, expected: &ResolverConfig) -> Result<DomainStatus> {
+        self.status_inner(&expected.domain, Some(expected))
+    }
+
+    fn status_inner(
+        &self,
+        domain: &str,
+        expected: Option<&ResolverConfig>,
+    ) -> Result<DomainStatus> {
+        let content = match std::fs::read_to_string(self.resolver_path(domain)) {
+            Ok(c) => c,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(DomainStatus::Missing);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::InvalidData => {
+                return Ok(DomainStatus::Corrupt {
+                    reason: "not valid UTF-8".into(),
+                });
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let Some(header) = ManagedHeader::parse(&self.marker, &content) else {
+            let first = content.lines().next().unwrap_or("");
+            if let Some(rest) = first.strip_prefix(self.marker.as_str()) {
+                if rest.starts_with([' ', '(']) {
+                    return Ok(DomainStatus::Corrupt {
+                        reason: format!("malformed header: {first}"),
+                    });
+                }
+            }
+            return Ok(foreign_marker(&self.marker, &content).map_or(
+                DomainStatus::Unmanaged,
+                |marker| DomainStatus::ForeignOwner {
+                    marker: marker.to_string(),
+                },
+            ));
+        };
+
+        let actual = match ResolverConfig::parse(domain, &content) {
+            Ok(config) => config,
+            Err(e) => {
+                return Ok(DomainStatus::Corrupt {
+                    reason: e.to_string(),
+                });
+            }
+        };
+        if let Some(expected) = expected {
+            if *expected != actual {
+                return Ok(DomainStatus::ContentMismatch {
+                    expected: expected.clone(),
+                    actual,
+                });
             }
         }
-        None
+
+        if header.pid.is_none() && header.lock.is_none() && header.expires.is_none() {
+            return Ok(DomainStatus::ManagedPermanent);
+        }
+        let alive = !header.is_expired(self.clock.now())
+            && (header.pid.is_none() && header.lock.is_none() || self.liveness.is_alive(&header));
+        Ok(DomainStatus::ManagedEphemeral {
+            pid: header.pid,
+            alive,
+        })
     }
-}
 
-/// Converts a prefix like `"my-app"` to an environment variable prefix `"MY_APP"`.
-///
-/// Uppercases and replaces `-` with `_`.
-#[must_use]
-pub fn to_env_prefix(prefix: &str) -> String {
-    prefix.to_uppercase().replace('-', "_")
-}
+    /// Compares every managed file against the `expected` configurations.
+    ///
+    /// Reports expected domains without a managed file, managed files that
+    /// are not expected, and field-by-field differences in `nameserver`,
+    /// `port` and `search_order`. Nothing is modified; re-register the
+    /// affected domains to repair drift.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ResolverError::Io`] if the directory or a file cannot be read.
+    pub fn verify(&self, expected: &[ResolverConfig]) -> Result<DriftReport> {
+        let mut report = DriftReport::default();
+        let mut managed = self.list()?;
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        for config in expected {
+            let Some(index) = managed.iter().position(|d| *d == config.domain) else {
+                report.missing.push(config.domain.clone());
+                continue;
+            };
+            managed.swap_remove(index);
 
-    fn test_config() -> ResolverConfig {
-        ResolverConfig::new("test.local", "127.0.0.1", 5553)
+            let content = std::fs::read_to_string(self.resolver_path(&config.domain))?;
+            match ResolverConfig::parse(&config.domain, &content) {
+                Ok(actual) => report.compare(config, &actual),
+                Err(_) => report.corrupt.push(config.domain.clone()),
+            }
+        }
+
+        managed.sort();
+        report.extra = managed;
+        Ok(report)
     }
 
-    #[test]
-    fn marker_is_derived_from_prefix() {
-        let resolver = FileResolver::new("myapp");
-        assert_eq!(resolver.marker(), "# managed by myapp");
+    /// Confirms that `domain` actually resolves: reads its managed file
+    /// back and looks up `name` at the nameserver and port it lists,
+    /// retrying over TCP if the UDP answer is truncated.
+    ///
+    /// Ask for the type the domain serves: a name with only AAAA or TXT
+    /// records answers an A query with no records, which does not count as
+    /// resolving. Uses the [`preflight`](Self::preflight) timeout if one is
+    /// set.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ResolverError::NotFound`] or [`ResolverError::NotManaged`]
+    /// if the domain has no managed file, [`ResolverError::InvalidConfig`] if
+    /// the file cannot be parsed or `name` is not in `domain`, or
+    /// [`ResolverError::Unreachable`] if the nameserver does not answer.
+    pub fn probe(&self, domain: &str, name: &str, rtype: RecordType) -> Result<ProbeReport> {
+        let (_, content, _) = self.read_managed(domain)?;
+        let config = ResolverConfig::parse(domain, &content)?;
+        self.preflight
+            .unwrap_or_default()
+            .probe(&config, name, rtype)
     }
 
-    #[test]
+    /// Removes resolver files whose owner is gone or whose lease has expired.
+    ///
+    /// Whether the owner is gone is decided by the configured
+    /// [`Liveness`] checker; by default, when the file's lock is free (for
+    /// lock-based registrations) or its creating PID is no longer running.
+    ///
+    /// Returns the number of files removed. Non-managed files and unexpired
+    /// files belonging to live owners are left untouched.
+    /// Permanent files (no PID, no lease) are also left untouched.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ResolverError::Io`] if the directory cannot be read.
+    pub fn cleanup_orphaned(&self) -> Result<usize> {
+        if !self.resolver_dir.exists() {
+            return Ok(0);
+        }
+
+        let mut removed = 0;
+        for entry in std::fs::read_dir(&self.resolver_dir)? {
+            let path = entry?.path();
+            if is_hidden(&path) || !path.is_file() {
+                continue;
+            }
+            let Some(header) = self.read_header(&path) else {
+                continue;
+            };
+
+            let permanent = header.pid.is_none() && header.lock.is_none();
+            let reason = if header.is_expired(self.clock.now()) {
+                "lease expired"
+            } else if !permanent && !self.liveness.is_alive(&header) {
+                "owner gone"
+            } else {
+                continue;
+            };
+
+            let domain = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or("unknown");
+            tracing::info!(
+                domain = %domain,
+                pid = ?header.pid,
+                reason,
+                "Removing orphaned resolver file"
+            );
+            match std::fs::remove_file(&path) {
+                Ok(()) => {
+                    removed += 1;
+                    if let Some(lock) = &header.lock {
+                        let _ = std::fs::remove_file(lock);
+                    }
+                }
+                Err(e) => tracing::warn!(
+                    domain = %domain,
+                    error = %e,
+                    "Failed to remove orphaned resolver file"
+                ),
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Rewrites managed files written in an older on-disk format to the
+    /// current [`FORMAT_VERSION`].
+    ///
+    /// Only the header line is rewritten: ownership (PID, permanence) is kept,
+    /// the file's modification time becomes its `created` timestamp, and all
+    /// directives are left byte-for-byte unchanged, so resolution behavior is
+    /// not affected. Files already in the current (or a newer) format are
+    /// skipped. Returns the number of files migrated.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ResolverError::Io`] if the directory cannot be read.
+    pub fn migrate(&self) -> Result<usize> {
+        if !self.resolver_dir.exists() {
+            return Ok(0);
+        }
+
+        let mut migrated = 0;
+        for entry in std::fs::read_dir(&self.resolver_dir)? {
+            let path = entry?.path();
+            if is_hidden(&path) || !path.is_file() {
+                continue;
+            }
+            let Ok(content) = std::fs::read_to_string(&path) else {
+                continue;
+            };
+            let Some(mut header) = ManagedHeader::parse(&self.marker, &content) else {
+                continue;
+            };
+            if !header.is_outdated() {
+                continue;
+            }
+
+            let from = header.format;
+            if header.created.is_none() {
+                header.created = std::fs::metadata(&path)
+                    .and_then(|m| m.modified())
+                    .ok()
+                    .map(unix_secs);
+            }
+            let Some(updated) = header.replace_in(&self.marker, &content) else {
+                continue;
+            };
+
+            let domain = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .unwrap_or("unknown");
+            match write_atomic(&path, updated) {
+                Ok(()) => {
+                    migrated += 1;
+                    tracing::info!(
+                        domain = %domain,
+                        from,
+                        to = FORMAT_VERSION,
+                        "Migrated resolver file format"
+                    );
+                }
+                Err(e) => tracing::warn!(
+                    domain = %domain,
+                    error = %e,
+                    "Failed to migrate resolver file"
+                ),
+            }
+        }
+        Ok(migrated)
+    }
+
+    fn resolver_path(&self, domain: &str) -> PathBuf {
+        self.resolver_dir.join(domain)
+    }
+
+    /// Atomically rebinds a managed file to `new_pid`.
+    fn rewrite_owner(
+        &self,
+        domain: &str,
+        path: &Path,
+        content: &str,
+        mut header: ManagedHeader,
+        new_pid: u32,
+    ) -> Result<()> {
+        let old_pid = header.pid;
+        header.pid = Some(new_pid);
+        header.lock = None;
+        write_atomic(path, with_header(&header.render(&self.marker), content))?;
+        self.release_lock(domain);
+
+        tracing::info!(
+            domain = %domain,
+            old_pid = ?old_pid,
+            new_pid,
+            "Transferred resolver file ownership"
+        );
+        Ok(())
+    }
+
+    /// Reads `/etc/resolver/<domain>`, mapping a missing file to
+    /// [`ResolverError::NotFound`].
+    fn read_existing(&self, domain: &str) -> Result<(PathBuf, String)> {
+        let path = self.resolver_path(domain);
+        match std::fs::read_to_string(&path) {
+            Ok(content) => Ok((path, content)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(ResolverError::NotFound {
+                domain: domain.to_string(),
+            }),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Reads a file managed by this instance along with its parsed header.
+    fn read_managed(&self, domain: &str) -> Result<(PathBuf, String, ManagedHeader)> {
+        let (path, content) = self.read_existing(domain)?;
+        let header = ManagedHeader::parse(&self.marker, &content).ok_or_else(|| {
+            ResolverError::NotManaged {
+                domain: domain.to_string(),
+            }
+        })?;
+        Ok((path, content, header))
+    }
+
+    /// Runs the [`preflight`](Self::preflight) check, if one is set.
+    fn check_reachable(&self, config: &ResolverConfig) -> Result<()> {
+        if let Some(preflight) = &self.preflight {
+            preflight.ensure(config)?;
+        }
+        Ok(())
+    }
+
+    /// Returns a header stamped with this instance's metadata and no owner.
+    fn new_header(&self) -> ManagedHeader {
+        ManagedHeader {
+            created: Some(unix_secs(self.clock.now())),
+            uid: Some(current_uid()),
+            host: hostname(),
+            version: Some(env!("CARGO_PKG_VERSION").to_string()),
+            app_version: self.app_version.clone(),
+            tags: self.tags.clone(),
+            ..ManagedHeader::default()
+        }
+    }
+
+    /// Writes the header and directives for `config`, creating the directory if needed.
+    fn write_entry(&self, config: &ResolverConfig, header: &ManagedHeader) -> Result<PathBuf> {
+        if !self.resolver_dir.exists() {
+            std::fs::create_dir_all(&self.resolver_dir)?;
+        }
+
+        let path = self.resolver_path(&config.domain);
+        let content = format!(
+            "{}\n{}",
+            header.render(&self.marker),
+            render_directives(config)
+        );
+        write_atomic(&path, content)?;
+        Ok(path)
+    }
+
+    /// Takes (or reuses) the lock for `domain` if lock-based ownership is enabled.
+    ///
+    /// Returns the lock file path to record in the header.
+    fn acquire_lock(&self, domain: &str) -> Result<Option<PathBuf>> {
+        let Some(lock_dir) = &self.lock_dir else {
+            return Ok(None);
+        };
+        let path = lock_dir.join(format!("{domain}.lock"));
+        let mut held = self
+            .held_locks
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        if !held.contains_key(domain) {
+            std::fs::create_dir_all(lock_dir)?;
+            let file = try_lock_file(&path)?.ok_or_else(|| ResolverError::Locked {
+                domain: domain.to_string(),
+            })?;
+            held.insert(domain.to_string(), file);
+        }
+        drop(held);
+        Ok(Some(path))
+    }
+
+    /// Whether this instance holds the lock for `domain`.
+    fn holds_lock(&self, domain: &str) -> bool {
+        self.held_locks
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .contains_key(domain)
+    }
+
+    /// Releases a lock taken for a write that failed, so the domain is not
+    /// held without a file; a lock held before the write is kept.
+    fn abandon_lock(&self, domain: &str, was_locked: bool) {
+        if !was_locked {
+            self.release_lock(domain);
+        }
+    }
+
+    /// Drops the lock held for `domain`, if any, and removes its lock file.
+    fn release_lock(&self, domain: &str) {
+        let file = self
+            .held_locks
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(domain);
+        if let (Some(file), Some(lock_dir)) = (file, &self.lock_dir) {
+            let _ = std::fs::remove_file(lock_dir.join(format!("{domain}.lock")));
+            drop(file);
+        }
+    }
+
+    /// Returns the lease expiry timestamp `ttl` from now.
+    fn expiry_after(&self, ttl: Duration) -> u64 {
+        unix_secs(self.clock.now()).saturating_add(ttl.as_secs())
+    }
+
+    /// Checks whether a file starts with this instance's header.
+    fn is_managed(&self, path: &Path) -> bool {
+        self.read_header(path).is_some()
+    }
+
+    /// Parses the managed header of a file, or `None` if it is not managed.
+    fn read_header(&self, path: &Path) -> Option<ManagedHeader> {
+        let content = std::fs::read_to_string(path).ok()?;
+        ManagedHeader::parse(&self.marker, &content)
+    }
+}
+
+/// Renders the resolver directives for `config`.
+fn render_directives(config: &ResolverConfig) -> String {
+    format!(
+        "nameserver {ns}\nport {port}\nsearch_order {order}\n",
+        ns = config.nameserver,
+        port = config.port,
+        order = config.search_order,
+    )
+}
+
+/// Replaces the first line of `content` with `header`, keeping the rest.
+fn with_header(header: &str, content: &str) -> String {
+    let body = content.split_once('\n').map_or("", |(_, body)| body);
+    format!("{header}\n{body}")
+}
+
+/// Whether `path` is a dotfile, such as an in-progress
+/// [`write_atomic`] temporary file, which is never a resolver entry.
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|n| n.starts_with('.'))
+}
+
+/// Returns the non-empty lines of `a` that do not appear in `b`.
+fn lines_missing_from(a: &str, b: &str) -> Vec<String> {
+    a.lines()
+        .filter(|line| !line.trim().is_empty() && !b.lines().any(|other| other == *line))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Converts a prefix like `"my-app"` to an environment variable prefix `"MY_APP"`.
+///
+/// Uppercases and replaces `-` with `_`.
+#[must_use]
+pub fn to_env_prefix(prefix: &str) -> String {
+    prefix.to_uppercase().replace('-', "_")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+    use crate::drift::{DriftField, FieldDrift};
+    use std::time::UNIX_EPOCH;
+
+    fn test_config() -> ResolverConfig {
+        ResolverConfig::new("test.local", "127.0.0.1", 5553)
+    }
+
+    #[test]
+    fn marker_is_derived_from_prefix() {
+        let resolver = FileResolver::new("myapp");
+        assert_eq!(resolver.marker(), "# managed by myapp");
+    }
+
+    #[test]
     fn register_writes_file_with_pid() {
         let dir = tempfile::tempdir().unwrap();
         let resolver = FileResolver::new("testapp").dir(dir.path());
@@ -340,6 +1269,81 @@ mod tests {
         assert!(content.contains(&format!("pid={}", std::process::id())));
     }
 
+    #[test]
+    fn register_on_free_port_writes_reserved_port() {
+        let dir = tempfile::tempdir().unwrap();
+        let resolver = FileResolver::new("testapp").dir(dir.path());
+
+        let (config, reserved) = resolver
+            .register_on_free_port("test.local", "127.0.0.1")
+            .unwrap();
+        assert_eq!(config.port, reserved.port());
+        let content = std::fs::read_to_string(dir.path().join("test.local")).unwrap();
+        assert!(content.contains(&format!("port {}", reserved.port())));
+    }
+
+    #[test]
+    fn preflight_blocks_registration_when_nameserver_is_silent() {
+        let dir = tempfile::tempdir().unwrap();
+        let silent = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        let config = ResolverConfig::new(
+            "test.local",
+            "127.0.0.1",
+            silent.local_addr().unwrap().port(),
+        );
+        let resolver = FileResolver::new("testapp")
+            .dir(dir.path())
+            .preflight(Preflight::new().timeout(Duration::from_millis(200)));
+
+        let err = resolver.register(&config).unwrap_err();
+        assert!(matches!(err, ResolverError::Unreachable { .. }), "{err}");
+        assert!(!dir.path().join("test.local").exists());
+        // Permanent entries and freshly reserved ports are not checked.
+        resolver.register_permanent(&config).unwrap();
+        resolver
+            .register_on_free_port("other.local", "127.0.0.1")
+            .unwrap();
+    }
+
+    #[test]
+    fn probe_queries_the_registered_nameserver() {
+        let dir = tempfile::tempdir().unwrap();
+        let resolver = FileResolver::new("testapp")
+            .dir(dir.path())
+            .preflight(Preflight::new().timeout(Duration::from_millis(200)));
+        assert!(matches!(
+            resolver.probe("test.local", "test.local", RecordType::A),
+            Err(ResolverError::NotFound { .. })
+        ));
+
+        // A nameserver that answers every query with one AAAA record.
+        let (udp, _tcp) = crate::util::bind_udp_tcp("127.0.0.1:0".parse().unwrap()).unwrap();
+        let config =
+            ResolverConfig::new("test.local", "127.0.0.1", udp.local_addr().unwrap().port());
+        let server = std::thread::spawn(move || {
+            let mut buf = [0; 512];
+            let (len, peer) = udp.recv_from(&mut buf).unwrap();
+            let query = crate::dns::Message::decode(&buf[..len]).unwrap();
+            assert_eq!(query.questions[0].qtype, RecordType::Aaaa);
+            let mut reply = query.reply();
+            let name = query.questions[0].name.clone();
+            reply.answers.push(crate::dns::Record::new(
+                name,
+                60,
+                std::net::Ipv6Addr::LOCALHOST.into(),
+            ));
+            udp.send_to(&reply.encode().unwrap(), peer).unwrap();
+        });
+        resolver.register_permanent(&config).unwrap();
+
+        let report = resolver
+            .probe("test.local", "web.test.local", RecordType::Aaaa)
+            .unwrap();
+        server.join().unwrap();
+        assert!(report.is_resolving(), "{report}");
+        assert_eq!(report.addr, config.socket_addr().unwrap());
+    }
+
     #[test]
     fn register_and_unregister() {
         let dir = tempfile::tempdir().unwrap();
@@ -390,7 +1394,7 @@ mod tests {
     }
 
     #[test]
-    fn extract_pid_parses_marker() {
+    fn read_header_parses_pid() {
         let dir = tempfile::tempdir().unwrap();
         let resolver = FileResolver::new("testapp").dir(dir.path());
         let path = dir.path().join("test.local");
@@ -399,7 +1403,7 @@ mod tests {
             "# managed by testapp (pid=42)\nnameserver 127.0.0.1\nport 5553\n",
         )
         .unwrap();
-        assert_eq!(resolver.extract_pid(&path), Some(42));
+        assert_eq!(resolver.read_header(&path).unwrap().pid, Some(42));
     }
 
     #[test]
@@ -435,6 +1439,24 @@ mod tests {
         assert!(path.exists());
     }
 
+    #[test]
+    fn scans_skip_hidden_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let resolver = FileResolver::new("testapp").dir(dir.path());
+        // A leftover temporary file from an interrupted write.
+        std::fs::write(
+            dir.path().join(".test.local.tmp-999999999-0"),
+            "# managed by testapp (v=2, pid=999999999)\nnameserver 127.0.0.1\n",
+        )
+        .unwrap();
+
+        assert!(resolver.list().unwrap().is_empty());
+        assert!(resolver.entries().unwrap().is_empty());
+        assert_eq!(resolver.cleanup_orphaned().unwrap(), 0);
+        assert_eq!(resolver.migrate().unwrap(), 0);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
     #[test]
     fn list_empty_and_nonexistent() {
         let dir = tempfile::tempdir().unwrap();
@@ -513,4 +1535,653 @@ mod tests {
         assert!(content.contains("port 6000"));
         assert!(!content.contains("port 5553"));
     }
+
+    #[test]
+    fn cleanup_removes_expired_lease_of_live_process() {
+        let dir = tempfile::tempdir().unwrap();
+        let clock = Arc::new(ManualClock::new(UNIX_EPOCH + Duration::from_secs(1_000)));
+        let resolver = FileResolver::new("testapp")
+            .dir(dir.path())
+            .clock(Arc::clone(&clock));
+
+        resolver
+            .register_with_lease(&test_config(), Duration::from_secs(60))
+            .unwrap();
+        let content = std::fs::read_to_string(dir.path().join("test.local")).unwrap();
+        assert!(content.contains("expires=1060"));
+
+        clock.advance(Duration::from_secs(59));
+        assert_eq!(resolver.cleanup_orphaned().unwrap(), 0);
+
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(resolver.cleanup_orphaned().unwrap(), 1);
+        assert!(!resolver.is_registered("test.local"));
+    }
+
+    #[test]
+    fn renew_extends_lease_and_keeps_directives() {
+        let dir = tempfile::tempdir().unwrap();
+        let clock = Arc::new(ManualClock::new(UNIX_EPOCH + Duration::from_secs(1_000)));
+        let resolver = FileResolver::new("testapp")
+            .dir(dir.path())
+            .clock(Arc::clone(&clock));
+
+        resolver
+            .register_with_lease(&test_config(), Duration::from_secs(60))
+            .unwrap();
+        clock.advance(Duration::from_secs(50));
+        resolver
+            .renew("test.local", Duration::from_secs(60))
+            .unwrap();
+
+        clock.advance(Duration::from_secs(50));
+        assert_eq!(resolver.cleanup_orphaned().unwrap(), 0);
+
+        let content = std::fs::read_to_string(dir.path().join("test.local")).unwrap();
+        assert!(content.contains(&format!("pid={}, expires=1110", std::process::id())));
+        assert!(content.contains("nameserver 127.0.0.1\nport 5553\nsearch_order 1\n"));
+    }
+
+    #[test]
+    fn renew_rejects_missing_and_unmanaged_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let resolver = FileResolver::new("testapp").dir(dir.path());
+        std::fs::write(dir.path().join("other.local"), "nameserver 1.1.1.1\n").unwrap();
+
+        assert!(matches!(
+            resolver.renew("missing.local", Duration::from_secs(1)),
+            Err(ResolverError::NotFound { .. })
+        ));
+        assert!(matches!(
+            resolver.renew("other.local", Duration::from_secs(1)),
+            Err(ResolverError::NotManaged { .. })
+        ));
+    }
+
+    #[test]
+    fn lock_ownership_survives_until_owner_drops() {
+        let dir = tempfile::tempdir().unwrap();
+        let locks = tempfile::tempdir().unwrap();
+        let owner = FileResolver::new("testapp")
+            .dir(dir.path())
+            .lock_dir(locks.path());
+        owner.register(&test_config()).unwrap();
+
+        let content = std::fs::read_to_string(dir.path().join("test.local")).unwrap();
+        assert!(content.contains("lock="));
+
+        let janitor = FileResolver::new("testapp").dir(dir.path());
+        assert_eq!(janitor.cleanup_orphaned().unwrap(), 0);
+        assert!(janitor.is_registered("test.local"));
+
+        drop(owner);
+        assert_eq!(janitor.cleanup_orphaned().unwrap(), 1);
+        assert!(!janitor.is_registered("test.local"));
+        assert!(!locks.path().join("test.local.lock").exists());
+    }
+
+    #[test]
+    fn lock_ownership_ignores_recorded_pid() {
+        let dir = tempfile::tempdir().unwrap();
+        let locks = tempfile::tempdir().unwrap();
+        let lock = locks.path().join("test.local.lock");
+        std::fs::write(&lock, "").unwrap();
+        std::fs::write(
+            dir.path().join("test.local"),
+            format!(
+                "# managed by testapp (pid={}, lock={})\nnameserver 127.0.0.1\n",
+                std::process::id(),
+                lock.display()
+            ),
+        )
+        .unwrap();
+
+        let resolver = FileResolver::new("testapp").dir(dir.path());
+        assert_eq!(resolver.cleanup_orphaned().unwrap(), 1);
+    }
+
+    #[test]
+    fn register_refuses_domain_locked_by_other_owner() {
+        let dir = tempfile::tempdir().unwrap();
+        let locks = tempfile::tempdir().unwrap();
+        let first = FileResolver::new("testapp")
+            .dir(dir.path())
+            .lock_dir(locks.path());
+        let second = FileResolver::new("testapp")
+            .dir(dir.path())
+            .lock_dir(locks.path());
+
+        first.register(&test_config()).unwrap();
+        first.register(&test_config()).unwrap();
+        assert!(matches!(
+            second.register(&test_config()),
+            Err(ResolverError::Locked { .. })
+        ));
+
+        first.unregister("test.local").unwrap();
+        second.register(&test_config()).unwrap();
+    }
+
+    #[test]
+    fn failed_write_releases_new_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let locks = tempfile::tempdir().unwrap();
+        let first = FileResolver::new("testapp")
+            .dir(dir.path())
+            .lock_dir(locks.path());
+        let second = FileResolver::new("testapp")
+            .dir(dir.path())
+            .lock_dir(locks.path());
+        // A non-empty directory in the way makes the final rename fail.
+        let blocker = dir.path().join("test.local");
+        std::fs::create_dir_all(blocker.join("x")).unwrap();
+
+        assert!(first.register(&test_config()).is_err());
+        assert!(!crate::util::is_lock_held(
+            &locks.path().join("test.local.lock")
+        ));
+
+        std::fs::remove_dir_all(&blocker).unwrap();
+        second.register(&test_config()).unwrap();
+    }
+
+    #[test]
+    fn cleanup_uses_custom_liveness() {
+        let dir = tempfile::tempdir().unwrap();
+        let resolver = FileResolver::new("testapp")
+            .dir(dir.path())
+            .liveness(|h: &ManagedHeader| h.pid != Some(std::process::id()));
+
+        resolver.register(&test_config()).unwrap();
+        resolver
+            .register_permanent(&ResolverConfig::new("perm.local", "127.0.0.1", 5553))
+            .unwrap();
+
+        assert_eq!(resolver.cleanup_orphaned().unwrap(), 1);
+        assert!(!resolver.is_registered("test.local"));
+        assert!(resolver.is_registered("perm.local"));
+    }
+
+    #[test]
+    fn header_records_metadata() {
+        let dir = tempfile::tempdir().unwrap();
+        let clock = ManualClock::new(UNIX_EPOCH + Duration::from_secs(1_000));
+        let resolver = FileResolver::new("testapp")
+            .dir(dir.path())
+            .clock(clock)
+            .app_version("1.4.0")
+            .tag("vm", "dev box");
+
+        resolver.register(&test_config()).unwrap();
+        let header = resolver.entry("test.local").unwrap().header;
+
+        assert_eq!(header.pid, Some(std::process::id()));
+        assert_eq!(header.created, Some(1_000));
+        assert_eq!(header.uid, Some(current_uid()));
+        assert_eq!(header.host, hostname());
+        assert_eq!(header.version.as_deref(), Some(env!("CARGO_PKG_VERSION")));
+        assert_eq!(header.app_version.as_deref(), Some("1.4.0"));
+        assert_eq!(header.tags.get("vm").map(String::as_str), Some("dev box"));
+
+        let entries = resolver.entries().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].header, header);
+    }
+
+    #[test]
+    fn entry_reads_legacy_header() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("old.local"),
+            "# managed by testapp (pid=42)\nnameserver 127.0.0.1\nport 5553\n",
+        )
+        .unwrap();
+
+        let resolver = FileResolver::new("testapp").dir(dir.path());
+        let header = resolver.entry("old.local").unwrap().header;
+        assert_eq!(header.pid, Some(42));
+        assert_eq!(header.created, None);
+        assert!(resolver.entry("missing.local").is_none());
+    }
+
+    #[test]
+    fn ownership_ignores_markers_sharing_a_stem() {
+        let dir = tempfile::tempdir().unwrap();
+        let stem = dir.path().join("stem.local");
+        let comment = dir.path().join("comment.local");
+        std::fs::write(
+            &stem,
+            "# managed by application (pid=999999999)\nnameserver 127.0.0.1\n",
+        )
+        .unwrap();
+        std::fs::write(
+            &comment,
+            "nameserver 127.0.0.1 # managed by app (pid=999999999)\n",
+        )
+        .unwrap();
+
+        let resolver = FileResolver::new("app").dir(dir.path());
+        assert!(resolver.list().unwrap().is_empty());
+        assert!(!resolver.is_registered("stem.local"));
+        assert!(resolver.unregister("stem.local").is_err());
+        assert!(resolver.unregister("comment.local").is_err());
+        assert_eq!(resolver.cleanup_orphaned().unwrap(), 0);
+        assert!(stem.exists());
+        assert!(comment.exists());
+    }
+
+    #[test]
+    fn migrate_upgrades_legacy_headers_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let legacy_body = "nameserver 127.0.0.1\nport 5553\ntimeout 5\n";
+        std::fs::write(
+            dir.path().join("ephemeral.local"),
+            format!("# managed by testapp (pid=42)\n{legacy_body}"),
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("permanent.local"),
+            format!("# managed by testapp\n{legacy_body}"),
+        )
+        .unwrap();
+        std::fs::write(dir.path().join("other.local"), legacy_body).unwrap();
+
+        let resolver = FileResolver::new("testapp").dir(dir.path());
+        resolver.register(&test_config()).unwrap();
+        assert_eq!(resolver.migrate().unwrap(), 2);
+
+        let ephemeral = std::fs::read_to_string(dir.path().join("ephemeral.local")).unwrap();
+        let (header, body) = ephemeral.split_once('\n').unwrap();
+        assert!(header.starts_with("# managed by testapp (v=2, pid=42, created="));
+        assert_eq!(body, legacy_body);
+
+        let permanent = resolver.entry("permanent.local").unwrap().header;
+        assert_eq!(permanent.format, FORMAT_VERSION);
+        assert_eq!(permanent.pid, None);
+        assert!(permanent.created.is_some());
+
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("other.local")).unwrap(),
+            legacy_body
+        );
+        assert_eq!(resolver.migrate().unwrap(), 0);
+    }
+
+    #[test]
+    fn adopt_preserves_directives_by_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("hand.local");
+        std::fs::write(&path, "nameserver 10.0.0.1\nport 53\n").unwrap();
+
+        let resolver = FileResolver::new("testapp").dir(dir.path());
+        assert!(!resolver.is_registered("hand.local"));
+
+        let report = resolver.adopt("hand.local", None).unwrap();
+        assert!(
+            report
+                .header_added
+                .as_deref()
+                .unwrap()
+                .starts_with("# managed by testapp (v=2")
+        );
+        assert!(report.lines_removed.is_empty() && report.lines_added.is_empty());
+        assert!(resolver.is_registered("hand.local"));
+        assert_eq!(resolver.entry("hand.local").unwrap().header.pid, None);
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.ends_with("\nnameserver 10.0.0.1\nport 53\n"));
+
+        assert!(resolver.adopt("hand.local", None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn adopt_can_replace_directives() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("test.local"),
+            "nameserver 10.0.0.1\nport 53\n",
+        )
+        .unwrap();
+
+        let resolver = FileResolver::new("testapp").dir(dir.path());
+        let report = resolver.adopt("test.local", Some(&test_config())).unwrap();
+
+        assert_eq!(report.lines_removed, vec!["nameserver 10.0.0.1", "port 53"]);
+        assert_eq!(
+            report.lines_added,
+            vec!["nameserver 127.0.0.1", "port 5553", "search_order 1"]
+        );
+        let content = std::fs::read_to_string(dir.path().join("test.local")).unwrap();
+        assert!(content.ends_with("\nnameserver 127.0.0.1\nport 5553\nsearch_order 1\n"));
+    }
+
+    #[test]
+    fn adopt_refuses_missing_and_foreign_files() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("shared.local"),
+            "# managed by otherapp (pid=1)\nnameserver 127.0.0.1\n",
+        )
+        .unwrap();
+
+        let resolver = FileResolver::new("testapp").dir(dir.path());
+        assert!(matches!(
+            resolver.adopt("missing.local", None),
+            Err(ResolverError::NotFound { .. })
+        ));
+        assert!(matches!(
+            resolver.adopt("shared.local", None),
+            Err(ResolverError::OwnedByOther { marker, .. }) if marker == "# managed by otherapp"
+        ));
+    }
+
+    #[test]
+    fn release_strips_header_and_keeps_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let resolver = FileResolver::new("testapp").dir(dir.path());
+        resolver.register(&test_config()).unwrap();
+
+        let report = resolver.release("test.local").unwrap();
+        assert!(
+            report
+                .header_removed
+                .unwrap()
+                .starts_with("# managed by testapp")
+        );
+        assert!(!resolver.is_registered("test.local"));
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("test.local")).unwrap(),
+            "nameserver 127.0.0.1\nport 5553\nsearch_order 1\n"
+        );
+
+        assert!(matches!(
+            resolver.release("test.local"),
+            Err(ResolverError::NotManaged { .. })
+        ));
+    }
+
+    #[test]
+    fn promote_and_demote_keep_directives() {
+        let dir = tempfile::tempdir().unwrap();
+        let resolver = FileResolver::new("testapp")
+            .dir(dir.path())
+            .liveness(|_: &ManagedHeader| false);
+        let path = dir.path().join("test.local");
+        let body = |p: &Path| {
+            let content = std::fs::read_to_string(p).unwrap();
+            content.split_once('\n').unwrap().1.to_string()
+        };
+
+        resolver
+            .register_with_lease(&test_config(), Duration::from_secs(60))
+            .unwrap();
+        let before = body(&path);
+        let created = resolver.entry("test.local").unwrap().header.created;
+
+        resolver.promote("test.local").unwrap();
+        let header = resolver.entry("test.local").unwrap().header;
+        assert_eq!((header.pid, header.expires), (None, None));
+        assert_eq!(header.created, created);
+        assert_eq!(body(&path), before);
+        assert_eq!(resolver.cleanup_orphaned().unwrap(), 0);
+
+        resolver.demote("test.local").unwrap();
+        let header = resolver.entry("test.local").unwrap().header;
+        assert_eq!(header.pid, Some(std::process::id()));
+        assert_eq!(body(&path), before);
+        assert_eq!(resolver.cleanup_orphaned().unwrap(), 1);
+    }
+
+    #[test]
+    fn promote_releases_held_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        let locks = tempfile::tempdir().unwrap();
+        let resolver = FileResolver::new("testapp")
+            .dir(dir.path())
+            .lock_dir(locks.path());
+
+        resolver.register(&test_config()).unwrap();
+        resolver.promote("test.local").unwrap();
+        assert!(!locks.path().join("test.local.lock").exists());
+        assert_eq!(resolver.entry("test.local").unwrap().header.lock, None);
+
+        resolver.demote("test.local").unwrap();
+        assert!(crate::util::is_lock_held(
+            &locks.path().join("test.local.lock")
+        ));
+    }
+
+    #[test]
+    fn promote_rejects_unmanaged_file() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("other.local"), "nameserver 1.1.1.1\n").unwrap();
+        let resolver = FileResolver::new("testapp").dir(dir.path());
+
+        assert!(matches!(
+            resolver.promote("other.local"),
+            Err(ResolverError::NotManaged { .. })
+        ));
+        assert!(matches!(
+            resolver.demote("missing.local"),
+            Err(ResolverError::NotFound { .. })
+        ));
+    }
+
+    #[test]
+    fn transfer_rewrites_pid_of_own_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let resolver = FileResolver::new("testapp").dir(dir.path());
+        resolver.register(&test_config()).unwrap();
+        let before = std::fs::read_to_string(dir.path().join("test.local")).unwrap();
+
+        resolver.transfer("test.local", 4242).unwrap();
+        let after = std::fs::read_to_string(dir.path().join("test.local")).unwrap();
+        assert_eq!(resolver.entry("test.local").unwrap().header.pid, Some(4242));
+        assert_eq!(
+            before.split_once('\n').unwrap().1,
+            after.split_once('\n').unwrap().1
+        );
+
+        assert!(matches!(
+            resolver.transfer("test.local", 1),
+            Err(ResolverError::NotOwner {
+                pid: Some(4242),
+                ..
+            })
+        ));
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn transfer_all_from_only_touches_matching_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |name: &str, content: &str| {
+            std::fs::write(dir.path().join(name), content).unwrap();
+        };
+        write(
+            "a.local",
+            "# managed by testapp (pid=100)\nnameserver 127.0.0.1\n",
+        );
+        write(
+            "b.local",
+            "# managed by testapp (v=2, pid=100)\nnameserver 127.0.0.1\n",
+        );
+        write(
+            "c.local",
+            "# managed by testapp (pid=200)\nnameserver 127.0.0.1\n",
+        );
+        write(
+            "d.local",
+            "# managed by otherapp (pid=100)\nnameserver 127.0.0.1\n",
+        );
+
+        let resolver = FileResolver::new("testapp").dir(dir.path());
+        let mut moved = resolver.transfer_all_from(100, 300).unwrap();
+        moved.sort();
+        assert_eq!(moved, vec!["a.local", "b.local"]);
+
+        let pid = |d: &str| resolver.entry(d).unwrap().header.pid;
+        assert_eq!(pid("a.local"), Some(300));
+        assert_eq!(pid("b.local"), Some(300));
+        assert_eq!(pid("c.local"), Some(200));
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("d.local")).unwrap(),
+            "# managed by otherapp (pid=100)\nnameserver 127.0.0.1\n"
+        );
+    }
+
+    #[test]
+    fn transfer_converts_lock_ownership_to_pid() {
+        let dir = tempfile::tempdir().unwrap();
+        let locks = tempfile::tempdir().unwrap();
+        let resolver = FileResolver::new("testapp")
+            .dir(dir.path())
+            .lock_dir(locks.path());
+        resolver.register(&test_config()).unwrap();
+
+        resolver.transfer("test.local", 4242).unwrap();
+        let header = resolver.entry("test.local").unwrap().header;
+        assert_eq!((header.pid, header.lock), (Some(4242), None));
+        assert!(!locks.path().join("test.local.lock").exists());
+    }
+
+    #[test]
+    fn status_distinguishes_states() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |name: &str, content: &[u8]| {
+            std::fs::write(dir.path().join(name), content).unwrap();
+        };
+        write(
+            "foreign.local",
+            b"# managed by otherapp (pid=1)\nnameserver 127.0.0.1\n",
+        );
+        write("hand.local", b"nameserver 10.0.0.1\n");
+        write(
+            "badheader.local",
+            b"# managed by testapp (pid=abc)\nnameserver 127.0.0.1\n",
+        );
+        write("nobody.local", b"# managed by testapp (v=2)\nport 53\n");
+        write("binary.local", b"\xff\xfe");
+        write(
+            "dead.local",
+            b"# managed by testapp (pid=1)\nnameserver 127.0.0.1\n",
+        );
+
+        let resolver = FileResolver::new("testapp")
+            .dir(dir.path())
+            .liveness(|h: &ManagedHeader| h.pid == Some(std::process::id()));
+        resolver.register(&test_config()).unwrap();
+        resolver
+            .register_permanent(&ResolverConfig::new("perm.local", "127.0.0.1", 5553))
+            .unwrap();
+
+        let status = |d: &str| resolver.status(d).unwrap();
+        assert_eq!(status("missing.local"), DomainStatus::Missing);
+        assert_eq!(
+            status("test.local"),
+            DomainStatus::ManagedEphemeral {
+                pid: Some(std::process::id()),
+                alive: true
+            }
+        );
+        assert_eq!(
+            status("dead.local"),
+            DomainStatus::ManagedEphemeral {
+                pid: Some(1),
+                alive: false
+            }
+        );
+        assert_eq!(status("perm.local"), DomainStatus::ManagedPermanent);
+        assert_eq!(
+            status("foreign.local"),
+            DomainStatus::ForeignOwner {
+                marker: "# managed by otherapp".into()
+            }
+        );
+        assert_eq!(status("hand.local"), DomainStatus::Unmanaged);
+        assert!(matches!(
+            status("badheader.local"),
+            DomainStatus::Corrupt { .. }
+        ));
+        assert!(matches!(
+            status("nobody.local"),
+            DomainStatus::Corrupt { .. }
+        ));
+        assert!(matches!(
+            status("binary.local"),
+            DomainStatus::Corrupt { .. }
+        ));
+    }
+
+    #[test]
+    fn status_against_reports_content_mismatch() {
+        let dir = tempfile::tempdir().unwrap();
+        let resolver = FileResolver::new("testapp").dir(dir.path());
+        resolver.register_permanent(&test_config()).unwrap();
+
+        assert_eq!(
+            resolver.status_against(&test_config()).unwrap(),
+            DomainStatus::ManagedPermanent
+        );
+        let expected = ResolverConfig::new("test.local", "127.0.0.1", 6000);
+        assert_eq!(
+            resolver.status_against(&expected).unwrap(),
+            DomainStatus::ContentMismatch {
+                expected: expected.clone(),
+                actual: test_config()
+            }
+        );
+    }
+
+    #[test]
+    fn verify_reports_drift_without_modifying() {
+        let dir = tempfile::tempdir().unwrap();
+        let resolver = FileResolver::new("testapp").dir(dir.path());
+        resolver.register(&test_config()).unwrap();
+        resolver
+            .register(&ResolverConfig::new("extra.local", "127.0.0.1", 5553))
+            .unwrap();
+        std::fs::write(dir.path().join("hand.local"), "nameserver 1.1.1.1\n").unwrap();
+        let before = std::fs::read_to_string(dir.path().join("test.local")).unwrap();
+
+        let expected = [
+            ResolverConfig::new("test.local", "10.0.0.1", 6000),
+            ResolverConfig::new("missing.local", "127.0.0.1", 5553),
+            ResolverConfig::new("hand.local", "1.1.1.1", 53),
+        ];
+        let report = resolver.verify(&expected).unwrap();
+
+        assert_eq!(report.missing, vec!["missing.local", "hand.local"]);
+        assert_eq!(report.extra, vec!["extra.local"]);
+        assert_eq!(
+            report.changed,
+            vec![
+                FieldDrift {
+                    domain: "test.local".into(),
+                    field: DriftField::Nameserver,
+                    expected: "10.0.0.1".into(),
+                    actual: "127.0.0.1".into(),
+                },
+                FieldDrift {
+                    domain: "test.local".into(),
+                    field: DriftField::Port,
+                    expected: "6000".into(),
+                    actual: "5553".into(),
+                },
+            ]
+        );
+        assert!(!report.is_clean());
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("test.local")).unwrap(),
+            before
+        );
+
+        let clean = resolver
+            .verify(&[
+                test_config(),
+                ResolverConfig::new("extra.local", "127.0.0.1", 5553),
+            ])
+            .unwrap();
+        assert!(clean.is_clean());
+    }
 }
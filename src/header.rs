@@ -0,0 +1,428 @@
+//! Managed-file header line.
+//!
+//! The header is the **first line** of a managed file: the exact marker,
+//! optionally followed by a single space and a parenthesized, comma-separated
+//! `key=value` list that ends the line:
+//!
+//! ```text
+//! # managed by myapp (v=2, created=1767225600, uid=0, host=mbp, version=0.3.0)
+//! # managed by myapp (v=2, pid=12345, expires=1767225600)
+//! # managed by myapp (v=2, pid=12345, lock=/var/run/myapp/myapp.local.lock)
+//! # managed by myapp (v=2, pid=12345, app=1.4.0, tag.vm=dev)
+//! ```
+//!
+//! # Format versions
+//!
+//! The `v` field records the on-disk format version:
+//!
+//! | Version | Written by | Header |
+//! |---------|------------|--------|
+//! | 1 | 0.1.x, 0.2.0 | `<marker>` or `<marker> (pid=N)`; no `v` field |
+//! | 2 | 0.3.0 and later | `<marker> (v=2, ...)` with the fields below |
+//!
+//! Headers without a `v` field are read as version 1. Headers are always
+//! written in [`FORMAT_VERSION`]; see
+//! [`FileResolver::migrate`](crate::FileResolver::migrate) to upgrade old files.
+//!
+//! Version 2 is not backward compatible: 0.2.0 and earlier only find a PID
+//! in a header starting with `(pid=`, so they read every version 2 file as
+//! permanent and never clean it up. All processes sharing a marker must be
+//! upgraded together.
+//!
+//! Values are percent-encoded where they contain separators or whitespace.
+//! Unknown keys are ignored, so readers of version 2 tolerate fields added
+//! later, but any other deviation — a marker that only shares a stem (`# managed by
+//! myapplication`), trailing text, a malformed field, or the marker on a
+//! later line — means the file is not managed.
+
+use crate::clock::unix_secs;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Current on-disk format version.
+pub const FORMAT_VERSION: u32 = 2;
+
+/// Ownership data carried in a managed file's header.
+///
+/// Passed to [`Liveness`](crate::liveness::Liveness) checkers so they can
+/// decide whether the owner of a file is still around.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ManagedHeader {
+    /// Format version the header was read in; always written as
+    /// [`FORMAT_VERSION`].
+    pub format: u32,
+    /// PID of the owning process; `None` for permanent entries.
+    pub pid: Option<u32>,
+    /// Lease expiry in seconds since the Unix epoch.
+    pub expires: Option<u64>,
+    /// Lock file whose `flock` is held by the owner for its lifetime.
+    pub lock: Option<PathBuf>,
+    /// Creation time in seconds since the Unix epoch.
+    pub created: Option<u64>,
+    /// Real user ID of the creating process.
+    pub uid: Option<u32>,
+    /// Hostname of the machine the file was created on.
+    pub host: Option<String>,
+    /// Version of this crate that wrote the file.
+    pub version: Option<String>,
+    /// Version of the application that wrote the file.
+    pub app_version: Option<String>,
+    /// Caller-provided key/value tags.
+    pub tags: BTreeMap<String, String>,
+}
+
+impl Default for ManagedHeader {
+    fn default() -> Self {
+        Self {
+            format: FORMAT_VERSION,
+            pid: None,
+            expires: None,
+            lock: None,
+            created: None,
+            uid: None,
+            host: None,
+            version: None,
+            app_version: None,
+            tags: BTreeMap::new(),
+        }
+    }
+}
+
+impl ManagedHeader {
+    /// Parses the first line of `content` as a header for `marker`.
+    ///
+    /// Returns `None` if the first line is not a well-formed header for
+    /// exactly this marker.
+    pub(crate) fn parse(marker: &str, content: &str) -> Option<Self> {
+        let first = content.lines().next()?;
+        Self::parse_line(marker, first.strip_suffix('\r').unwrap_or(first))
+    }
+
+    /// Parses a single header line for `marker`.
+    fn parse_line(marker: &str, line: &str) -> Option<Self> {
+        let rest = line.strip_prefix(marker)?;
+        let mut header = Self {
+            format: 1,
+            ..Self::default()
+        };
+        if rest.is_empty() {
+            return Some(header);
+        }
+        let fields = rest.strip_prefix(" (")?.strip_suffix(')')?;
+
+        for field in fields.split(", ") {
+            let (key, value) = field.split_once('=')?;
+            if key.is_empty() || value.is_empty() {
+                return None;
+            }
+            match key {
+                "v" => header.format = value.parse().ok().filter(|&v| v >= 2)?,
+                "pid" => header.pid = Some(value.parse().ok()?),
+                "expires" => header.expires = Some(value.parse().ok()?),
+                "lock" => header.lock = Some(PathBuf::from(unescape(value)?)),
+                "created" => header.created = Some(value.parse().ok()?),
+                "uid" => header.uid = Some(value.parse().ok()?),
+                "host" => header.host = Some(unescape(value)?),
+                "version" => header.version = Some(unescape(value)?),
+                "app" => header.app_version = Some(unescape(value)?),
+                _ => {
+                    if let Some(tag) = key.strip_prefix("tag.") {
+                        header.tags.insert(unescape(tag)?, unescape(value)?);
+                    } else if !key.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_') {
+                        return None;
+                    }
+                }
+            }
+        }
+        Some(header)
+    }
+
+    /// Renders the header line (without trailing newline) in the current format.
+    pub(crate) fn render(&self, marker: &str) -> String {
+        let mut fields = vec![format!("v={FORMAT_VERSION}")];
+        if let Some(pid) = self.pid {
+            fields.push(format!("pid={pid}"));
+        }
+        if let Some(expires) = self.expires {
+            fields.push(format!("expires={expires}"));
+        }
+        if let Some(lock) = &self.lock {
+            fields.push(format!("lock={}", escape(&lock.to_string_lossy())));
+        }
+        if let Some(created) = self.created {
+            fields.push(format!("created={created}"));
+        }
+        if let Some(uid) = self.uid {
+            fields.push(format!("uid={uid}"));
+        }
+        let text = [
+            ("host", &self.host),
+            ("version", &self.version),
+            ("app", &self.app_version),
+        ];
+        for (key, value) in text {
+            if let Some(value) = value {
+                fields.push(format!("{key}={}", escape(value)));
+            }
+        }
+        for (key, value) in &self.tags {
+            fields.push(format!("tag.{}={}", escape(key), escape(value)));
+        }
+        format!("{marker} ({})", fields.join(", "))
+    }
+
+    /// Returns `true` if the header was read in an older format than
+    /// [`FORMAT_VERSION`].
+    #[must_use]
+    pub const fn is_outdated(&self) -> bool {
+        self.format < FORMAT_VERSION
+    }
+
+    /// Returns the creation time, if recorded.
+    #[must_use]
+    pub fn created_at(&self) -> Option<SystemTime> {
+        self.created
+            .map(|secs| UNIX_EPOCH + Duration::from_secs(secs))
+    }
+
+    /// Returns the lease expiry time, if any.
+    #[must_use]
+    pub fn expires_at(&self) -> Option<SystemTime> {
+        self.expires
+            .map(|secs| UNIX_EPOCH + Duration::from_secs(secs))
+    }
+
+    /// Returns `true` if the header carries a lease that ended at or before `now`.
+    #[must_use]
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires.is_some_and(|t| t <= unix_secs(now))
+    }
+
+    /// Replaces the header line for `marker` in `content`, keeping all other lines.
+    ///
+    /// Returns `None` if `content` does not start with a header for `marker`.
+    pub(crate) fn replace_in(&self, marker: &str, content: &str) -> Option<String> {
+        Self::parse(marker, content)?;
+        let body = content.split_once('\n').map_or("", |(_, body)| body);
+        Some(format!("{}\n{body}", self.render(marker)))
+    }
+}
+
+/// Stem shared by markers derived from a prefix (`# managed by <prefix>`).
+pub(crate) const MARKER_STEM: &str = "# managed by ";
+
+/// Returns the marker of a file that starts with a `# managed by ...` header
+/// for a marker other than `marker`.
+pub(crate) fn foreign_marker<'a>(marker: &str, content: &'a str) -> Option<&'a str> {
+    let first = content.lines().next()?;
+    let first = first.strip_suffix('\r').unwrap_or(first);
+    if !first.starts_with(MARKER_STEM) || ManagedHeader::parse(marker, first).is_some() {
+        return None;
+    }
+    let end = first.find(" (").unwrap_or(first.len());
+    Some(&first[..end])
+}
+
+/// Percent-encodes characters that would break the header grammar.
+fn escape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for b in value.bytes() {
+        if b.is_ascii_graphic() && !matches!(b, b'%' | b',' | b'(' | b')' | b'=') {
+            out.push(char::from(b));
+        } else {
+            let _ = write!(out, "%{b:02X}");
+        }
+    }
+    out
+}
+
+/// Reverses [`escape`]; `None` on malformed escapes or invalid UTF-8.
+fn unescape(value: &str) -> Option<String> {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = value.get(i + 1..i + 3)?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    const MARKER: &str = "# managed by testapp";
+
+    #[test]
+    fn parses_legacy_pid_suffix() {
+        let h = ManagedHeader::parse(MARKER, "# managed by testapp (pid=42)\nport 53\n").unwrap();
+        assert_eq!(h.format, 1);
+        assert!(h.is_outdated());
+        assert_eq!(h.pid, Some(42));
+        assert_eq!(h.expires, None);
+    }
+
+    #[test]
+    fn round_trips_fields() {
+        let h = ManagedHeader {
+            pid: Some(7),
+            expires: Some(1_000),
+            ..ManagedHeader::default()
+        };
+        let line = h.render(MARKER);
+        assert_eq!(line, "# managed by testapp (v=2, pid=7, expires=1000)");
+        assert_eq!(ManagedHeader::parse(MARKER, &line), Some(h));
+    }
+
+    #[test]
+    fn lock_path_is_escaped() {
+        let h = ManagedHeader {
+            pid: Some(7),
+            lock: Some(PathBuf::from("/tmp/my dir, (x)/a.lock")),
+            ..ManagedHeader::default()
+        };
+        let line = h.render(MARKER);
+        assert_eq!(
+            line,
+            "# managed by testapp (v=2, pid=7, lock=/tmp/my%20dir%2C%20%28x%29/a.lock)"
+        );
+        assert_eq!(ManagedHeader::parse(MARKER, &line), Some(h));
+    }
+
+    #[test]
+    fn round_trips_metadata_and_tags() {
+        let mut tags = BTreeMap::new();
+        tags.insert("vm".to_string(), "dev box".to_string());
+        tags.insert("a=b".to_string(), "c,d".to_string());
+        let h = ManagedHeader {
+            pid: Some(7),
+            created: Some(1_700_000_000),
+            uid: Some(501),
+            host: Some("mbp".to_string()),
+            version: Some("0.2.0".to_string()),
+            app_version: Some("1.4.0".to_string()),
+            tags,
+            ..ManagedHeader::default()
+        };
+        let line = h.render(MARKER);
+        assert_eq!(
+            line,
+            "# managed by testapp (v=2, pid=7, created=1700000000, uid=501, host=mbp, \
+             version=0.2.0, app=1.4.0, tag.a%3Db=c%2Cd, tag.vm=dev%20box)"
+        );
+        assert_eq!(ManagedHeader::parse(MARKER, &line), Some(h));
+    }
+
+    #[test]
+    fn permanent_header_carries_only_version() {
+        assert_eq!(
+            ManagedHeader::default().render(MARKER),
+            "# managed by testapp (v=2)"
+        );
+        let legacy = ManagedHeader::parse(MARKER, MARKER).unwrap();
+        assert_eq!(legacy.format, 1);
+        assert_eq!(legacy.pid, None);
+    }
+
+    #[test]
+    fn rejects_invalid_format_version() {
+        assert!(ManagedHeader::parse(MARKER, "# managed by testapp (v=1)").is_none());
+        assert!(ManagedHeader::parse(MARKER, "# managed by testapp (v=x)").is_none());
+        let future = ManagedHeader::parse(MARKER, "# managed by testapp (v=3, pid=1)").unwrap();
+        assert_eq!(future.format, 3);
+        assert!(!future.is_outdated());
+    }
+
+    #[test]
+    fn expiry_is_inclusive() {
+        let h = ManagedHeader {
+            expires: Some(100),
+            ..ManagedHeader::default()
+        };
+        assert!(!h.is_expired(UNIX_EPOCH + Duration::from_secs(99)));
+        assert!(h.is_expired(UNIX_EPOCH + Duration::from_secs(100)));
+        assert!(!ManagedHeader::default().is_expired(SystemTime::now()));
+    }
+
+    #[test]
+    fn replace_keeps_body() {
+        let content = "# managed by testapp (pid=1)\nnameserver 127.0.0.1\nport 53\n";
+        let h = ManagedHeader {
+            pid: Some(1),
+            expires: Some(5),
+            ..ManagedHeader::default()
+        };
+        assert_eq!(
+            h.replace_in(MARKER, content).unwrap(),
+            "# managed by testapp (v=2, pid=1, expires=5)\nnameserver 127.0.0.1\nport 53\n"
+        );
+        assert!(h.replace_in(MARKER, "nameserver 1.1.1.1\n").is_none());
+    }
+
+    #[test]
+    fn rejects_marker_sharing_a_stem() {
+        assert!(ManagedHeader::parse("# managed by app", "# managed by application\n").is_none());
+        assert!(
+            ManagedHeader::parse("# managed by app", "# managed by application (pid=1)\n")
+                .is_none()
+        );
+        assert!(ManagedHeader::parse("# managed by app", "# managed by app\n").is_some());
+    }
+
+    #[test]
+    fn header_must_be_first_line() {
+        let content = "nameserver 127.0.0.1\n# managed by testapp (pid=1)\n";
+        assert!(ManagedHeader::parse(MARKER, content).is_none());
+        let content = "nameserver 127.0.0.1 # managed by testapp\n";
+        assert!(ManagedHeader::parse(MARKER, content).is_none());
+    }
+
+    #[test]
+    fn rejects_malformed_suffix() {
+        for line in [
+            "# managed by testapp (pid=1) trailing",
+            "# managed by testapp (pid=1",
+            "# managed by testapp(pid=1)",
+            "# managed by testapp (pid=abc)",
+            "# managed by testapp (pid)",
+            "# managed by testapp ()",
+            "# managed by testapp (pid=1,expires=2)",
+            "# managed by testapp extra",
+        ] {
+            assert!(ManagedHeader::parse(MARKER, line).is_none(), "{line}");
+        }
+    }
+
+    #[test]
+    fn ignores_unknown_keys() {
+        let h = ManagedHeader::parse(MARKER, "# managed by testapp (pid=1, future_key=x)").unwrap();
+        assert_eq!(h.pid, Some(1));
+    }
+
+    #[test]
+    fn detects_foreign_marker() {
+        assert_eq!(
+            foreign_marker(MARKER, "# managed by otherapp (pid=1)\nport 53\n"),
+            Some("# managed by otherapp")
+        );
+        assert_eq!(
+            foreign_marker(MARKER, "# managed by testapplication\n"),
+            Some("# managed by testapplication")
+        );
+        assert_eq!(
+            foreign_marker(MARKER, "# managed by testapp (pid=1)\n"),
+            None
+        );
+        assert_eq!(foreign_marker(MARKER, "nameserver 1.1.1.1\n"), None);
+    }
+}
@@ -0,0 +1,254 @@
+//! Client side of the helper.
+
+use std::io;
+use std::io::BufReader;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+
+use super::protocol::{
+    Binding, Envelope, PROTOCOL_VERSION, Reply, Request, Response, read_line, write_message,
+};
+use crate::config::ResolverConfig;
+use crate::error::{ResolverError, Result};
+use crate::status::DomainStatus;
+
+/// Connection to a [`HelperServer`](super::HelperServer).
+///
+/// Methods mirror [`FileResolver`](crate::FileResolver) and return the same
+/// [`ResolverError`] variants the helper hit, so callers can handle remote
+/// and local failures alike. Requests on one client are serialized.
+///
+/// If the helper closed the connection while it was idle, the next request
+/// reconnects and is sent again.
+#[derive(Debug)]
+pub struct HelperClient {
+    path: PathBuf,
+    conn: Mutex<Connection>,
+}
+
+#[derive(Debug)]
+struct Connection {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+    timeout: Option<Duration>,
+}
+
+impl Connection {
+    fn open(path: &Path, timeout: Option<Duration>) -> io::Result<Self> {
+        let writer = UnixStream::connect(path)?;
+        writer.set_read_timeout(timeout)?;
+        writer.set_write_timeout(timeout)?;
+        let reader = BufReader::new(writer.try_clone()?);
+        Ok(Self {
+            reader,
+            writer,
+            timeout,
+        })
+    }
+
+    /// Sends one request line and reads the response line.
+    fn exchange(&mut self, request: &Envelope<Request>) -> io::Result<String> {
+        if let Err(e) = write_message(&mut self.writer, request) {
+            // The helper may have answered and closed before reading, e.g.
+            // to refuse the connection; prefer its answer to the write error.
+            return match read_line(&mut self.reader) {
+                Ok(Some(line)) => Ok(line),
+                _ => Err(e),
+            };
+        }
+        read_line(&mut self.reader)?.ok_or_else(|| io::ErrorKind::UnexpectedEof.into())
+    }
+}
+
+impl HelperClient {
+    /// Connects to the helper listening on `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ResolverError::Io`] if the socket cannot be connected to.
+    pub fn connect(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let conn = Connection::open(&path, None)?;
+        Ok(Self {
+            path,
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Sets how long to wait for each response (default: forever).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ResolverError::Io`] if the timeout cannot be applied.
+    pub fn set_timeout(&self, timeout: Option<Duration>) -> Result<()> {
+        let mut conn = self.conn.lock().unwrap_or_else(PoisonError::into_inner);
+        conn.timeout = timeout;
+        let result = conn
+            .writer
+            .set_read_timeout(timeout)
+            .and_then(|()| conn.writer.set_write_timeout(timeout));
+        drop(conn);
+        Ok(result?)
+    }
+
+    /// Registers `config` via [`FileResolver::register`](crate::FileResolver::register)
+    /// in the helper, bound to this process ([`Binding::Process`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns the helper's error, or [`ResolverError::Io`] /
+    /// [`ResolverError::Protocol`] if the exchange fails.
+    pub fn register(&self, config: &ResolverConfig) -> Result<()> {
+        self.register_bound(config, Binding::Process)
+    }
+
+    /// Registers `config` in the helper, which removes it again as soon as
+    /// this client disconnects ([`Binding::Connection`]).
+    ///
+    /// # Errors
+    ///
+    /// Same as [`register`](Self::register).
+    pub fn register_for_connection(&self, config: &ResolverConfig) -> Result<()> {
+        self.register_bound(config, Binding::Connection)
+    }
+
+    /// Registers `config` in the helper with an explicit [`Binding`].
+    ///
+    /// # Errors
+    ///
+    /// Same as [`register`](Self::register).
+    pub fn register_bound(&self, config: &ResolverConfig, bind: Binding) -> Result<()> {
+        self.expect_done(Request::Register {
+            config: config.clone(),
+            permanent: false,
+            bind,
+        })
+    }
+
+    /// Registers `config` via
+    /// [`FileResolver::register_permanent`](crate::FileResolver::register_permanent)
+    /// in the helper.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`register`](Self::register).
+    pub fn register_permanent(&self, config: &ResolverConfig) -> Result<()> {
+        self.expect_done(Request::Register {
+            config: config.clone(),
+            permanent: true,
+            bind: Binding::default(),
+        })
+    }
+
+    /// Removes a managed file via the helper.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`register`](Self::register).
+    pub fn unregister(&self, domain: &str) -> Result<()> {
+        self.expect_done(Request::Unregister {
+            domain: domain.to_string(),
+        })
+    }
+
+    /// Lists managed domains via the helper.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`register`](Self::register).
+    pub fn list(&self) -> Result<Vec<String>> {
+        match self.call(Request::List)? {
+            Reply::Domains { domains } => Ok(domains),
+            other => Err(unexpected(&other)),
+        }
+    }
+
+    /// Removes orphaned files via the helper, returning how many were removed.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`register`](Self::register).
+    pub fn cleanup_orphaned(&self) -> Result<usize> {
+        match self.call(Request::Cleanup)? {
+            Reply::Removed { count } => Ok(count),
+            other => Err(unexpected(&other)),
+        }
+    }
+
+    /// Reports a domain's state via the helper.
+    ///
+    /// # Errors
+    ///
+    /// Same as [`register`](Self::register).
+    pub fn status(&self, domain: &str) -> Result<DomainStatus> {
+        match self.call(Request::Status {
+            domain: domain.to_string(),
+        })? {
+            Reply::Status { status } => Ok(status),
+            other => Err(unexpected(&other)),
+        }
+    }
+
+    /// Sends a raw request and waits for its reply.
+    ///
+    /// # Errors
+    ///
+    /// Returns the helper's error converted to [`ResolverError`],
+    /// [`ResolverError::Io`] if the connection fails, or
+    /// [`ResolverError::Protocol`] if the response is malformed or from an
+    /// incompatible protocol version.
+    pub fn call(&self, request: Request) -> Result<Reply> {
+        let request = Envelope::new(request);
+        let mut conn = self.conn.lock().unwrap_or_else(PoisonError::into_inner);
+        let line = match conn.exchange(&request) {
+            Err(e) if is_disconnect(&e) => {
+                tracing::debug!(error = %e, "Helper connection closed, reconnecting");
+                *conn = Connection::open(&self.path, conn.timeout)?;
+                conn.exchange(&request)
+            }
+            result => result,
+        };
+        drop(conn);
+        let line = line.map_err(|e| {
+            if e.kind() == io::ErrorKind::UnexpectedEof {
+                ResolverError::Protocol("helper closed the connection".into())
+            } else {
+                e.into()
+            }
+        })?;
+
+        let response: Envelope<Response> = serde_json::from_str(&line)
+            .map_err(|e| ResolverError::Protocol(format!("malformed response: {e}")))?;
+        if response.v != PROTOCOL_VERSION {
+            return Err(ResolverError::Protocol(format!(
+                "helper speaks protocol version {}, expected {PROTOCOL_VERSION}",
+                response.v
+            )));
+        }
+        match response.body {
+            Response::Ok(reply) => Ok(reply),
+            Response::Error(e) => Err(e.into()),
+        }
+    }
+
+    fn expect_done(&self, request: Request) -> Result<()> {
+        match self.call(request)? {
+            Reply::Done => Ok(()),
+            other => Err(unexpected(&other)),
+        }
+    }
+}
+
+/// Whether `e` means the helper had already closed the connection.
+fn is_disconnect(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::UnexpectedEof | io::ErrorKind::BrokenPipe | io::ErrorKind::ConnectionReset
+    )
+}
+
+fn unexpected(reply: &Reply) -> ResolverError {
+    ResolverError::Protocol(format!("unexpected reply: {reply:?}"))
+}
@@ -0,0 +1,30 @@
+//! Privileged helper over a Unix domain socket (`helper` feature).
+//!
+//! Writing `/etc/resolver` needs root, but GUI apps usually run
+//! unprivileged. [`HelperServer`] runs as root, listens on a Unix socket and
+//! performs [`FileResolver`](crate::FileResolver) operations on behalf of
+//! clients; [`HelperClient`] is the matching client. Messages are versioned
+//! JSON lines, see [`protocol`]. A [`Policy`] restricts what each client
+//! may do based on its [`PeerCredentials`].
+//!
+//! ```rust,ignore
+//! // In the root helper:
+//! let resolver = Arc::new(FileResolver::new("myapp"));
+//! HelperServer::new(resolver).serve("/var/run/myapp-dns.sock")?;
+//!
+//! // In the app:
+//! let client = HelperClient::connect("/var/run/myapp-dns.sock")?;
+//! client.register(&ResolverConfig::new("myapp.local", "127.0.0.1", 5553))?;
+//! ```
+
+mod client;
+mod peer;
+mod policy;
+pub mod protocol;
+mod server;
+
+pub use client::HelperClient;
+pub use peer::PeerCredentials;
+pub use policy::Policy;
+pub use protocol::{Binding, PROTOCOL_VERSION};
+pub use server::{HelperHandle, HelperServer};
@@ -0,0 +1,116 @@
+//! Identity of the process on the other end of a Unix socket.
+
+use std::io;
+use std::os::fd::AsRawFd;
+use std::os::unix::net::UnixStream;
+
+/// Credentials of a connected client, as reported by the kernel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerCredentials {
+    /// Effective user ID.
+    pub uid: u32,
+    /// Effective (primary) group ID.
+    pub gid: u32,
+    /// Process ID, if the platform reports it.
+    pub pid: Option<u32>,
+}
+
+impl PeerCredentials {
+    /// Reads the credentials of `stream`'s peer (`SO_PEERCRED` on Linux,
+    /// `getpeereid` and `LOCAL_PEERPID` on macOS).
+    ///
+    /// # Errors
+    ///
+    /// Returns the OS error if the credentials cannot be read, or
+    /// `Unsupported` on other platforms.
+    pub fn of(stream: &UnixStream) -> io::Result<Self> {
+        peer_credentials(stream.as_raw_fd())
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn peer_credentials(fd: libc::c_int) -> io::Result<PeerCredentials> {
+    let mut cred = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    #[allow(clippy::cast_possible_truncation)]
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // SAFETY: `cred` and `len` describe a valid, writable `ucred`.
+    let rc = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            (&raw mut cred).cast(),
+            &raw mut len,
+        )
+    };
+    if rc != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(PeerCredentials {
+        uid: cred.uid,
+        gid: cred.gid,
+        pid: u32::try_from(cred.pid).ok().filter(|&pid| pid != 0),
+    })
+}
+
+#[cfg(target_os = "macos")]
+fn peer_credentials(fd: libc::c_int) -> io::Result<PeerCredentials> {
+    // From <sys/un.h>; not exported by every libc version.
+    const SOL_LOCAL: libc::c_int = 0;
+    const LOCAL_PEERPID: libc::c_int = 0x002;
+
+    let mut uid: libc::uid_t = 0;
+    let mut gid: libc::gid_t = 0;
+    // SAFETY: `uid` and `gid` are valid out-pointers.
+    if unsafe { libc::getpeereid(fd, &raw mut uid, &raw mut gid) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    let mut pid: libc::pid_t = 0;
+    #[allow(clippy::cast_possible_truncation)]
+    let mut len = std::mem::size_of::<libc::pid_t>() as libc::socklen_t;
+    // SAFETY: `pid` and `len` describe a valid, writable `pid_t`.
+    let rc = unsafe {
+        libc::getsockopt(
+            fd,
+            SOL_LOCAL,
+            LOCAL_PEERPID,
+            (&raw mut pid).cast(),
+            &raw mut len,
+        )
+    };
+    Ok(PeerCredentials {
+        uid,
+        gid,
+        pid: if rc == 0 {
+            u32::try_from(pid).ok().filter(|&pid| pid != 0)
+        } else {
+            None
+        },
+    })
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn peer_credentials(_fd: libc::c_int) -> io::Result<PeerCredentials> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "peer credentials are not supported on this platform",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_own_credentials() {
+        let (a, _b) = UnixStream::pair().unwrap();
+        let peer = PeerCredentials::of(&a).unwrap();
+        assert_eq!(peer.uid, crate::util::current_uid());
+        assert_eq!(peer.pid, Some(std::process::id()));
+    }
+}
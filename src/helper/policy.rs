@@ -0,0 +1,273 @@
+//! Authorization rules for helper clients.
+
+use std::collections::BTreeSet;
+use std::net::IpAddr;
+use std::ops::RangeInclusive;
+
+use super::peer::PeerCredentials;
+use crate::config::ResolverConfig;
+use crate::error::{ResolverError, Result};
+
+/// Decides which clients may do what through a
+/// [`HelperServer`](super::HelperServer).
+///
+/// Rules are checked against the client's [`PeerCredentials`]. Writes are
+/// denied by default: a client can only register domains matching an
+/// [`allow_domain`](Self::allow_domain) pattern, pointing at a nameserver
+/// from [`allow_nameserver`](Self::allow_nameserver). Unset user, group and
+/// port restrictions allow everything; permanent entries are refused unless
+/// [`allow_permanent`](Self::allow_permanent) is set.
+///
+/// ```rust,ignore
+/// let policy = Policy::new()
+///     .allow_gid(20) // staff
+///     .allow_domain("*.myapp.local")
+///     .allow_nameserver("127.0.0.1".parse()?)
+///     .allow_ports(5300..=5399)
+///     .max_entries_per_uid(8);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Policy {
+    uids: BTreeSet<u32>,
+    gids: BTreeSet<u32>,
+    domains: Vec<String>,
+    nameservers: BTreeSet<IpAddr>,
+    ports: Vec<RangeInclusive<u16>>,
+    max_entries_per_uid: Option<usize>,
+    allow_permanent: bool,
+}
+
+impl Policy {
+    /// Creates a policy that lets any user connect, read, and clean up, but
+    /// register nothing.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allows clients running as `uid`. Once any user or group is allowed,
+    /// all other clients are refused.
+    #[must_use]
+    pub fn allow_uid(mut self, uid: u32) -> Self {
+        self.uids.insert(uid);
+        self
+    }
+
+    /// Allows clients whose primary group is `gid`. Once any user or group
+    /// is allowed, all other clients are refused.
+    #[must_use]
+    pub fn allow_gid(mut self, gid: u32) -> Self {
+        self.gids.insert(gid);
+        self
+    }
+
+    /// Allows domains matching `pattern`.
+    ///
+    /// `"myapp.local"` matches that domain and its subdomains;
+    /// `"*.myapp.local"` matches only subdomains. Matching ignores case.
+    #[must_use]
+    pub fn allow_domain(mut self, pattern: impl Into<String>) -> Self {
+        self.domains.push(pattern.into().to_ascii_lowercase());
+        self
+    }
+
+    /// Allows entries pointing at `nameserver`.
+    #[must_use]
+    pub fn allow_nameserver(mut self, nameserver: IpAddr) -> Self {
+        self.nameservers.insert(nameserver);
+        self
+    }
+
+    /// Allows entries using a port in `ports`. Once any range is allowed,
+    /// ports outside all ranges are refused.
+    #[must_use]
+    pub fn allow_ports(mut self, ports: RangeInclusive<u16>) -> Self {
+        self.ports.push(ports);
+        self
+    }
+
+    /// Limits how many entries each user may have registered at once.
+    #[must_use]
+    pub const fn max_entries_per_uid(mut self, max: usize) -> Self {
+        self.max_entries_per_uid = Some(max);
+        self
+    }
+
+    /// Sets whether clients may register permanent entries (default: no).
+    #[must_use]
+    pub const fn allow_permanent(mut self, allow: bool) -> Self {
+        self.allow_permanent = allow;
+        self
+    }
+
+    /// Checks that `peer` may use the helper at all.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ResolverError::Unauthorized`] if it may not.
+    pub fn check_peer(&self, peer: &PeerCredentials) -> Result<()> {
+        if self.uids.is_empty() && self.gids.is_empty()
+            || self.uids.contains(&peer.uid)
+            || self.gids.contains(&peer.gid)
+        {
+            Ok(())
+        } else {
+            Err(ResolverError::Unauthorized(format!(
+                "uid {} is not allowed",
+                peer.uid
+            )))
+        }
+    }
+
+    /// Checks that `peer` may touch `domain` (unregister, or as part of a
+    /// registration).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ResolverError::Unauthorized`] if it may not.
+    pub fn check_domain(&self, peer: &PeerCredentials, domain: &str) -> Result<()> {
+        self.check_peer(peer)?;
+        let domain = domain.to_ascii_lowercase();
+        if self.domains.iter().any(|p| domain_matches(p, &domain)) {
+            Ok(())
+        } else {
+            Err(ResolverError::Unauthorized(format!(
+                "domain {domain} is not allowed"
+            )))
+        }
+    }
+
+    /// Checks that `peer` may register `config`, given that it already has
+    /// `existing` other entries.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ResolverError::Unauthorized`] naming the first rule the
+    /// request breaks.
+    pub fn check_register(
+        &self,
+        peer: &PeerCredentials,
+        config: &ResolverConfig,
+        permanent: bool,
+        existing: usize,
+    ) -> Result<()> {
+        self.check_domain(peer, &config.domain)?;
+        let allowed_ns = config
+            .nameserver
+            .parse::<IpAddr>()
+            .is_ok_and(|ip| self.nameservers.contains(&ip));
+        if !allowed_ns {
+            return Err(ResolverError::Unauthorized(format!(
+                "nameserver {} is not allowed",
+                config.nameserver
+            )));
+        }
+        if !self.ports.is_empty() && !self.ports.iter().any(|r| r.contains(&config.port)) {
+            return Err(ResolverError::Unauthorized(format!(
+                "port {} is not allowed",
+                config.port
+            )));
+        }
+        if permanent && !self.allow_permanent {
+            return Err(ResolverError::Unauthorized(
+                "permanent entries are not allowed".into(),
+            ));
+        }
+        if let Some(max) = self.max_entries_per_uid {
+            if existing >= max {
+                return Err(ResolverError::Unauthorized(format!(
+                    "uid {} already has {existing} entries (limit {max})",
+                    peer.uid
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn domain_matches(pattern: &str, domain: &str) -> bool {
+    let (suffix, exact_ok) = pattern
+        .strip_prefix("*.")
+        .map_or((pattern, true), |suffix| (suffix, false));
+    (exact_ok && domain == suffix)
+        || domain
+            .strip_suffix(suffix)
+            .is_some_and(|rest| rest.ends_with('.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const USER: PeerCredentials = PeerCredentials {
+        uid: 501,
+        gid: 20,
+        pid: Some(100),
+    };
+
+    fn policy() -> Policy {
+        Policy::new()
+            .allow_domain("myapp.local")
+            .allow_domain("*.dev.test")
+            .allow_nameserver("127.0.0.1".parse().unwrap())
+            .allow_ports(5300..=5399)
+            .max_entries_per_uid(2)
+    }
+
+    fn config(domain: &str, ns: &str, port: u16) -> ResolverConfig {
+        ResolverConfig::new(domain, ns, port)
+    }
+
+    #[test]
+    fn domain_patterns() {
+        assert!(domain_matches("myapp.local", "myapp.local"));
+        assert!(domain_matches("myapp.local", "a.b.myapp.local"));
+        assert!(!domain_matches("myapp.local", "evilmyapp.local"));
+        assert!(!domain_matches("*.dev.test", "dev.test"));
+        assert!(domain_matches("*.dev.test", "x.dev.test"));
+    }
+
+    #[test]
+    fn register_rules() {
+        let p = policy();
+        let ok = config("API.myapp.local", "127.0.0.1", 5353);
+        assert!(p.check_register(&USER, &ok, false, 1).is_ok());
+
+        let refused = [
+            (config("example.com", "127.0.0.1", 5353), false, 0),
+            (config("dev.test", "127.0.0.1", 5353), false, 0),
+            (config("myapp.local", "10.0.0.1", 5353), false, 0),
+            (config("myapp.local", "127.0.0.1", 53), false, 0),
+            (ok.clone(), true, 0),
+            (ok, false, 2),
+        ];
+        for (config, permanent, existing) in refused {
+            assert!(
+                matches!(
+                    p.check_register(&USER, &config, permanent, existing),
+                    Err(ResolverError::Unauthorized(_))
+                ),
+                "{config:?} permanent={permanent} existing={existing}"
+            );
+        }
+    }
+
+    #[test]
+    fn default_policy_registers_nothing() {
+        let p = Policy::new();
+        assert!(p.check_peer(&USER).is_ok());
+        assert!(
+            p.check_register(&USER, &config("a.local", "127.0.0.1", 53), false, 0)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn user_and_group_rules() {
+        let p = policy().allow_uid(0).allow_gid(80);
+        assert!(p.check_peer(&USER).is_err());
+        assert!(p.check_peer(&PeerCredentials { gid: 80, ..USER }).is_ok());
+        assert!(p.check_peer(&PeerCredentials { uid: 0, ..USER }).is_ok());
+        assert!(p.check_domain(&USER, "myapp.local").is_err());
+    }
+}
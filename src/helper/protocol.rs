@@ -0,0 +1,420 @@
+//! Wire format between [`HelperClient`](super::HelperClient) and
+//! [`HelperServer`](super::HelperServer).
+//!
+//! Each message is one line of JSON. Every request and response carries the
+//! protocol version in `v`; a server rejects requests with a version it does
+//! not speak. Examples:
+//!
+//! ```text
+//! > {"v":1,"op":"register","config":{"domain":"myapp.local","nameserver":"127.0.0.1","port":5553,"search_order":1}}
+//! < {"v":1,"ok":{"kind":"done"}}
+//! > {"v":1,"op":"unregister","domain":"other.local"}
+//! < {"v":1,"error":{"code":"not_managed","message":"...","domain":"other.local"}}
+//! ```
+
+use std::io::{self, BufRead, Read, Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::ResolverConfig;
+use crate::error::ResolverError;
+use crate::status::DomainStatus;
+
+/// Version of the request/response protocol spoken by this crate.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Longest accepted message line, in bytes.
+pub const MAX_LINE: usize = 64 * 1024;
+
+/// A message with its protocol version.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Envelope<T> {
+    /// Protocol version of the sender.
+    pub v: u32,
+    /// The message itself.
+    #[serde(flatten)]
+    pub body: T,
+}
+
+impl<T> Envelope<T> {
+    /// Wraps `body` with the current [`PROTOCOL_VERSION`].
+    pub const fn new(body: T) -> Self {
+        Self {
+            v: PROTOCOL_VERSION,
+            body,
+        }
+    }
+}
+
+/// An operation requested by a client.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Request {
+    /// [`FileResolver::register`](crate::FileResolver::register), or
+    /// [`register_permanent`](crate::FileResolver::register_permanent) if
+    /// `permanent` is set.
+    Register {
+        /// The entry to write.
+        config: ResolverConfig,
+        /// Whether the entry survives the helper.
+        #[serde(default)]
+        permanent: bool,
+        /// What the entry's lifetime is tied to; ignored if `permanent`.
+        #[serde(default)]
+        bind: Binding,
+    },
+    /// [`FileResolver::unregister`](crate::FileResolver::unregister).
+    Unregister {
+        /// The domain to remove.
+        domain: String,
+    },
+    /// [`FileResolver::list`](crate::FileResolver::list).
+    List,
+    /// [`FileResolver::cleanup_orphaned`](crate::FileResolver::cleanup_orphaned).
+    Cleanup,
+    /// [`FileResolver::status`](crate::FileResolver::status).
+    Status {
+        /// The domain to inspect.
+        domain: String,
+    },
+}
+
+/// What a non-permanent registration made through the helper lives as long
+/// as.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Binding {
+    /// The entry records the client's PID, so
+    /// [`cleanup_orphaned`](crate::FileResolver::cleanup_orphaned) removes
+    /// it once the client exits. Falls back to [`Connection`](Self::Connection)
+    /// if the client's PID is unknown.
+    #[default]
+    Process,
+    /// The helper removes the entry when the client's connection closes.
+    Connection,
+}
+
+/// Successful result of a [`Request`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Reply {
+    /// The operation completed with nothing to return.
+    Done,
+    /// Managed domains, for [`Request::List`].
+    Domains {
+        /// The domains.
+        domains: Vec<String>,
+    },
+    /// Number of files removed, for [`Request::Cleanup`].
+    Removed {
+        /// How many files were removed.
+        count: usize,
+    },
+    /// A domain's state, for [`Request::Status`].
+    Status {
+        /// The state.
+        status: DomainStatus,
+    },
+}
+
+/// Response to a [`Request`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Response {
+    /// The request succeeded.
+    Ok(Reply),
+    /// The request failed.
+    Error(RemoteError),
+}
+
+/// Machine-readable error category in a [`RemoteError`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// I/O failed with `PermissionDenied`.
+    PermissionDenied,
+    /// Any other I/O failure.
+    Io,
+    /// See [`ResolverError::DirNotFound`].
+    DirNotFound,
+    /// See [`ResolverError::NotManaged`].
+    NotManaged,
+    /// See [`ResolverError::OwnedByOther`].
+    OwnedByOther,
+    /// See [`ResolverError::NotOwner`].
+    NotOwner,
+    /// See [`ResolverError::NotFound`].
+    NotFound,
+    /// See [`ResolverError::Locked`].
+    Locked,
+    /// See [`ResolverError::InvalidConfig`].
+    InvalidConfig,
+    /// See [`ResolverError::Unauthorized`].
+    Unauthorized,
+    /// See [`ResolverError::Unreachable`].
+    Unreachable,
+    /// The request could not be parsed.
+    BadRequest,
+    /// The request's protocol version is not supported.
+    UnsupportedVersion,
+    /// Any other failure.
+    Internal,
+}
+
+/// An error reported by the helper, convertible back to [`ResolverError`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RemoteError {
+    /// Error category.
+    pub code: ErrorCode,
+    /// Human-readable description (the bare reason or path for
+    /// [`ErrorCode::InvalidConfig`], [`ErrorCode::Unauthorized`],
+    /// [`ErrorCode::Unreachable`] and [`ErrorCode::DirNotFound`]).
+    pub message: String,
+    /// The affected domain, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub domain: Option<String>,
+    /// The other owner's marker, for [`ErrorCode::OwnedByOther`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub marker: Option<String>,
+    /// The recorded owner PID, for [`ErrorCode::NotOwner`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pid: Option<u32>,
+}
+
+impl RemoteError {
+    /// Creates an error with only a code and message.
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            domain: None,
+            marker: None,
+            pid: None,
+        }
+    }
+}
+
+impl From<&ResolverError> for RemoteError {
+    fn from(e: &ResolverError) -> Self {
+        let mut remote = Self::new(ErrorCode::Internal, e.to_string());
+        match e {
+            ResolverError::Io(io) if io.kind() == io::ErrorKind::PermissionDenied => {
+                remote.code = ErrorCode::PermissionDenied;
+            }
+            ResolverError::Io(_) => remote.code = ErrorCode::Io,
+            ResolverError::DirNotFound { path } => {
+                remote.code = ErrorCode::DirNotFound;
+                remote.message.clone_from(path);
+            }
+            ResolverError::NotManaged { domain } => {
+                remote.code = ErrorCode::NotManaged;
+                remote.domain = Some(domain.clone());
+            }
+            ResolverError::OwnedByOther { domain, marker } => {
+                remote.code = ErrorCode::OwnedByOther;
+                remote.domain = Some(domain.clone());
+                remote.marker = Some(marker.clone());
+            }
+            ResolverError::NotOwner { domain, pid } => {
+                remote.code = ErrorCode::NotOwner;
+                remote.domain = Some(domain.clone());
+                remote.pid = *pid;
+            }
+            ResolverError::NotFound { domain } => {
+                remote.code = ErrorCode::NotFound;
+                remote.domain = Some(domain.clone());
+            }
+            ResolverError::Locked { domain } => {
+                remote.code = ErrorCode::Locked;
+                remote.domain = Some(domain.clone());
+            }
+            ResolverError::InvalidConfig(reason) => {
+                remote.code = ErrorCode::InvalidConfig;
+                remote.message.clone_from(reason);
+            }
+            ResolverError::Unauthorized(reason) => {
+                remote.code = ErrorCode::Unauthorized;
+                remote.message.clone_from(reason);
+            }
+            ResolverError::Unreachable { domain, reason } => {
+                remote.code = ErrorCode::Unreachable;
+                remote.domain = Some(domain.clone());
+                remote.message.clone_from(reason);
+            }
+            ResolverError::Signing(_) | ResolverError::Protocol(_) => {}
+        }
+        remote
+    }
+}
+
+impl From<RemoteError> for ResolverError {
+    fn from(e: RemoteError) -> Self {
+        let domain = e.domain.unwrap_or_default();
+        match e.code {
+            ErrorCode::PermissionDenied => {
+                Self::Io(io::Error::new(io::ErrorKind::PermissionDenied, e.message))
+            }
+            ErrorCode::Io => Self::Io(io::Error::other(e.message)),
+            ErrorCode::DirNotFound => Self::DirNotFound { path: e.message },
+            ErrorCode::NotManaged => Self::NotManaged { domain },
+            ErrorCode::OwnedByOther => Self::OwnedByOther {
+                domain,
+                marker: e.marker.unwrap_or_default(),
+            },
+            ErrorCode::NotOwner => Self::NotOwner { domain, pid: e.pid },
+            ErrorCode::NotFound => Self::NotFound { domain },
+            ErrorCode::Locked => Self::Locked { domain },
+            ErrorCode::InvalidConfig => Self::InvalidConfig(e.message),
+            ErrorCode::Unauthorized => Self::Unauthorized(e.message),
+            ErrorCode::Unreachable => Self::Unreachable {
+                domain,
+                reason: e.message,
+            },
+            ErrorCode::BadRequest | ErrorCode::UnsupportedVersion | ErrorCode::Internal => {
+                Self::Protocol(e.message)
+            }
+        }
+    }
+}
+
+/// Reads one line of at most [`MAX_LINE`] bytes.
+///
+/// Returns `Ok(None)` on a clean end of stream.
+///
+/// # Errors
+///
+/// Returns `InvalidData` if the line is too long or not UTF-8, or any
+/// underlying I/O error.
+pub fn read_line(reader: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut buf = Vec::new();
+    let n = reader
+        .by_ref()
+        .take(MAX_LINE as u64 + 1)
+        .read_until(b'\n', &mut buf)?;
+    if n == 0 {
+        return Ok(None);
+    }
+    if buf.len() > MAX_LINE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "message too long",
+        ));
+    }
+    String::from_utf8(buf)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Writes `message` as one JSON line and flushes.
+///
+/// # Errors
+///
+/// Returns any serialization or I/O error.
+pub fn write_message<T: Serialize>(writer: &mut impl Write, message: &T) -> io::Result<()> {
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    writer.write_all(&line)?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn request_wire_format() {
+        let request = Envelope::new(Request::Register {
+            config: ResolverConfig::new("a.local", "127.0.0.1", 5553),
+            permanent: false,
+            bind: Binding::Connection,
+        });
+        let json = serde_json::to_string(&request).unwrap();
+        assert_eq!(
+            json,
+            r#"{"v":1,"op":"register","config":{"domain":"a.local","nameserver":"127.0.0.1","port":5553,"search_order":1},"permanent":false,"bind":"connection"}"#
+        );
+        let parsed: Envelope<Request> = serde_json::from_str(
+            r#"{"v":1,"op":"register","config":{"domain":"a.local","nameserver":"127.0.0.1","port":5553,"search_order":1}}"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            parsed.body,
+            Request::Register {
+                permanent: false,
+                bind: Binding::Process,
+                ..
+            }
+        ));
+        let parsed: Envelope<Request> = serde_json::from_str(r#"{"v":1,"op":"list"}"#).unwrap();
+        assert_eq!(parsed, Envelope::new(Request::List));
+    }
+
+    #[test]
+    fn response_wire_format() {
+        let ok = Envelope::new(Response::Ok(Reply::Status {
+            status: DomainStatus::ManagedEphemeral {
+                pid: Some(7),
+                alive: true,
+            },
+        }));
+        assert_eq!(
+            serde_json::to_string(&ok).unwrap(),
+            r#"{"v":1,"ok":{"kind":"status","status":{"state":"managed_ephemeral","pid":7,"alive":true}}}"#
+        );
+
+        let err = Envelope::new(Response::Error(RemoteError::from(
+            &ResolverError::NotManaged {
+                domain: "a.local".into(),
+            },
+        )));
+        let json = serde_json::to_string(&err).unwrap();
+        let back: Envelope<Response> = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, err);
+    }
+
+    #[test]
+    fn errors_round_trip() {
+        let cases = [
+            ResolverError::OwnedByOther {
+                domain: "a.local".into(),
+                marker: "# managed by x".into(),
+            },
+            ResolverError::NotOwner {
+                domain: "a.local".into(),
+                pid: Some(3),
+            },
+            ResolverError::Io(io::Error::from(io::ErrorKind::PermissionDenied)),
+            ResolverError::InvalidConfig("bad port".into()),
+            ResolverError::Unauthorized("domain not allowed".into()),
+            ResolverError::DirNotFound {
+                path: "/etc/resolver".into(),
+            },
+            ResolverError::Unreachable {
+                domain: "a.local".into(),
+                reason: "127.0.0.1:5553 UDP timeout".into(),
+            },
+        ];
+        for e in cases {
+            let back = ResolverError::from(RemoteError::from(&e));
+            assert_eq!(std::mem::discriminant(&back), std::mem::discriminant(&e));
+            if !matches!(e, ResolverError::Io(_)) {
+                assert_eq!(back.to_string(), e.to_string());
+            }
+        }
+        let denied = ResolverError::Io(io::Error::from(io::ErrorKind::PermissionDenied));
+        assert!(ResolverError::from(RemoteError::from(&denied)).is_permission_denied());
+    }
+
+    #[test]
+    fn read_line_limits_length() {
+        let mut input = io::Cursor::new(b"{}\n".to_vec());
+        assert_eq!(read_line(&mut input).unwrap().as_deref(), Some("{}\n"));
+        assert_eq!(read_line(&mut input).unwrap(), None);
+
+        let mut long = io::Cursor::new(vec![b'x'; MAX_LINE + 10]);
+        assert_eq!(
+            read_line(&mut long).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+}
@@ -0,0 +1,904 @@
+//! Socket server side of the helper.
+
+use std::collections::BTreeMap;
+use std::io::{self, BufReader};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use super::peer::PeerCredentials;
+use super::policy::Policy;
+use super::protocol::{
+    Binding, Envelope, ErrorCode, PROTOCOL_VERSION, RemoteError, Reply, Request, Response,
+    read_line, write_message,
+};
+use crate::config::validate_domain;
+use crate::error::{ResolverError, Result};
+use crate::file_resolver::FileResolver;
+use crate::status::DomainStatus;
+use crate::util::{bind_unix_socket, wait_readable};
+
+/// Default permissions of the listening socket: owner only.
+const DEFAULT_SOCKET_MODE: u32 = 0o600;
+
+/// How often the accept loop checks whether it has been shut down.
+const STOP_POLL: Duration = Duration::from_millis(100);
+
+/// Default for [`HelperServer::idle_timeout`].
+const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// How long a client may take to read a response.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Default for [`HelperServer::max_connections`].
+const DEFAULT_MAX_CONNECTIONS: usize = 64;
+
+/// Serves [`FileResolver`] operations to [`HelperClient`](super::HelperClient)s.
+///
+/// Each connection is handled on its own thread, up to
+/// [`max_connections`](Self::max_connections) at once. Configs and domains from
+/// clients are validated with [`ResolverConfig::validate`](crate::ResolverConfig::validate)
+/// before touching the filesystem.
+///
+/// Registrations are bound to the client as chosen by its [`Binding`]:
+/// entries record the client's PID, or are removed when its connection
+/// closes.
+pub struct HelperServer {
+    resolver: Arc<FileResolver>,
+    socket_mode: u32,
+    policy: Option<Policy>,
+    /// Connection-bound domains and the session that registered them. The
+    /// lock also serializes registrations so entry limits hold.
+    bound: Mutex<BTreeMap<String, u64>>,
+    next_session: AtomicU64,
+    idle_timeout: Duration,
+    max_connections: usize,
+    /// Connections currently being served.
+    active: AtomicUsize,
+}
+
+/// One client connection.
+struct Session {
+    id: u64,
+    peer: Option<PeerCredentials>,
+}
+
+impl HelperServer {
+    /// Creates a server performing operations through `resolver`.
+    #[must_use]
+    pub const fn new(resolver: Arc<FileResolver>) -> Self {
+        Self {
+            resolver,
+            socket_mode: DEFAULT_SOCKET_MODE,
+            policy: None,
+            bound: Mutex::new(BTreeMap::new()),
+            next_session: AtomicU64::new(0),
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            active: AtomicUsize::new(0),
+        }
+    }
+
+    /// Authorizes every request against `policy` using the client's peer
+    /// credentials. Refused requests fail with
+    /// [`ResolverError::Unauthorized`](crate::ResolverError::Unauthorized).
+    ///
+    /// Under a policy, clients other than root can only re-register or
+    /// unregister entries recorded with their own uid, and cannot touch
+    /// files this resolver does not manage.
+    #[must_use]
+    pub fn policy(mut self, policy: Policy) -> Self {
+        self.policy = Some(policy);
+        self
+    }
+
+    /// Sets the permissions of the socket file (default: `0o600`).
+    ///
+    /// Unprivileged clients can only connect if the mode allows it, e.g.
+    /// `0o666`. Combine a permissive mode with a [`policy`](Self::policy).
+    #[must_use]
+    pub const fn socket_mode(mut self, mode: u32) -> Self {
+        self.socket_mode = mode;
+        self
+    }
+
+    /// Closes connections that send no request for `timeout` (default: 60
+    /// seconds), unless they hold connection-bound registrations.
+    /// [`HelperClient`](super::HelperClient) reconnects transparently.
+    #[must_use]
+    pub const fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// Limits how many connections are served at once (default: 64).
+    /// Connections over the limit get an error response and are closed.
+    #[must_use]
+    pub const fn max_connections(mut self, max: usize) -> Self {
+        self.max_connections = max;
+        self
+    }
+
+    /// Binds `path` and serves connections on the current thread forever.
+    ///
+    /// Failing to accept a client (e.g. out of file descriptors) is logged
+    /// and does not stop the server.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ResolverError::Io`](crate::ResolverError::Io) if the socket cannot be bound.
+    pub fn serve(self, path: impl AsRef<Path>) -> Result<()> {
+        let listener = self.bind(path.as_ref())?;
+        let server = Arc::new(self);
+        loop {
+            match listener.accept() {
+                Ok((stream, _)) => server.spawn_connection(stream),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => {
+                    tracing::warn!(error = %e, "Failed to accept helper client");
+                    // Back off so a persistent error such as EMFILE does
+                    // not spin.
+                    std::thread::sleep(STOP_POLL);
+                }
+            }
+        }
+    }
+
+    /// Binds `path` and serves connections on a background thread until the
+    /// returned handle is shut down or dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ResolverError::Io`](crate::ResolverError::Io) if the socket cannot be bound or the
+    /// thread cannot be spawned.
+    pub fn spawn(self, path: impl AsRef<Path>) -> Result<HelperHandle> {
+        let path = path.as_ref().to_path_buf();
+        let listener = self.bind(&path)?;
+        let server = Arc::new(self);
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = Arc::clone(&stop);
+        // Poll a nonblocking listener so shutdown never depends on being
+        // able to connect to (and thereby wake) the socket.
+        listener.set_nonblocking(true)?;
+        let thread = std::thread::Builder::new()
+            .name("resolver-helper".into())
+            .spawn(move || {
+                while !thread_stop.load(Ordering::Acquire) {
+                    match wait_readable(&listener, STOP_POLL) {
+                        Ok(false) => continue,
+                        Ok(true) => {}
+                        Err(e) => {
+                            tracing::error!(error = %e, "Helper listener failed");
+                            break;
+                        }
+                    }
+                    match listener.accept() {
+                        Ok((stream, _)) => {
+                            // Accepted sockets inherit O_NONBLOCK on macOS.
+                            if let Err(e) = stream.set_nonblocking(false) {
+                                tracing::warn!(error = %e, "Failed to configure helper client");
+                                continue;
+                            }
+                            server.spawn_connection(stream);
+                        }
+                        Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                        Err(e) => tracing::warn!(error = %e, "Failed to accept helper client"),
+                    }
+                }
+            })?;
+        Ok(HelperHandle {
+            path,
+            stop,
+            thread: Some(thread),
+        })
+    }
+
+    /// Binds the socket, replacing a stale socket file left by a crash.
+    fn bind(&self, path: &Path) -> Result<UnixListener> {
+        let listener = bind_unix_socket(path, self.socket_mode)?;
+        tracing::info!(path = %path.display(), "Resolver helper listening");
+        Ok(listener)
+    }
+
+    fn spawn_connection(self: &Arc<Self>, mut stream: UnixStream) {
+        if let Err(e) = stream.set_write_timeout(Some(WRITE_TIMEOUT)) {
+            tracing::warn!(error = %e, "Failed to configure helper client");
+            return;
+        }
+        if self.active.fetch_add(1, Ordering::AcqRel) >= self.max_connections {
+            self.active.fetch_sub(1, Ordering::AcqRel);
+            tracing::warn!(
+                max = self.max_connections,
+                "Refusing helper client: too many connections"
+            );
+            let busy = RemoteError::new(ErrorCode::Internal, "too many connections");
+            let _ = write_message(&mut stream, &Envelope::new(Response::Error(busy)));
+            return;
+        }
+        let server = Arc::clone(self);
+        let spawned = std::thread::Builder::new()
+            .name("resolver-helper-conn".into())
+            .spawn(move || {
+                if let Err(e) = server.handle_connection(stream) {
+                    tracing::debug!(error = %e, "Helper connection ended with error");
+                }
+                server.active.fetch_sub(1, Ordering::AcqRel);
+            });
+        if let Err(e) = spawned {
+            self.active.fetch_sub(1, Ordering::AcqRel);
+            tracing::warn!(error = %e, "Failed to spawn helper connection thread");
+        }
+    }
+
+    fn handle_connection(&self, stream: UnixStream) -> io::Result<()> {
+        let peer = match PeerCredentials::of(&stream) {
+            Ok(peer) => Some(peer),
+            Err(e) => {
+                tracing::warn!(error = %e, "Cannot read helper client credentials");
+                None
+            }
+        };
+        let session = Session {
+            id: self.next_session.fetch_add(1, Ordering::Relaxed),
+            peer,
+        };
+        tracing::debug!(session = session.id, peer = ?peer, "Helper client connected");
+        let result = self.serve_session(&session, stream);
+        self.release(session.id);
+        result
+    }
+
+    fn serve_session(&self, session: &Session, stream: UnixStream) -> io::Result<()> {
+        let mut writer = stream.try_clone()?;
+        let mut reader = BufReader::new(stream);
+        loop {
+            // A connection-bound registration lives as long as the
+            // connection, so only sessions without one may be closed idle.
+            let idle = (!self.holds_bindings(session.id)).then_some(self.idle_timeout);
+            reader.get_ref().set_read_timeout(idle)?;
+            let line = match read_line(&mut reader) {
+                Ok(Some(line)) => line,
+                Ok(None) => break,
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) =>
+                {
+                    tracing::debug!(session = session.id, "Closing idle helper connection");
+                    break;
+                }
+                Err(e) => return Err(e),
+            };
+            let response = self.respond(&line, session);
+            write_message(&mut writer, &Envelope::new(response))?;
+        }
+        Ok(())
+    }
+
+    /// Whether `session` has registered connection-bound entries.
+    fn holds_bindings(&self, session: u64) -> bool {
+        self.bound
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .values()
+            .any(|&owner| owner == session)
+    }
+
+    /// Removes the entries bound to a closed session's connection.
+    fn release(&self, session: u64) {
+        let mut bound = self.bound.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut released = Vec::new();
+        bound.retain(|domain, owner| {
+            if *owner == session {
+                released.push(domain.clone());
+            }
+            *owner != session
+        });
+        for domain in released {
+            match self.resolver.unregister(&domain) {
+                Ok(()) => tracing::info!(%domain, session, "Removed connection-bound entry"),
+                Err(e) => {
+                    tracing::warn!(%domain, session, error = %e, "Failed to remove connection-bound entry");
+                }
+            }
+        }
+        drop(bound);
+    }
+
+    /// Parses one request line and performs it.
+    fn respond(&self, line: &str, session: &Session) -> Response {
+        let request = match serde_json::from_str::<Envelope<Request>>(line) {
+            Ok(request) => request,
+            Err(e) => {
+                // Report a version mismatch even if the body did not parse.
+                let version = serde_json::from_str::<serde_json::Value>(line)
+                    .ok()
+                    .and_then(|v| v.get("v")?.as_u64());
+                return Response::Error(match version {
+                    Some(v) if v != u64::from(PROTOCOL_VERSION) => unsupported(v),
+                    _ => RemoteError::new(ErrorCode::BadRequest, e.to_string()),
+                });
+            }
+        };
+        if request.v != PROTOCOL_VERSION {
+            return Response::Error(unsupported(request.v.into()));
+        }
+        match self.perform(request.body, session) {
+            Ok(reply) => Response::Ok(reply),
+            Err(e) => Response::Error(RemoteError::from(&e)),
+        }
+    }
+
+    fn perform(&self, request: Request, session: &Session) -> Result<Reply> {
+        let peer = session.peer.as_ref();
+        tracing::debug!(request = ?request, session = session.id, "Helper request");
+        let policy = match (&self.policy, peer) {
+            (None, _) => None,
+            (Some(policy), Some(peer)) => Some((policy, peer)),
+            (Some(_), None) => {
+                return Err(ResolverError::Unauthorized("cannot identify client".into()));
+            }
+        };
+        match request {
+            Request::Register {
+                config,
+                permanent,
+                bind,
+            } => {
+                config.validate()?;
+                let mut bound = self.bound.lock().unwrap_or_else(PoisonError::into_inner);
+                if let Some((policy, peer)) = policy {
+                    self.check_owner(peer, &config.domain)?;
+                    let existing = self.entries_of(peer.uid, &config.domain)?;
+                    policy.check_register(peer, &config, permanent, existing)?;
+                }
+                let client_pid = peer.and_then(|p| p.pid);
+                let (pid, on_connection) = match (permanent, bind, client_pid) {
+                    (true, _, _) => (None, false),
+                    (false, Binding::Process, Some(pid)) => (Some(pid), false),
+                    (false, _, _) => (Some(std::process::id()), true),
+                };
+                self.resolver
+                    .register_on_behalf(&config, peer.map(|p| p.uid), pid)?;
+                if on_connection {
+                    bound.insert(config.domain, session.id);
+                } else {
+                    bound.remove(&config.domain);
+                }
+                drop(bound);
+                Ok(Reply::Done)
+            }
+            Request::Unregister { domain } => {
+                validate_domain(&domain)?;
+                if let Some((policy, peer)) = policy {
+                    policy.check_domain(peer, &domain)?;
+                    self.check_owner(peer, &domain)?;
+                }
+                let mut bound = self.bound.lock().unwrap_or_else(PoisonError::into_inner);
+                self.resolver.unregister(&domain)?;
+                bound.remove(&domain);
+                drop(bound);
+                Ok(Reply::Done)
+            }
+            Request::List => {
+                if let Some((policy, peer)) = policy {
+                    policy.check_peer(peer)?;
+                }
+                Ok(Reply::Domains {
+                    domains: self.resolver.list()?,
+                })
+            }
+            Request::Cleanup => {
+                if let Some((policy, peer)) = policy {
+                    policy.check_peer(peer)?;
+                }
+                Ok(Reply::Removed {
+                    count: self.resolver.cleanup_orphaned()?,
+                })
+            }
+            Request::Status { domain } => {
+                validate_domain(&domain)?;
+                if let Some((policy, peer)) = policy {
+                    policy.check_domain(peer, &domain)?;
+                }
+                Ok(Reply::Status {
+                    status: self.resolver.status(&domain)?,
+                })
+            }
+        }
+    }
+
+    /// Refuses unless `domain` has no file yet or is managed and recorded
+    /// with the peer's uid. Root may touch any entry.
+    fn check_owner(&self, peer: &PeerCredentials, domain: &str) -> Result<()> {
+        if peer.uid == 0 {
+            return Ok(());
+        }
+        if let Some(entry) = self.resolver.entry(domain) {
+            return match entry.header.uid {
+                Some(uid) if uid == peer.uid => Ok(()),
+                Some(uid) => Err(ResolverError::Unauthorized(format!(
+                    "{domain} belongs to uid {uid}"
+                ))),
+                None => Err(ResolverError::Unauthorized(format!(
+                    "{domain} has no recorded owner"
+                ))),
+            };
+        }
+        match self.resolver.status(domain)? {
+            DomainStatus::Missing => Ok(()),
+            DomainStatus::ForeignOwner { marker } => Err(ResolverError::OwnedByOther {
+                domain: domain.to_string(),
+                marker,
+            }),
+            _ => Err(ResolverError::NotManaged {
+                domain: domain.to_string(),
+            }),
+        }
+    }
+
+    /// Counts managed entries recorded with `uid`, other than `except`.
+    fn entries_of(&self, uid: u32, except: &str) -> Result<usize> {
+        Ok(self
+            .resolver
+            .entries()?
+            .iter()
+            .filter(|e| e.domain != except && e.header.uid == Some(uid))
+            .count())
+    }
+}
+
+fn unsupported(version: u64) -> RemoteError {
+    RemoteError::new(
+        ErrorCode::UnsupportedVersion,
+        format!("unsupported protocol version {version}, expected {PROTOCOL_VERSION}"),
+    )
+}
+
+/// Handle to a [`HelperServer`] running in the background.
+///
+/// Shutting down (or dropping) stops accepting connections and removes the
+/// socket file. Connections already open are served until the client
+/// disconnects.
+#[derive(Debug)]
+pub struct HelperHandle {
+    path: PathBuf,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl HelperHandle {
+    /// Path of the listening socket.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Stops the server and waits for its accept loop to exit.
+    pub fn shutdown(mut self) {
+        self.stop_accepting();
+    }
+
+    fn stop_accepting(&mut self) {
+        let Some(thread) = self.thread.take() else {
+            return;
+        };
+        self.stop.store(true, Ordering::Release);
+        let _ = thread.join();
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            tracing::debug!(path = %self.path.display(), error = %e, "Failed to remove helper socket");
+        }
+    }
+}
+
+impl Drop for HelperHandle {
+    fn drop(&mut self) {
+        self.stop_accepting();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ResolverError;
+    use crate::config::ResolverConfig;
+    use crate::header::ManagedHeader;
+    use crate::helper::HelperClient;
+    use std::io::{BufRead, Write};
+    use std::os::unix::fs::PermissionsExt;
+
+    struct Fixture {
+        handle: HelperHandle,
+        resolver_dir: tempfile::TempDir,
+        _socket_dir: tempfile::TempDir,
+    }
+
+    fn start() -> Fixture {
+        start_with(|server| server)
+    }
+
+    fn start_with(configure: impl FnOnce(HelperServer) -> HelperServer) -> Fixture {
+        let resolver_dir = tempfile::tempdir().unwrap();
+        let socket_dir = tempfile::Builder::new()
+            .prefix("helper")
+            .tempdir_in("/tmp")
+            .unwrap();
+        let resolver = Arc::new(FileResolver::new("test").dir(resolver_dir.path()));
+        let handle = configure(HelperServer::new(resolver))
+            .spawn(socket_dir.path().join("helper.sock"))
+            .unwrap();
+        Fixture {
+            handle,
+            resolver_dir,
+            _socket_dir: socket_dir,
+        }
+    }
+
+    #[test]
+    fn client_round_trip() {
+        let fx = start();
+        let client = HelperClient::connect(fx.handle.path()).unwrap();
+        let config = ResolverConfig::new("a.local", "127.0.0.1", 5553);
+
+        client.register(&config).unwrap();
+        client
+            .register_permanent(&ResolverConfig::new("b.local", "127.0.0.1", 5553))
+            .unwrap();
+        let mut domains = client.list().unwrap();
+        domains.sort();
+        assert_eq!(domains, ["a.local", "b.local"]);
+        assert_eq!(
+            client.status("b.local").unwrap(),
+            DomainStatus::ManagedPermanent
+        );
+        assert_eq!(client.cleanup_orphaned().unwrap(), 0);
+
+        client.unregister("a.local").unwrap();
+        assert!(!fx.resolver_dir.path().join("a.local").exists());
+        assert_eq!(client.status("a.local").unwrap(), DomainStatus::Missing);
+    }
+
+    #[test]
+    fn errors_are_mapped_back() {
+        let fx = start();
+        std::fs::write(
+            fx.resolver_dir.path().join("c.local"),
+            "nameserver 1.1.1.1\n",
+        )
+        .unwrap();
+        let client = HelperClient::connect(fx.handle.path()).unwrap();
+
+        assert!(matches!(
+            client.unregister("c.local"),
+            Err(ResolverError::NotManaged { domain }) if domain == "c.local"
+        ));
+        assert!(matches!(
+            client.register(&ResolverConfig::new("../escape", "127.0.0.1", 53)),
+            Err(ResolverError::InvalidConfig(_))
+        ));
+        assert!(
+            !fx.resolver_dir
+                .path()
+                .parent()
+                .unwrap()
+                .join("escape")
+                .exists()
+        );
+    }
+
+    #[test]
+    fn rejects_bad_requests_and_versions() {
+        let fx = start();
+        let stream = UnixStream::connect(fx.handle.path()).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut writer = stream;
+        let mut exchange = |line: &str| {
+            writer.write_all(line.as_bytes()).unwrap();
+            let mut reply = String::new();
+            reader.read_line(&mut reply).unwrap();
+            serde_json::from_str::<Envelope<Response>>(&reply).unwrap()
+        };
+
+        let reply = exchange("{\"v\":99,\"op\":\"frobnicate\"}\n");
+        assert_eq!(reply.v, PROTOCOL_VERSION);
+        assert!(matches!(
+            reply.body,
+            Response::Error(RemoteError {
+                code: ErrorCode::UnsupportedVersion,
+                ..
+            })
+        ));
+        let reply = exchange("not json\n");
+        assert!(matches!(
+            reply.body,
+            Response::Error(RemoteError {
+                code: ErrorCode::BadRequest,
+                ..
+            })
+        ));
+        let reply = exchange("{\"v\":1,\"op\":\"list\"}\n");
+        assert_eq!(reply.body, Response::Ok(Reply::Domains { domains: vec![] }));
+    }
+
+    #[test]
+    fn replaces_stale_socket_and_cleans_up() {
+        let socket_dir = tempfile::tempdir_in("/tmp").unwrap();
+        let path = socket_dir.path().join("helper.sock");
+        drop(UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+
+        let resolver_dir = tempfile::tempdir().unwrap();
+        let resolver = Arc::new(FileResolver::new("test").dir(resolver_dir.path()));
+        let handle = HelperServer::new(Arc::clone(&resolver))
+            .socket_mode(0o666)
+            .spawn(&path)
+            .unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o666);
+
+        assert!(HelperServer::new(resolver).spawn(&path).is_err());
+        handle.shutdown();
+        assert!(!path.exists());
+    }
+
+    fn unauthorized<T>(result: crate::Result<T>) -> bool {
+        matches!(result.err(), Some(ResolverError::Unauthorized(_)))
+    }
+
+    #[test]
+    fn policy_restricts_registrations() {
+        let policy = Policy::new()
+            .allow_domain("myapp.local")
+            .allow_nameserver("127.0.0.1".parse().unwrap())
+            .allow_ports(5300..=5399)
+            .max_entries_per_uid(1);
+        let fx = start_with(|server| server.policy(policy));
+        let client = HelperClient::connect(fx.handle.path()).unwrap();
+        let ok = ResolverConfig::new("a.myapp.local", "127.0.0.1", 5353);
+
+        client.register(&ok).unwrap();
+        // Re-registering the same domain does not count against the limit.
+        client.register(&ok).unwrap();
+        let entry = FileResolver::new("test")
+            .dir(fx.resolver_dir.path())
+            .entry("a.myapp.local")
+            .unwrap();
+        assert_eq!(entry.header.uid, Some(crate::util::current_uid()));
+
+        assert!(unauthorized(client.register(&ResolverConfig::new(
+            "b.myapp.local",
+            "127.0.0.1",
+            5353
+        ))));
+        assert!(unauthorized(client.register(&ResolverConfig::new(
+            "evil.com",
+            "127.0.0.1",
+            5353
+        ))));
+        assert!(unauthorized(client.register_permanent(&ok)));
+        assert!(unauthorized(client.unregister("other.com")));
+        assert!(!fx.resolver_dir.path().join("evil.com").exists());
+
+        client.unregister("a.myapp.local").unwrap();
+        client
+            .register(&ResolverConfig::new("b.myapp.local", "127.0.0.1", 5353))
+            .unwrap();
+    }
+
+    #[test]
+    fn policy_refuses_unknown_users() {
+        let me = crate::util::current_uid();
+        let fx = start_with(|server| {
+            server.policy(
+                Policy::new()
+                    .allow_uid(me.wrapping_add(1))
+                    .allow_domain("myapp.local"),
+            )
+        });
+        let client = HelperClient::connect(fx.handle.path()).unwrap();
+        assert!(unauthorized(client.list()));
+        assert!(unauthorized(client.status("myapp.local")));
+    }
+
+    #[test]
+    fn non_root_clients_only_touch_their_own_entries() {
+        let resolver_dir = tempfile::tempdir().unwrap();
+        let dir = resolver_dir.path();
+        let resolver = Arc::new(FileResolver::new("test").dir(dir));
+        let server = HelperServer::new(Arc::clone(&resolver)).policy(
+            Policy::new()
+                .allow_domain("myapp.local")
+                .allow_nameserver("127.0.0.1".parse().unwrap())
+                .allow_ports(5553..=5553),
+        );
+        let session = |uid| Session {
+            id: 0,
+            peer: Some(PeerCredentials {
+                uid,
+                gid: uid,
+                pid: None,
+            }),
+        };
+        let register = |domain: &str| Request::Register {
+            config: ResolverConfig::new(domain, "127.0.0.1", 5553),
+            permanent: false,
+            bind: Binding::Connection,
+        };
+        let unregister = |domain: &str| Request::Unregister {
+            domain: domain.into(),
+        };
+
+        std::fs::write(dir.join("hand.myapp.local"), "nameserver 1.1.1.1\n").unwrap();
+        std::fs::write(
+            dir.join("other.myapp.local"),
+            "# managed by other-tool\nnameserver 1.1.1.1\n",
+        )
+        .unwrap();
+        // Managed, but written before uids were recorded.
+        std::fs::write(
+            dir.join("legacy.myapp.local"),
+            "# managed by test (v=2)\nnameserver 127.0.0.1\n",
+        )
+        .unwrap();
+        assert_eq!(
+            resolver.entry("legacy.myapp.local").unwrap().header.uid,
+            None
+        );
+        server
+            .perform(register("mine.myapp.local"), &session(501))
+            .unwrap();
+
+        for request in [register("hand.myapp.local"), unregister("hand.myapp.local")] {
+            assert!(matches!(
+                server.perform(request, &session(501)),
+                Err(ResolverError::NotManaged { .. })
+            ));
+        }
+        for request in [
+            register("other.myapp.local"),
+            unregister("other.myapp.local"),
+        ] {
+            assert!(matches!(
+                server.perform(request, &session(501)),
+                Err(ResolverError::OwnedByOther { .. })
+            ));
+        }
+        for request in [
+            register("legacy.myapp.local"),
+            unregister("legacy.myapp.local"),
+            register("mine.myapp.local"),
+            unregister("mine.myapp.local"),
+        ] {
+            assert!(unauthorized(server.perform(request, &session(502))));
+        }
+        assert_eq!(
+            std::fs::read_to_string(dir.join("hand.myapp.local")).unwrap(),
+            "nameserver 1.1.1.1\n"
+        );
+        assert!(dir.join("other.myapp.local").exists());
+        assert!(dir.join("legacy.myapp.local").exists());
+
+        server
+            .perform(unregister("mine.myapp.local"), &session(501))
+            .unwrap();
+        server
+            .perform(unregister("legacy.myapp.local"), &session(0))
+            .unwrap();
+    }
+
+    #[test]
+    fn status_is_limited_to_allowed_domains() {
+        let fx = start_with(|server| server.policy(Policy::new().allow_domain("myapp.local")));
+        let client = HelperClient::connect(fx.handle.path()).unwrap();
+        assert_eq!(
+            client.status("a.myapp.local").unwrap(),
+            DomainStatus::Missing
+        );
+        assert!(unauthorized(client.status("example.com")));
+    }
+
+    fn wait_until(mut done: impl FnMut() -> bool) -> bool {
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while !done() {
+            if std::time::Instant::now() > deadline {
+                return false;
+            }
+            std::thread::sleep(Duration::from_millis(20));
+        }
+        true
+    }
+
+    #[test]
+    fn idle_connections_are_closed_and_clients_reconnect() {
+        let fx = start_with(|server| server.idle_timeout(Duration::from_millis(100)));
+        let client = HelperClient::connect(fx.handle.path()).unwrap();
+        let bound = HelperClient::connect(fx.handle.path()).unwrap();
+        bound
+            .register_for_connection(&ResolverConfig::new("a.local", "127.0.0.1", 5553))
+            .unwrap();
+        assert!(client.list().unwrap().contains(&"a.local".to_string()));
+
+        // A client that never sends a request is disconnected.
+        let mut silent = UnixStream::connect(fx.handle.path()).unwrap();
+        silent
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        assert_eq!(io::Read::read(&mut silent, &mut [0; 1]).unwrap(), 0);
+
+        // The idle client reconnects; the connection-bound entry survives.
+        assert_eq!(client.list().unwrap(), ["a.local"]);
+    }
+
+    #[test]
+    fn connections_over_the_limit_are_refused() {
+        let fx = start_with(|server| server.max_connections(1));
+        let first = HelperClient::connect(fx.handle.path()).unwrap();
+        assert!(first.list().unwrap().is_empty());
+
+        let second = HelperClient::connect(fx.handle.path()).unwrap();
+        let err = second.list().unwrap_err();
+        assert!(err.to_string().contains("too many connections"), "{err}");
+
+        drop(first);
+        assert!(wait_until(|| second.list().is_ok()));
+    }
+
+    #[test]
+    fn connection_bound_entries_removed_on_disconnect() {
+        let fx = start();
+        let first = HelperClient::connect(fx.handle.path()).unwrap();
+        let second = HelperClient::connect(fx.handle.path()).unwrap();
+        let a = ResolverConfig::new("a.local", "127.0.0.1", 5553);
+        let b = ResolverConfig::new("b.local", "127.0.0.1", 5553);
+
+        first.register_for_connection(&a).unwrap();
+        first.register_for_connection(&b).unwrap();
+        // Re-registering through another binding takes the entry over.
+        second.register(&b).unwrap();
+        drop(first);
+
+        let dir = fx.resolver_dir.path();
+        assert!(wait_until(|| !dir.join("a.local").exists()));
+        assert!(dir.join("b.local").exists());
+        assert_eq!(second.list().unwrap(), ["b.local"]);
+    }
+
+    #[test]
+    fn process_bound_entries_record_client_pid() {
+        let resolver_dir = tempfile::tempdir().unwrap();
+        // Pid 4242 stands in for a client that has exited.
+        let exited = 4242;
+        let resolver = Arc::new(
+            FileResolver::new("test")
+                .dir(resolver_dir.path())
+                .liveness(move |h: &ManagedHeader| h.pid != Some(exited)),
+        );
+        let server = HelperServer::new(Arc::clone(&resolver));
+        let session = Session {
+            id: 0,
+            peer: Some(PeerCredentials {
+                uid: crate::util::current_uid(),
+                gid: 0,
+                pid: Some(exited),
+            }),
+        };
+        let register = Request::Register {
+            config: ResolverConfig::new("a.local", "127.0.0.1", 5553),
+            permanent: false,
+            bind: Binding::Process,
+        };
+
+        server.perform(register, &session).unwrap();
+        let header = resolver.entry("a.local").unwrap().header;
+        assert_eq!(header.pid, Some(exited));
+        assert_eq!(header.lock, None);
+
+        // The client is gone, so its entry is orphaned.
+        assert_eq!(resolver.cleanup_orphaned().unwrap(), 1);
+        assert!(!resolver_dir.path().join("a.local").exists());
+    }
+}
@@ -56,11 +56,52 @@
 #![warn(clippy::all, clippy::pedantic, clippy::nursery)]
 #![allow(clippy::module_name_repetitions)]
 
+#[cfg(feature = "admin")]
+pub mod admin;
+#[cfg(feature = "tokio")]
+pub mod async_resolver;
+#[cfg(feature = "capi")]
+pub mod capi;
+pub mod clock;
 pub mod config;
+pub mod dns;
+#[cfg(feature = "dns-proxy")]
+pub mod dns_proxy;
+#[cfg(feature = "dns-server")]
+pub mod dns_server;
+pub mod drift;
 pub mod error;
 pub mod file_resolver;
+pub mod header;
+#[cfg(feature = "helper")]
+pub mod helper;
+pub mod liveness;
+pub mod mobileconfig;
+pub mod preflight;
+pub mod status;
 pub mod util;
+pub mod watch;
+pub mod watchdog;
 
-pub use config::ResolverConfig;
+#[cfg(feature = "admin")]
+pub use admin::{AdminAddr, AdminHandle, AdminServer};
+#[cfg(feature = "tokio")]
+pub use async_resolver::AsyncFileResolver;
+pub use clock::{Clock, ManualClock, SystemClock};
+pub use config::{ReservedPort, ResolverConfig};
+#[cfg(feature = "dns-proxy")]
+pub use dns_proxy::{DnsProxy, DnsProxyHandle};
+#[cfg(feature = "dns-server")]
+pub use dns_server::{DnsServer, DnsServerHandle, Records};
+pub use drift::{DriftField, DriftReport, FieldDrift};
 pub use error::{ResolverError, Result};
-pub use file_resolver::{FileResolver, to_env_prefix};
+pub use file_resolver::{ChangeReport, FileResolver, ManagedEntry, to_env_prefix};
+pub use header::{FORMAT_VERSION, ManagedHeader};
+#[cfg(feature = "helper")]
+pub use helper::{HelperClient, HelperHandle, HelperServer, PeerCredentials, Policy};
+pub use liveness::{DefaultLiveness, Liveness};
+pub use mobileconfig::{EncryptedDnsConfig, EncryptedTransport, MobileConfig};
+pub use preflight::{Preflight, PreflightReport, ProbeReport, Reachability};
+pub use status::DomainStatus;
+pub use watch::{ChangeEvent, ChangeKind, Changes, DirWatcher, Owner};
+pub use watchdog::{RepairEvent, Watchdog, WatchdogHandle};
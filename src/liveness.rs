@@ -0,0 +1,80 @@
+//! Owner liveness checks for orphan cleanup.
+
+use crate::header::ManagedHeader;
+use crate::util::{is_lock_held, is_process_alive};
+
+/// Decides whether the owner recorded in a managed file is still alive.
+///
+/// [`FileResolver::cleanup_orphaned`](crate::FileResolver::cleanup_orphaned)
+/// consults this for every unexpired, non-permanent managed file and removes
+/// the file when it returns `false`. Supply a custom checker with
+/// [`FileResolver::liveness`](crate::FileResolver::liveness) when the owner
+/// is better identified by something other than a PID.
+///
+/// Any `Fn(&ManagedHeader) -> bool` closure implements this trait.
+///
+/// # Example
+///
+/// ```
+/// use macos_resolver::FileResolver;
+/// use macos_resolver::header::ManagedHeader;
+///
+/// // Treat every owner as gone.
+/// let resolver = FileResolver::new("myapp").liveness(|_: &ManagedHeader| false);
+/// ```
+pub trait Liveness: Send + Sync {
+    /// Returns `true` if the owner described by `header` is still alive.
+    fn is_alive(&self, header: &ManagedHeader) -> bool;
+}
+
+impl<F> Liveness for F
+where
+    F: Fn(&ManagedHeader) -> bool + Send + Sync,
+{
+    fn is_alive(&self, header: &ManagedHeader) -> bool {
+        self(header)
+    }
+}
+
+/// The built-in checker.
+///
+/// If the header names a lock file, the owner is alive while some process
+/// holds its `flock`. Otherwise it is alive while the recorded PID exists
+/// (`kill(pid, 0)`). Headers with neither are always alive.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultLiveness;
+
+impl Liveness for DefaultLiveness {
+    fn is_alive(&self, header: &ManagedHeader) -> bool {
+        if let Some(lock) = &header.lock {
+            return is_lock_held(lock);
+        }
+        header.pid.is_none_or(is_process_alive)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_checks_pid() {
+        let alive = ManagedHeader {
+            pid: Some(std::process::id()),
+            ..ManagedHeader::default()
+        };
+        assert!(DefaultLiveness.is_alive(&alive));
+        assert!(DefaultLiveness.is_alive(&ManagedHeader::default()));
+    }
+
+    #[test]
+    fn default_prefers_lock_over_pid() {
+        let dir = tempfile::tempdir().unwrap();
+        let header = ManagedHeader {
+            pid: Some(std::process::id()),
+            lock: Some(dir.path().join("free.lock")),
+            ..ManagedHeader::default()
+        };
+        assert!(!DefaultLiveness.is_alive(&header));
+    }
+}
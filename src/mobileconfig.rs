@@ -0,0 +1,471 @@
+//! Encrypted DNS configuration profile (`.mobileconfig`) generation.
+//!
+//! `/etc/resolver` files can only route a domain to a plain-text DNS server.
+//! Per-domain DNS-over-HTTPS / DNS-over-TLS routing requires a
+//! `com.apple.dnsSettings.managed` configuration profile whose
+//! `SupplementalMatchDomains` restrict the encrypted resolver to specific
+//! domain suffixes. This module renders such profiles as XML property lists.
+//!
+//! Payload UUIDs are derived from the marker and the domain, so generating
+//! the same profile twice yields byte-identical output and installing it
+//! again replaces the previous profile instead of adding a second one.
+
+use crate::config::ResolverConfig;
+use crate::error::{ResolverError, Result};
+use std::fmt::Write as _;
+use std::io::Write as _;
+
+/// Encrypted transport used to reach the nameserver.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EncryptedTransport {
+    /// DNS-over-HTTPS with the given `https://` server URL.
+    Https {
+        /// Server URL, e.g. `"https://127.0.0.1:5553/dns-query"`.
+        url: String,
+    },
+    /// DNS-over-TLS, authenticating the server by name.
+    Tls {
+        /// TLS server name, e.g. `"dns.myapp.local"`.
+        server_name: String,
+    },
+}
+
+/// A [`ResolverConfig`] extended with an encrypted transport.
+///
+/// The domain becomes the profile's match domain and the nameserver its
+/// server address. The config's port is not expressible in a profile: for
+/// `DoH` it belongs in the URL, and `DoT` always uses port 853.
+///
+/// # Example
+///
+/// ```
+/// use macos_resolver::ResolverConfig;
+/// use macos_resolver::mobileconfig::EncryptedDnsConfig;
+///
+/// let entry = EncryptedDnsConfig::https(
+///     ResolverConfig::new("myapp.local", "127.0.0.1", 5553),
+///     "https://127.0.0.1:5553/dns-query",
+/// );
+/// assert_eq!(entry.config.domain, "myapp.local");
+/// ```
+#[derive(Debug, Clone)]
+pub struct EncryptedDnsConfig {
+    /// Domain and nameserver address.
+    pub config: ResolverConfig,
+
+    /// Encrypted transport settings.
+    pub transport: EncryptedTransport,
+}
+
+impl EncryptedDnsConfig {
+    /// Creates a DNS-over-HTTPS entry.
+    #[must_use]
+    pub fn https(config: ResolverConfig, url: impl Into<String>) -> Self {
+        Self {
+            config,
+            transport: EncryptedTransport::Https { url: url.into() },
+        }
+    }
+
+    /// Creates a DNS-over-TLS entry.
+    #[must_use]
+    pub fn tls(config: ResolverConfig, server_name: impl Into<String>) -> Self {
+        Self {
+            config,
+            transport: EncryptedTransport::Tls {
+                server_name: server_name.into(),
+            },
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.config.domain.is_empty() {
+            return Err(ResolverError::InvalidConfig("empty domain".into()));
+        }
+        match &self.transport {
+            EncryptedTransport::Https { url } if !url.starts_with("https://") => Err(
+                ResolverError::InvalidConfig(format!("DoH URL must use https://: {url}")),
+            ),
+            EncryptedTransport::Tls { server_name } if server_name.is_empty() => {
+                Err(ResolverError::InvalidConfig("empty DoT server name".into()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Builder for an encrypted DNS configuration profile.
+///
+/// # Example
+///
+/// ```
+/// use macos_resolver::ResolverConfig;
+/// use macos_resolver::mobileconfig::{EncryptedDnsConfig, MobileConfig};
+///
+/// let xml = MobileConfig::new("# managed by myapp")
+///     .display_name("MyApp DNS")
+///     .entry(EncryptedDnsConfig::tls(
+///         ResolverConfig::new("myapp.local", "127.0.0.1", 853),
+///         "dns.myapp.local",
+///     ))
+///     .to_xml()
+///     .unwrap();
+///
+/// assert!(xml.contains("com.apple.dnsSettings.managed"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct MobileConfig {
+    marker: String,
+    identifier: String,
+    display_name: String,
+    organization: Option<String>,
+    entries: Vec<EncryptedDnsConfig>,
+}
+
+impl MobileConfig {
+    /// Creates an empty profile whose identifiers and UUIDs derive from `marker`.
+    ///
+    /// Use the same marker as the [`FileResolver`](crate::FileResolver) that
+    /// manages the plain-text entries so both are attributed to one owner.
+    #[must_use]
+    pub fn new(marker: &str) -> Self {
+        let slug: String = marker
+            .trim_start_matches('#')
+            .trim()
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() {
+                    c.to_ascii_lowercase()
+                } else {
+                    '-'
+                }
+            })
+            .collect();
+        Self {
+            marker: marker.to_string(),
+            identifier: format!("macos-resolver.{slug}"),
+            display_name: "Encrypted DNS".to_string(),
+            organization: None,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Overrides the reverse-DNS profile identifier.
+    #[must_use]
+    pub fn identifier(mut self, identifier: impl Into<String>) -> Self {
+        self.identifier = identifier.into();
+        self
+    }
+
+    /// Sets the name shown in System Settings → Profiles.
+    #[must_use]
+    pub fn display_name(mut self, name: impl Into<String>) -> Self {
+        self.display_name = name.into();
+        self
+    }
+
+    /// Sets the organization shown alongside the profile.
+    #[must_use]
+    pub fn organization(mut self, organization: impl Into<String>) -> Self {
+        self.organization = Some(organization.into());
+        self
+    }
+
+    /// Adds one encrypted resolver payload, replacing an earlier entry for
+    /// the same domain: macOS rejects profiles whose payloads share an
+    /// identifier.
+    #[must_use]
+    pub fn entry(mut self, entry: EncryptedDnsConfig) -> Self {
+        let key =
+            |e: &EncryptedDnsConfig| e.config.domain.trim_end_matches('.').to_ascii_lowercase();
+        let domain = key(&entry);
+        match self.entries.iter_mut().find(|e| key(e) == domain) {
+            Some(existing) => *existing = entry,
+            None => self.entries.push(entry),
+        }
+        self
+    }
+
+    /// Returns the top-level `PayloadUUID` of this profile.
+    #[must_use]
+    pub fn uuid(&self) -> String {
+        stable_uuid(&self.marker)
+    }
+
+    /// Renders the profile as an unsigned XML property list.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ResolverError::InvalidConfig`] if the profile has no entries
+    /// or an entry has an empty domain, a non-`https://` `DoH` URL, or an empty
+    /// `DoT` server name.
+    pub fn to_xml(&self) -> Result<String> {
+        if self.entries.is_empty() {
+            return Err(ResolverError::InvalidConfig(
+                "profile has no entries".into(),
+            ));
+        }
+        for entry in &self.entries {
+            entry.validate()?;
+        }
+
+        let mut out = String::new();
+        out.push_str(concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" ",
+            "\"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n",
+            "<plist version=\"1.0\">\n<dict>\n",
+        ));
+        out.push_str("\t<key>PayloadContent</key>\n\t<array>\n");
+        for entry in &self.entries {
+            self.write_payload(&mut out, entry);
+        }
+        out.push_str("\t</array>\n");
+        push_string(&mut out, 1, "PayloadDisplayName", &self.display_name);
+        push_string(&mut out, 1, "PayloadIdentifier", &self.identifier);
+        if let Some(org) = &self.organization {
+            push_string(&mut out, 1, "PayloadOrganization", org);
+        }
+        push_string(&mut out, 1, "PayloadType", "Configuration");
+        push_string(&mut out, 1, "PayloadUUID", &self.uuid());
+        out.push_str("\t<key>PayloadVersion</key>\n\t<integer>1</integer>\n");
+        out.push_str("</dict>\n</plist>\n");
+        Ok(out)
+    }
+
+    /// Renders the profile and signs it with a keychain identity.
+    ///
+    /// Runs `security cms -S -N <identity>`, so this only works on macOS
+    /// with the identity's certificate and private key in the keychain.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of [`to_xml`](Self::to_xml),
+    /// [`ResolverError::Io`] if `security` cannot be run, or
+    /// [`ResolverError::Signing`] if it exits unsuccessfully.
+    pub fn sign(&self, identity: &str) -> Result<Vec<u8>> {
+        let xml = self.to_xml()?;
+        let mut child = std::process::Command::new("/usr/bin/security")
+            .args(["cms", "-S", "-N", identity])
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .spawn()?;
+        // Feed stdin from its own thread while the output is drained, so a
+        // profile larger than the pipe buffer cannot deadlock with the tool.
+        let stdin = child.stdin.take();
+        let (output, written) = std::thread::scope(|scope| {
+            let writer =
+                scope.spawn(|| stdin.map_or(Ok(()), |mut stdin| stdin.write_all(xml.as_bytes())));
+            let output = child.wait_with_output();
+            let written = writer
+                .join()
+                .unwrap_or_else(|_| Err(std::io::Error::other("stdin writer panicked")));
+            (output, written)
+        });
+        let output = output?;
+        if !output.status.success() {
+            return Err(ResolverError::Signing(
+                String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            ));
+        }
+        written?;
+        Ok(output.stdout)
+    }
+
+    fn write_payload(&self, out: &mut String, entry: &EncryptedDnsConfig) {
+        let domain = &entry.config.domain;
+        out.push_str("\t\t<dict>\n");
+        out.push_str("\t\t\t<key>DNSSettings</key>\n\t\t\t<dict>\n");
+        match &entry.transport {
+            EncryptedTransport::Https { url } => {
+                push_string(out, 4, "DNSProtocol", "HTTPS");
+                push_string(out, 4, "ServerURL", url);
+            }
+            EncryptedTransport::Tls { server_name } => {
+                push_string(out, 4, "DNSProtocol", "TLS");
+                push_string(out, 4, "ServerName", server_name);
+            }
+        }
+        push_array(out, 4, "ServerAddresses", &entry.config.nameserver);
+        push_array(out, 4, "SupplementalMatchDomains", domain);
+        out.push_str("\t\t\t</dict>\n");
+        push_string(out, 3, "PayloadDisplayName", domain);
+        push_string(
+            out,
+            3,
+            "PayloadIdentifier",
+            &format!("{}.dns.{domain}", self.identifier),
+        );
+        push_string(out, 3, "PayloadType", "com.apple.dnsSettings.managed");
+        push_string(
+            out,
+            3,
+            "PayloadUUID",
+            &stable_uuid(&format!("{}\n{domain}", self.marker)),
+        );
+        out.push_str("\t\t\t<key>PayloadVersion</key>\n\t\t\t<integer>1</integer>\n");
+        out.push_str("\t\t</dict>\n");
+    }
+}
+
+fn push_string(out: &mut String, depth: usize, key: &str, value: &str) {
+    let indent = "\t".repeat(depth);
+    let _ = writeln!(
+        out,
+        "{indent}<key>{key}</key>\n{indent}<string>{}</string>",
+        xml_escape(value)
+    );
+}
+
+fn push_array(out: &mut String, depth: usize, key: &str, value: &str) {
+    let indent = "\t".repeat(depth);
+    let _ = writeln!(
+        out,
+        "{indent}<key>{key}</key>\n{indent}<array>\n{indent}\t<string>{}</string>\n{indent}</array>",
+        xml_escape(value)
+    );
+}
+
+fn xml_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Derives a name-based UUID (version 8) from the FNV-1a 128-bit hash of `name`.
+fn stable_uuid(name: &str) -> String {
+    const OFFSET: u128 = 0x6c62_272e_07bb_0142_62b8_2175_6295_c58d;
+    const PRIME: u128 = 0x0000_0000_0100_0000_0000_0000_0000_013b;
+
+    let mut hash = OFFSET;
+    for b in name.bytes() {
+        hash ^= u128::from(b);
+        hash = hash.wrapping_mul(PRIME);
+    }
+    // Version 8 (custom) and RFC 4122 variant bits.
+    hash = (hash & !(0xf << 76)) | (0x8 << 76);
+    hash = (hash & !(0x3 << 62)) | (0x2 << 62);
+
+    let hex = format!("{hash:032X}");
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doh_entry() -> EncryptedDnsConfig {
+        EncryptedDnsConfig::https(
+            ResolverConfig::new("myapp.local", "127.0.0.1", 5553),
+            "https://127.0.0.1:5553/dns-query",
+        )
+    }
+
+    #[test]
+    fn renders_doh_payload() {
+        let xml = MobileConfig::new("# managed by myapp")
+            .entry(doh_entry())
+            .to_xml()
+            .unwrap();
+
+        assert!(xml.contains("<string>com.apple.dnsSettings.managed</string>"));
+        assert!(xml.contains("<key>DNSProtocol</key>\n\t\t\t\t<string>HTTPS</string>"));
+        assert!(xml.contains("<string>https://127.0.0.1:5553/dns-query</string>"));
+        assert!(xml.contains("<key>SupplementalMatchDomains</key>"));
+        assert!(xml.contains("<string>myapp.local</string>"));
+        assert!(xml.contains("<string>macos-resolver.managed-by-myapp</string>"));
+    }
+
+    #[test]
+    fn renders_dot_payload() {
+        let xml = MobileConfig::new("# managed by myapp")
+            .entry(EncryptedDnsConfig::tls(
+                ResolverConfig::new("myapp.local", "10.0.0.1", 853),
+                "dns.myapp.local",
+            ))
+            .to_xml()
+            .unwrap();
+
+        assert!(xml.contains("<string>TLS</string>"));
+        assert!(xml.contains("<key>ServerName</key>\n\t\t\t\t<string>dns.myapp.local</string>"));
+        assert!(xml.contains("<string>10.0.0.1</string>"));
+        assert!(!xml.contains("ServerURL"));
+    }
+
+    #[test]
+    fn repeated_domain_replaces_entry() {
+        let xml = MobileConfig::new("# managed by myapp")
+            .entry(doh_entry())
+            .entry(EncryptedDnsConfig::tls(
+                ResolverConfig::new("MyApp.local.", "10.0.0.1", 853),
+                "dns.myapp.local",
+            ))
+            .to_xml()
+            .unwrap();
+
+        assert_eq!(xml.matches("<key>DNSSettings</key>").count(), 1);
+        assert!(xml.contains("<string>TLS</string>"));
+        assert!(!xml.contains("ServerURL"));
+    }
+
+    #[test]
+    fn regeneration_is_idempotent() {
+        let a = MobileConfig::new("# managed by myapp").entry(doh_entry());
+        let b = MobileConfig::new("# managed by myapp").entry(doh_entry());
+        assert_eq!(a.to_xml().unwrap(), b.to_xml().unwrap());
+
+        let other = MobileConfig::new("# managed by otherapp").entry(doh_entry());
+        assert_ne!(a.uuid(), other.uuid());
+    }
+
+    #[test]
+    fn uuid_has_version_and_variant() {
+        let uuid = stable_uuid("# managed by myapp");
+        assert_eq!(uuid.len(), 36);
+        assert_eq!(&uuid[14..15], "8");
+        assert!(matches!(&uuid[19..20], "8" | "9" | "A" | "B"));
+    }
+
+    #[test]
+    fn rejects_invalid_entries() {
+        assert!(MobileConfig::new("# managed by myapp").to_xml().is_err());
+
+        let plain_http = EncryptedDnsConfig::https(
+            ResolverConfig::new("myapp.local", "127.0.0.1", 5553),
+            "http://127.0.0.1/dns-query",
+        );
+        assert!(
+            MobileConfig::new("# managed by myapp")
+                .entry(plain_http)
+                .to_xml()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn escapes_xml() {
+        let xml = MobileConfig::new("# managed by myapp")
+            .display_name("Tom & Jerry <DNS>")
+            .entry(doh_entry())
+            .to_xml()
+            .unwrap();
+        assert!(xml.contains("Tom &amp; Jerry &lt;DNS&gt;"));
+    }
+}
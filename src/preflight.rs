@@ -0,0 +1,515 @@
+//! Nameserver reachability checks.
+//!
+//! Registering a domain whose nameserver is not listening makes every lookup
+//! under it hang until macOS gives up. [`Preflight`] sends a real query (SOA
+//! for the domain) to `nameserver:port` first and reports whether anything
+//! answered. Set it on a [`FileResolver`](crate::FileResolver) with
+//! [`preflight`](crate::FileResolver::preflight) to make registration fail
+//! fast instead, and use [`FileResolver::probe`](crate::FileResolver::probe)
+//! to confirm a registered domain actually resolves.
+//!
+//! ```no_run
+//! use macos_resolver::{Preflight, ResolverConfig};
+//!
+//! let config = ResolverConfig::new("myapp.local", "127.0.0.1", 5553);
+//! let report = Preflight::new().tcp(true).check(&config)?;
+//! if !report.is_ok() {
+//!     eprintln!("{}: {report}", config.domain);
+//! }
+//! # Ok::<(), macos_resolver::ResolverError>(())
+//! ```
+
+use std::fmt;
+use std::io;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::config::ResolverConfig;
+use crate::dns::{Message, Rcode, Record, RecordType, exchange_tcp, exchange_udp, in_zone};
+use crate::error::{ResolverError, Result};
+
+/// Default time to wait for an answer on each transport.
+pub const DEFAULT_PREFLIGHT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Outcome of querying a nameserver over one transport.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reachability {
+    /// The nameserver answered. Any response code counts: even `REFUSED`
+    /// shows a DNS server is listening.
+    Responsive {
+        /// Response code of the answer.
+        rcode: Rcode,
+        /// Time from sending the query to receiving the answer.
+        latency: Duration,
+    },
+    /// Nothing is listening on the port (connection refused or ICMP port
+    /// unreachable).
+    Refused,
+    /// No answer arrived within the timeout.
+    Timeout,
+}
+
+impl Reachability {
+    /// Returns `true` if the nameserver answered.
+    #[must_use]
+    pub const fn is_responsive(&self) -> bool {
+        matches!(self, Self::Responsive { .. })
+    }
+}
+
+impl fmt::Display for Reachability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Responsive { rcode, latency } => {
+                write!(f, "responsive ({rcode:?} in {}ms)", latency.as_millis())
+            }
+            Self::Refused => f.write_str("refused"),
+            Self::Timeout => f.write_str("timeout"),
+        }
+    }
+}
+
+/// Result of a [`Preflight::check`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreflightReport {
+    /// Address that was queried.
+    pub addr: SocketAddr,
+    /// Outcome over UDP.
+    pub udp: Reachability,
+    /// Outcome over TCP, if [`Preflight::tcp`] was enabled.
+    pub tcp: Option<Reachability>,
+}
+
+impl PreflightReport {
+    /// Returns `true` if every checked transport answered.
+    #[must_use]
+    pub fn is_ok(&self) -> bool {
+        self.udp.is_responsive() && self.tcp.is_none_or(|tcp| tcp.is_responsive())
+    }
+}
+
+impl fmt::Display for PreflightReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} UDP {}", self.addr, self.udp)?;
+        if let Some(tcp) = self.tcp {
+            write!(f, ", TCP {tcp}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Answer to a [`Preflight::probe`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProbeReport {
+    /// Address that was queried.
+    pub addr: SocketAddr,
+    /// Name that was looked up.
+    pub name: String,
+    /// Response code of the answer.
+    pub rcode: Rcode,
+    /// Records in the answer section.
+    pub answers: Vec<Record>,
+    /// Time from sending the query to receiving the final answer.
+    pub latency: Duration,
+    /// Whether the answer came over TCP after a truncated UDP answer.
+    pub tcp: bool,
+}
+
+impl ProbeReport {
+    /// Returns `true` if the nameserver answered `NOERROR` with records.
+    #[must_use]
+    pub fn is_resolving(&self) -> bool {
+        self.rcode == Rcode::NoError && !self.answers.is_empty()
+    }
+}
+
+impl fmt::Display for ProbeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} answered {:?} for {} with {} record(s) in {}ms",
+            self.addr,
+            self.rcode,
+            self.name,
+            self.answers.len(),
+            self.latency.as_millis()
+        )
+    }
+}
+
+/// Checks that a config's nameserver answers DNS queries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Preflight {
+    timeout: Duration,
+    tcp: bool,
+}
+
+impl Default for Preflight {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Preflight {
+    /// Creates a UDP-only check with a one-second timeout.
+    #[must_use]
+    pub const fn new() -> Self {
+        Self {
+            timeout: DEFAULT_PREFLIGHT_TIMEOUT,
+            tcp: false,
+        }
+    }
+
+    /// Sets how long to wait for an answer on each transport.
+    #[must_use]
+    pub const fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Also queries over TCP, which clients fall back to for large answers.
+    #[must_use]
+    pub const fn tcp(mut self, tcp: bool) -> Self {
+        self.tcp = tcp;
+        self
+    }
+
+    /// Sends an SOA query for `config.domain` to its nameserver and port.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ResolverError::InvalidConfig`] if the nameserver is not an
+    /// IP address or the domain cannot be encoded, or [`ResolverError::Io`]
+    /// for network failures other than a refusal or timeout (e.g. no route
+    /// to the host).
+    pub fn check(&self, config: &ResolverConfig) -> Result<PreflightReport> {
+        let addr = config.socket_addr()?;
+        let query = Message::query(query_id(), &config.domain, RecordType::Soa)
+            .encode()
+            .map_err(|e| {
+                ResolverError::InvalidConfig(format!("cannot query {}: {e}", config.domain))
+            })?;
+        let udp = classify(|| exchange_udp(addr, &query, self.timeout))?;
+        let tcp = if self.tcp {
+            Some(classify(|| exchange_tcp(addr, &query, self.timeout))?)
+        } else {
+            None
+        };
+        let report = PreflightReport { addr, udp, tcp };
+        tracing::debug!(domain = %config.domain, %report, "Nameserver preflight");
+        Ok(report)
+    }
+
+    /// Looks up `name` (`rtype` records) at the config's nameserver, as macOS
+    /// would for a name under the domain. Truncated UDP answers are retried
+    /// over TCP.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ResolverError::InvalidConfig`] if `name` is not in
+    /// `config.domain` or cannot be encoded, or the nameserver is not an IP
+    /// address; [`ResolverError::Unreachable`] if the nameserver refused or
+    /// did not answer; or [`ResolverError::Io`] for other network failures.
+    pub fn probe(
+        &self,
+        config: &ResolverConfig,
+        name: &str,
+        rtype: RecordType,
+    ) -> Result<ProbeReport> {
+        if !in_zone(name, &config.domain) {
+            return Err(ResolverError::InvalidConfig(format!(
+                "{name} is not in {}",
+                config.domain
+            )));
+        }
+        let addr = config.socket_addr()?;
+        let query = Message::query(query_id(), name, rtype)
+            .encode()
+            .map_err(|e| ResolverError::InvalidConfig(format!("cannot query {name}: {e}")))?;
+        let unreachable = |transport, outcome: Reachability| ResolverError::Unreachable {
+            domain: config.domain.clone(),
+            reason: format!("{addr} {transport} {outcome}"),
+        };
+
+        let started = Instant::now();
+        let mut tcp = false;
+        let mut reply = match exchange_timed(|| exchange_udp(addr, &query, self.timeout))? {
+            (_, Some(reply)) => reply,
+            (outcome, None) => return Err(unreachable("UDP", outcome)),
+        };
+        if reply.truncated {
+            tcp = true;
+            reply = match exchange_timed(|| exchange_tcp(addr, &query, self.timeout))? {
+                (_, Some(reply)) => reply,
+                (outcome, None) => return Err(unreachable("TCP", outcome)),
+            };
+        }
+        let report = ProbeReport {
+            addr,
+            name: name.to_string(),
+            rcode: reply.rcode,
+            answers: reply.answers,
+            latency: started.elapsed(),
+            tcp,
+        };
+        tracing::debug!(domain = %config.domain, %report, "Resolution probe");
+        Ok(report)
+    }
+
+    /// Like [`check`](Self::check), but fails unless every checked transport
+    /// answered.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ResolverError::Unreachable`] if the nameserver did not
+    /// answer, otherwise as [`check`](Self::check).
+    pub fn ensure(&self, config: &ResolverConfig) -> Result<PreflightReport> {
+        let report = self.check(config)?;
+        if report.is_ok() {
+            Ok(report)
+        } else {
+            Err(ResolverError::Unreachable {
+                domain: config.domain.clone(),
+                reason: report.to_string(),
+            })
+        }
+    }
+}
+
+/// Runs one exchange and sorts its outcome into a [`Reachability`].
+fn classify(exchange: impl FnOnce() -> io::Result<Vec<u8>>) -> Result<Reachability> {
+    exchange_timed(exchange).map(|(outcome, _)| outcome)
+}
+
+/// Runs one exchange, returning its outcome and the decoded answer, if any.
+fn exchange_timed(
+    exchange: impl FnOnce() -> io::Result<Vec<u8>>,
+) -> Result<(Reachability, Option<Message>)> {
+    let started = Instant::now();
+    match exchange() {
+        Ok(answer) => {
+            let latency = started.elapsed();
+            let reply = Message::decode(&answer)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+            let outcome = Reachability::Responsive {
+                rcode: reply.rcode,
+                latency,
+            };
+            Ok((outcome, Some(reply)))
+        }
+        Err(e) => match e.kind() {
+            io::ErrorKind::ConnectionRefused | io::ErrorKind::ConnectionReset => {
+                Ok((Reachability::Refused, None))
+            }
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => {
+                Ok((Reachability::Timeout, None))
+            }
+            _ => Err(e.into()),
+        },
+    }
+}
+
+/// A query ID that differs between checks, so late answers to an earlier
+/// check are not mistaken for this one's.
+fn query_id() -> u16 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.subsec_nanos());
+    #[allow(clippy::cast_possible_truncation)]
+    let id = (nanos ^ std::process::id()) as u16;
+    id
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::bind_udp_tcp;
+    use std::net::{TcpListener, UdpSocket};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread::JoinHandle;
+
+    const TIMEOUT: Duration = Duration::from_millis(300);
+
+    /// A loopback responder answering every query with `rcode` and, for
+    /// `NOERROR`, a loopback A record. UDP answers for `big.*` names are
+    /// truncated.
+    struct Responder {
+        config: ResolverConfig,
+        stop: Arc<AtomicBool>,
+        thread: Option<JoinHandle<()>>,
+    }
+
+    impl Responder {
+        fn start(rcode: Rcode) -> Self {
+            let (udp, tcp) = bind_udp_tcp("127.0.0.1:0".parse().unwrap()).unwrap();
+            let port = udp.local_addr().unwrap().port();
+            let stop = Arc::new(AtomicBool::new(false));
+            let thread = {
+                let stop = Arc::clone(&stop);
+                std::thread::spawn(move || serve(&udp, &tcp, rcode, &stop))
+            };
+            Self {
+                config: ResolverConfig::new("myapp.local", "127.0.0.1", port),
+                stop,
+                thread: Some(thread),
+            }
+        }
+    }
+
+    impl Drop for Responder {
+        fn drop(&mut self) {
+            self.stop.store(true, Ordering::Release);
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+
+    fn serve(udp: &UdpSocket, tcp: &TcpListener, rcode: Rcode, stop: &AtomicBool) {
+        let reply = |packet: &[u8], over_udp: bool| {
+            let query = Message::decode(packet).unwrap();
+            let name = query.questions[0].name.clone();
+            let mut reply = Message {
+                rcode,
+                ..query.reply()
+            };
+            if over_udp && name.starts_with("big.") {
+                reply.truncated = true;
+            } else if rcode == Rcode::NoError {
+                let ip = std::net::Ipv4Addr::LOCALHOST;
+                reply.answers.push(Record::new(name, 60, ip.into()));
+            }
+            reply.encode().unwrap()
+        };
+        udp.set_nonblocking(true).unwrap();
+        tcp.set_nonblocking(true).unwrap();
+        let mut buf = [0; 512];
+        while !stop.load(Ordering::Acquire) {
+            if let Ok((len, peer)) = udp.recv_from(&mut buf) {
+                udp.send_to(&reply(&buf[..len], true), peer).unwrap();
+            }
+            if let Ok((mut stream, _)) = tcp.accept() {
+                stream.set_nonblocking(false).unwrap();
+                let packet = crate::dns::read_tcp(&mut stream).unwrap();
+                crate::dns::write_tcp(&mut stream, &reply(&packet, false)).unwrap();
+            }
+            std::thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    /// A config pointing at a loopback port with nothing bound to it.
+    fn closed_config() -> ResolverConfig {
+        let port = UdpSocket::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        ResolverConfig::new("myapp.local", "127.0.0.1", port)
+    }
+
+    #[test]
+    fn responsive_over_udp_and_tcp() {
+        let responder = Responder::start(Rcode::NoError);
+        let report = Preflight::new()
+            .tcp(true)
+            .timeout(TIMEOUT)
+            .check(&responder.config)
+            .unwrap();
+        assert!(report.is_ok(), "{report}");
+        assert!(matches!(
+            report.udp,
+            Reachability::Responsive {
+                rcode: Rcode::NoError,
+                ..
+            }
+        ));
+        assert!(report.tcp.unwrap().is_responsive());
+    }
+
+    #[test]
+    fn dns_refusal_still_counts_as_responsive() {
+        let responder = Responder::start(Rcode::Refused);
+        let report = Preflight::new()
+            .timeout(TIMEOUT)
+            .ensure(&responder.config)
+            .unwrap();
+        assert!(matches!(
+            report.udp,
+            Reachability::Responsive {
+                rcode: Rcode::Refused,
+                ..
+            }
+        ));
+        assert_eq!(report.tcp, None);
+    }
+
+    #[test]
+    fn closed_port_is_refused() {
+        let report = Preflight::new()
+            .tcp(true)
+            .timeout(TIMEOUT)
+            .check(&closed_config())
+            .unwrap();
+        assert_eq!(report.udp, Reachability::Refused);
+        assert_eq!(report.tcp, Some(Reachability::Refused));
+        assert!(!report.is_ok());
+    }
+
+    #[test]
+    fn silent_port_times_out() {
+        let silent = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let config = ResolverConfig::new(
+            "myapp.local",
+            "127.0.0.1",
+            silent.local_addr().unwrap().port(),
+        );
+        let err = Preflight::new()
+            .timeout(TIMEOUT)
+            .ensure(&config)
+            .unwrap_err();
+        assert!(
+            matches!(&err, ResolverError::Unreachable { domain, reason }
+                if domain == "myapp.local" && reason.ends_with("UDP timeout")),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn probe_returns_answers_and_retries_truncated_over_tcp() {
+        let responder = Responder::start(Rcode::NoError);
+        let check = Preflight::new().timeout(TIMEOUT);
+
+        let report = check
+            .probe(&responder.config, "web.myapp.local", RecordType::A)
+            .unwrap();
+        assert!(report.is_resolving(), "{report}");
+        assert!(!report.tcp);
+        assert_eq!(report.answers[0].name, "web.myapp.local");
+
+        let report = check
+            .probe(&responder.config, "big.myapp.local", RecordType::A)
+            .unwrap();
+        assert!(report.tcp);
+        assert!(report.is_resolving(), "{report}");
+    }
+
+    #[test]
+    fn probe_reports_failures() {
+        let check = Preflight::new().timeout(TIMEOUT);
+        let responder = Responder::start(Rcode::NxDomain);
+        let report = check
+            .probe(&responder.config, "web.myapp.local", RecordType::A)
+            .unwrap();
+        assert!(!report.is_resolving());
+
+        assert!(matches!(
+            check.probe(&responder.config, "example.com", RecordType::A),
+            Err(ResolverError::InvalidConfig(_))
+        ));
+        assert!(matches!(
+            check.probe(&closed_config(), "myapp.local", RecordType::A),
+            Err(ResolverError::Unreachable { .. })
+        ));
+    }
+}
@@ -0,0 +1,78 @@
+//! Per-domain resolver file status.
+
+use crate::config::ResolverConfig;
+
+/// Detailed state of `/etc/resolver/<domain>` as seen by a
+/// [`FileResolver`](crate::FileResolver).
+///
+/// Returned by [`FileResolver::status`](crate::FileResolver::status) and
+/// [`FileResolver::status_against`](crate::FileResolver::status_against).
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(tag = "state", rename_all = "snake_case")
+)]
+pub enum DomainStatus {
+    /// No file exists for the domain.
+    Missing,
+
+    /// Managed by this instance and bound to an owner.
+    ManagedEphemeral {
+        /// Recorded owner PID; `None` for lease- or lock-only entries.
+        pid: Option<u32>,
+        /// Whether the owner is alive and its lease (if any) unexpired, i.e.
+        /// whether [`cleanup_orphaned`](crate::FileResolver::cleanup_orphaned)
+        /// would keep the file.
+        alive: bool,
+    },
+
+    /// Managed by this instance with no owner; survives restarts.
+    ManagedPermanent,
+
+    /// Carries another instance's `# managed by ...` marker.
+    ForeignOwner {
+        /// The other owner's marker.
+        marker: String,
+    },
+
+    /// Exists but has no ownership marker (e.g. created by hand).
+    Unmanaged,
+
+    /// Starts with this instance's marker but cannot be understood.
+    Corrupt {
+        /// What is wrong with the file.
+        reason: String,
+    },
+
+    /// Managed by this instance, but its directives differ from the
+    /// expected configuration.
+    ContentMismatch {
+        /// The configuration the caller expected.
+        expected: ResolverConfig,
+        /// The configuration found on disk.
+        actual: ResolverConfig,
+    },
+}
+
+impl DomainStatus {
+    /// Returns `true` for the managed states (ephemeral, permanent, or
+    /// managed with mismatching content).
+    #[must_use]
+    pub const fn is_managed(&self) -> bool {
+        matches!(
+            self,
+            Self::ManagedEphemeral { .. } | Self::ManagedPermanent | Self::ContentMismatch { .. }
+        )
+    }
+
+    /// Returns `true` if the domain is managed, matches any expectation, and
+    /// (for ephemeral entries) its owner is alive.
+    #[must_use]
+    pub const fn is_healthy(&self) -> bool {
+        matches!(
+            self,
+            Self::ManagedPermanent | Self::ManagedEphemeral { alive: true, .. }
+        )
+    }
+}
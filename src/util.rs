@@ -1,5 +1,12 @@
 //! Internal utilities.
 
+use std::fs::File;
+use std::io;
+use std::net::{SocketAddr, TcpListener, UdpSocket};
+use std::os::fd::AsRawFd;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+
 /// Checks whether the process with the given PID is still alive.
 ///
 /// Uses `kill(pid, 0)` — signal 0 checks existence without delivering a signal.
@@ -13,6 +20,180 @@ pub fn is_process_alive(pid: u32) -> bool {
     }
 }
 
+/// Returns the real user ID of the current process.
+#[must_use]
+pub(crate) fn current_uid() -> u32 {
+    // SAFETY: `getuid` is always successful and has no side effects.
+    unsafe { libc::getuid() }
+}
+
+/// Returns the machine's hostname, or `None` if it cannot be determined.
+#[must_use]
+pub(crate) fn hostname() -> Option<String> {
+    let mut buf = [0u8; 256];
+    // SAFETY: the buffer is valid for `buf.len()` bytes; `gethostname`
+    // NUL-terminates on success when the name fits.
+    let rc = unsafe { libc::gethostname(buf.as_mut_ptr().cast(), buf.len()) };
+    if rc != 0 {
+        return None;
+    }
+    let len = buf.iter().position(|&b| b == 0)?;
+    String::from_utf8(buf[..len].to_vec()).ok()
+}
+
+/// Writes `contents` to `path` atomically.
+///
+/// The data is written to a hidden temporary file in the same directory and
+/// then renamed over `path`, so readers see either the old or the new file,
+/// never a partial one.
+///
+/// # Errors
+///
+/// Returns any I/O error from writing the temporary file or renaming it.
+pub(crate) fn write_atomic(path: &Path, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    // Unique per call, so concurrent writers in one process never share a
+    // temporary file.
+    static NEXT_TMP: AtomicU64 = AtomicU64::new(0);
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "path has no file name"))?;
+    let tmp = path.with_file_name(format!(
+        ".{}.tmp-{}-{}",
+        name.to_string_lossy(),
+        std::process::id(),
+        NEXT_TMP.fetch_add(1, Ordering::Relaxed)
+    ));
+    let result = std::fs::write(&tmp, contents).and_then(|()| std::fs::rename(&tmp, path));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// Opens (creating if needed) `path` and takes an exclusive, non-blocking `flock`.
+///
+/// Returns `Ok(None)` if another open file description holds the lock. The
+/// lock is released when the returned file is dropped or the process exits.
+///
+/// # Errors
+///
+/// Returns any I/O error from opening the file or from `flock` other than
+/// `EWOULDBLOCK`.
+pub(crate) fn try_lock_file(path: &Path) -> io::Result<Option<File>> {
+    let file = File::options()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?;
+    // SAFETY: `flock` on a valid, owned file descriptor.
+    let rc = unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) };
+    if rc == 0 {
+        return Ok(Some(file));
+    }
+    let err = io::Error::last_os_error();
+    if err.kind() == io::ErrorKind::WouldBlock {
+        Ok(None)
+    } else {
+        Err(err)
+    }
+}
+
+/// Returns `true` if some process currently holds the `flock` on `path`.
+///
+/// A missing lock file counts as not held.
+#[must_use]
+pub(crate) fn is_lock_held(path: &Path) -> bool {
+    if !path.exists() {
+        return false;
+    }
+    matches!(try_lock_file(path), Ok(None))
+}
+
+/// Waits up to `timeout` for `fd` to become readable.
+///
+/// Returns `false` on timeout or if interrupted by a signal.
+///
+/// # Errors
+///
+/// Returns the OS error if `poll` fails.
+pub(crate) fn wait_readable(fd: &impl AsRawFd, timeout: std::time::Duration) -> io::Result<bool> {
+    let mut pfd = libc::pollfd {
+        fd: fd.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let millis = libc::c_int::try_from(timeout.as_millis()).unwrap_or(libc::c_int::MAX);
+    // SAFETY: one valid `pollfd`.
+    let rc = unsafe { libc::poll(&raw mut pfd, 1, millis) };
+    if rc < 0 {
+        let err = io::Error::last_os_error();
+        return if err.kind() == io::ErrorKind::Interrupted {
+            Ok(false)
+        } else {
+            Err(err)
+        };
+    }
+    Ok(rc > 0)
+}
+
+/// Binds a Unix socket at `path` with permissions `mode`, replacing a stale
+/// socket file left behind by a crashed server.
+///
+/// # Errors
+///
+/// Returns `AlreadyExists` if `path` is not a socket, `AddrInUse` if a
+/// server is still listening on it, or the error from binding.
+#[cfg(any(feature = "helper", feature = "admin"))]
+pub(crate) fn bind_unix_socket(
+    path: &Path,
+    mode: u32,
+) -> io::Result<std::os::unix::net::UnixListener> {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    if let Ok(meta) = std::fs::symlink_metadata(path) {
+        if !meta.file_type().is_socket() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} exists and is not a socket", path.display()),
+            ));
+        }
+        if UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("another server is listening on {}", path.display()),
+            ));
+        }
+        std::fs::remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    Ok(listener)
+}
+
+/// Binds a UDP socket and a TCP listener on the same address, as a DNS
+/// server needs. With port 0, retries until the port picked for UDP is also
+/// free for TCP.
+///
+/// # Errors
+///
+/// Returns the error from binding.
+pub(crate) fn bind_udp_tcp(addr: SocketAddr) -> io::Result<(UdpSocket, TcpListener)> {
+    let mut attempts = 0;
+    loop {
+        let udp = UdpSocket::bind(addr)?;
+        match TcpListener::bind(udp.local_addr()?) {
+            Ok(tcp) => return Ok((udp, tcp)),
+            Err(e) if addr.port() == 0 && attempts < 16 => {
+                tracing::debug!(error = %e, "UDP port taken for TCP, retrying");
+                attempts += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -26,4 +207,50 @@ mod tests {
     fn dead_pid_is_not_alive() {
         assert!(!is_process_alive(999_999_999));
     }
+
+    #[test]
+    fn lock_is_exclusive_until_dropped() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.lock");
+        assert!(!is_lock_held(&path));
+
+        let held = try_lock_file(&path).unwrap().unwrap();
+        assert!(is_lock_held(&path));
+        assert!(try_lock_file(&path).unwrap().is_none());
+
+        drop(held);
+        assert!(!is_lock_held(&path));
+    }
+
+    #[test]
+    fn write_atomic_replaces_contents() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file");
+        write_atomic(&path, "one").unwrap();
+        write_atomic(&path, "two").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "two");
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn write_atomic_tolerates_concurrent_writers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file");
+        std::thread::scope(|scope| {
+            for i in 0..8 {
+                let path = &path;
+                scope.spawn(move || {
+                    for _ in 0..50 {
+                        write_atomic(path, format!("writer {i}")).unwrap();
+                    }
+                });
+            }
+        });
+        assert!(
+            std::fs::read_to_string(&path)
+                .unwrap()
+                .starts_with("writer ")
+        );
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
 }
@@ -0,0 +1,567 @@
+//! Resolver directory watching.
+//!
+//! [`DirWatcher`] blocks until something in a directory may have changed,
+//! using inotify on Linux, kqueue on macOS, and plain timeouts (polling)
+//! elsewhere or when the native backend is unavailable. It only reports
+//! *that* something happened; callers rescan the directory to find out what.
+//!
+//! kqueue reports entries being added, removed or renamed in the directory,
+//! but not in-place writes to existing files, so callers should also rescan
+//! periodically by passing a finite timeout to [`DirWatcher::wait`].
+//!
+//! [`Changes`], returned by [`FileResolver::changes`](crate::FileResolver::changes),
+//! does exactly that and turns rescans into typed [`ChangeEvent`]s.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::header::{ManagedHeader, foreign_marker};
+
+/// Waits for changes in a single directory.
+#[derive(Debug)]
+pub struct DirWatcher {
+    backend: Backend,
+}
+
+#[derive(Debug)]
+enum Backend {
+    #[cfg(target_os = "linux")]
+    Inotify(std::os::fd::OwnedFd),
+    #[cfg(target_os = "macos")]
+    Kqueue {
+        kq: std::os::fd::OwnedFd,
+        _dir: std::os::fd::OwnedFd,
+    },
+    Poll,
+}
+
+impl DirWatcher {
+    /// Watches `dir` with the platform's native backend, falling back to
+    /// polling if it cannot be set up (e.g. the directory does not exist yet).
+    #[must_use]
+    pub fn new(dir: &Path) -> Self {
+        match Backend::native(dir) {
+            Ok(backend) => Self { backend },
+            Err(e) => {
+                tracing::debug!(
+                    dir = %dir.display(),
+                    error = %e,
+                    "Native directory watching unavailable, polling instead"
+                );
+                Self::polling()
+            }
+        }
+    }
+
+    /// Creates a watcher that never receives events and only times out.
+    #[must_use]
+    pub const fn polling() -> Self {
+        Self {
+            backend: Backend::Poll,
+        }
+    }
+
+    /// Returns `true` if this watcher only polls.
+    #[must_use]
+    pub const fn is_polling(&self) -> bool {
+        matches!(self.backend, Backend::Poll)
+    }
+
+    /// Blocks until the directory may have changed or `timeout` elapses.
+    ///
+    /// Returns `true` if a change notification arrived. Pending
+    /// notifications are drained, so one call covers a burst of changes.
+    ///
+    /// # Errors
+    ///
+    /// Returns the underlying OS error if waiting fails.
+    pub fn wait(&self, timeout: Duration) -> io::Result<bool> {
+        match &self.backend {
+            #[cfg(target_os = "linux")]
+            Backend::Inotify(fd) => inotify_wait(fd, timeout),
+            #[cfg(target_os = "macos")]
+            Backend::Kqueue { kq, .. } => kqueue_wait(kq, timeout),
+            Backend::Poll => {
+                std::thread::sleep(timeout);
+                Ok(false)
+            }
+        }
+    }
+}
+
+impl Backend {
+    #[cfg(target_os = "linux")]
+    fn native(dir: &Path) -> io::Result<Self> {
+        use std::os::fd::{FromRawFd, OwnedFd};
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = std::ffi::CString::new(dir.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        // SAFETY: plain syscall; the returned descriptor is checked below.
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: `fd` is a freshly created descriptor we exclusively own.
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let mask = libc::IN_CREATE
+            | libc::IN_DELETE
+            | libc::IN_MODIFY
+            | libc::IN_CLOSE_WRITE
+            | libc::IN_MOVED_FROM
+            | libc::IN_MOVED_TO
+            | libc::IN_ATTRIB
+            | libc::IN_DELETE_SELF
+            | libc::IN_MOVE_SELF;
+        // SAFETY: valid inotify descriptor and NUL-terminated path.
+        let wd = unsafe {
+            libc::inotify_add_watch(std::os::fd::AsRawFd::as_raw_fd(&fd), path.as_ptr(), mask)
+        };
+        if wd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self::Inotify(fd))
+    }
+
+    #[cfg(target_os = "macos")]
+    fn native(dir: &Path) -> io::Result<Self> {
+        use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+        use std::os::unix::ffi::OsStrExt;
+
+        let path = std::ffi::CString::new(dir.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        // SAFETY: NUL-terminated path; the returned descriptor is checked below.
+        let dir_fd = unsafe { libc::open(path.as_ptr(), libc::O_EVTONLY | libc::O_CLOEXEC) };
+        if dir_fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: `dir_fd` is a freshly opened descriptor we exclusively own.
+        let dir_fd = unsafe { OwnedFd::from_raw_fd(dir_fd) };
+        // SAFETY: plain syscall; the returned descriptor is checked below.
+        let kq = unsafe { libc::kqueue() };
+        if kq < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: `kq` is a freshly created descriptor we exclusively own.
+        let kq = unsafe { OwnedFd::from_raw_fd(kq) };
+
+        // SAFETY: all-zero is a valid `kevent`.
+        let mut change: libc::kevent = unsafe { std::mem::zeroed() };
+        #[allow(clippy::cast_sign_loss)]
+        {
+            change.ident = dir_fd.as_raw_fd() as libc::uintptr_t;
+        }
+        change.filter = libc::EVFILT_VNODE;
+        change.flags = libc::EV_ADD | libc::EV_CLEAR;
+        change.fflags = libc::NOTE_WRITE
+            | libc::NOTE_DELETE
+            | libc::NOTE_RENAME
+            | libc::NOTE_EXTEND
+            | libc::NOTE_ATTRIB;
+        // SAFETY: one valid changelist entry, no eventlist.
+        let rc = unsafe {
+            libc::kevent(
+                kq.as_raw_fd(),
+                &change,
+                1,
+                std::ptr::null_mut(),
+                0,
+                std::ptr::null(),
+            )
+        };
+        if rc < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self::Kqueue { kq, _dir: dir_fd })
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    fn native(_dir: &Path) -> io::Result<Self> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "no native directory watcher on this platform",
+        ))
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn inotify_wait(fd: &std::os::fd::OwnedFd, timeout: Duration) -> io::Result<bool> {
+    use std::os::fd::AsRawFd;
+
+    if !crate::util::wait_readable(fd, timeout)? {
+        return Ok(false);
+    }
+
+    // Drain all queued events; their contents do not matter.
+    let mut buf = [0u8; 4096];
+    loop {
+        // SAFETY: `buf` is valid for `buf.len()` bytes.
+        let n = unsafe { libc::read(fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len()) };
+        if n <= 0 {
+            break;
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(target_os = "macos")]
+fn kqueue_wait(kq: &std::os::fd::OwnedFd, timeout: Duration) -> io::Result<bool> {
+    use std::os::fd::AsRawFd;
+
+    let ts = libc::timespec {
+        tv_sec: libc::time_t::try_from(timeout.as_secs()).unwrap_or(libc::time_t::MAX),
+        tv_nsec: libc::c_long::from(timeout.subsec_nanos()),
+    };
+    // SAFETY: all-zero is a valid `kevent`.
+    let mut events: [libc::kevent; 8] = unsafe { std::mem::zeroed() };
+    // SAFETY: no changelist; `events` is valid for its length.
+    let rc = unsafe {
+        libc::kevent(
+            kq.as_raw_fd(),
+            std::ptr::null(),
+            0,
+            events.as_mut_ptr(),
+            8,
+            &ts,
+        )
+    };
+    if rc < 0 {
+        let err = io::Error::last_os_error();
+        return if err.kind() == io::ErrorKind::Interrupted {
+            Ok(false)
+        } else {
+            Err(err)
+        };
+    }
+    Ok(rc > 0)
+}
+
+/// Default interval between rescans when no change is reported.
+const DEFAULT_RESCAN: Duration = Duration::from_secs(2);
+
+/// What happened to a resolver file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChangeKind {
+    /// The file appeared.
+    Added,
+    /// The file disappeared.
+    Removed,
+    /// The file's content changed.
+    Modified,
+}
+
+/// Who owns a resolver file, judged by its first line.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Owner {
+    /// Carries this resolver's marker.
+    Managed,
+    /// Carries another instance's `# managed by ...` marker.
+    Foreign {
+        /// The other owner's marker.
+        marker: String,
+    },
+    /// Has no ownership marker.
+    Unmanaged,
+}
+
+impl Owner {
+    /// Classifies `content` relative to `marker`.
+    fn classify(marker: &str, content: &str) -> Self {
+        if ManagedHeader::parse(marker, content).is_some() {
+            Self::Managed
+        } else {
+            foreign_marker(marker, content).map_or(Self::Unmanaged, |m| Self::Foreign {
+                marker: m.to_string(),
+            })
+        }
+    }
+}
+
+/// A change to a file in the resolver directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangeEvent {
+    /// The domain (file name) that changed.
+    pub domain: String,
+    /// What happened.
+    pub kind: ChangeKind,
+    /// Owner after the change; for [`ChangeKind::Removed`], the last known
+    /// owner.
+    pub owner: Owner,
+}
+
+/// Last seen state of one resolver file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Seen {
+    content: Vec<u8>,
+    owner: Owner,
+}
+
+/// Blocking stream of [`ChangeEvent`]s for every file in a resolver
+/// directory, managed or not.
+///
+/// Created by [`FileResolver::changes`](crate::FileResolver::changes). Files
+/// present at creation time form the baseline and are not reported.
+/// Iterating blocks until the next change and never ends; use
+/// [`next_timeout`](Self::next_timeout) to wait with a deadline.
+///
+/// Temporary files (names starting with `.`) are ignored, so an atomic
+/// rewrite shows up as a single [`ChangeKind::Modified`].
+#[derive(Debug)]
+pub struct Changes {
+    dir: PathBuf,
+    marker: String,
+    watcher: DirWatcher,
+    force_polling: bool,
+    rescan: Duration,
+    seen: BTreeMap<String, Seen>,
+    pending: VecDeque<ChangeEvent>,
+}
+
+impl Changes {
+    pub(crate) fn new(dir: &Path, marker: &str) -> Self {
+        let mut changes = Self {
+            dir: dir.to_path_buf(),
+            marker: marker.to_string(),
+            watcher: DirWatcher::new(dir),
+            force_polling: false,
+            rescan: DEFAULT_RESCAN,
+            seen: BTreeMap::new(),
+            pending: VecDeque::new(),
+        };
+        changes.seen = changes.snapshot();
+        changes
+    }
+
+    /// Sets the interval between rescans when no notification arrives
+    /// (default: 2 seconds).
+    #[must_use]
+    pub const fn rescan_interval(mut self, interval: Duration) -> Self {
+        self.rescan = interval;
+        self
+    }
+
+    /// Disables native directory watching and relies on rescans only.
+    #[must_use]
+    pub fn polling(mut self) -> Self {
+        self.watcher = DirWatcher::polling();
+        self.force_polling = true;
+        self
+    }
+
+    /// Rescans the directory now and returns the changes since the last scan
+    /// (including any not yet yielded), without blocking.
+    pub fn poll(&mut self) -> Vec<ChangeEvent> {
+        self.rescan_now();
+        self.pending.drain(..).collect()
+    }
+
+    /// Waits up to `timeout` for the next change.
+    pub fn next_timeout(&mut self, timeout: Duration) -> Option<ChangeEvent> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some(event);
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return None;
+            }
+            self.wait(remaining.min(self.rescan));
+            self.rescan_now();
+        }
+    }
+
+    fn wait(&mut self, timeout: Duration) {
+        if self.watcher.is_polling() && !self.force_polling && self.dir.is_dir() {
+            // The directory may have appeared since we started.
+            self.watcher = DirWatcher::new(&self.dir);
+        }
+        if let Err(e) = self.watcher.wait(timeout) {
+            tracing::warn!(error = %e, "Directory watch failed, polling instead");
+            self.watcher = DirWatcher::polling();
+        }
+    }
+
+    fn rescan_now(&mut self) {
+        let current = self.snapshot();
+        for (domain, old) in &self.seen {
+            if !current.contains_key(domain) {
+                self.pending.push_back(ChangeEvent {
+                    domain: domain.clone(),
+                    kind: ChangeKind::Removed,
+                    owner: old.owner.clone(),
+                });
+            }
+        }
+        for (domain, new) in &current {
+            let kind = match self.seen.get(domain) {
+                None => ChangeKind::Added,
+                Some(old) if old.content != new.content => ChangeKind::Modified,
+                Some(_) => continue,
+            };
+            self.pending.push_back(ChangeEvent {
+                domain: domain.clone(),
+                kind,
+                owner: new.owner.clone(),
+            });
+        }
+        self.seen = current;
+    }
+
+    /// Reads every regular, non-hidden file in the directory.
+    fn snapshot(&self) -> BTreeMap<String, Seen> {
+        let mut seen = BTreeMap::new();
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return seen,
+            Err(e) => {
+                tracing::warn!(dir = %self.dir.display(), error = %e, "Cannot scan resolver dir");
+                return self.seen.clone();
+            }
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+                continue;
+            };
+            if name.starts_with('.') || !path.is_file() {
+                continue;
+            }
+            let Ok(content) = std::fs::read(&path) else {
+                continue;
+            };
+            let owner = Owner::classify(&self.marker, &String::from_utf8_lossy(&content));
+            seen.insert(name.to_string(), Seen { content, owner });
+        }
+        seen
+    }
+}
+
+impl Iterator for Changes {
+    type Item = ChangeEvent;
+
+    fn next(&mut self) -> Option<ChangeEvent> {
+        loop {
+            if let Some(event) = self.next_timeout(self.rescan) {
+                return Some(event);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn inotify_reports_new_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let watcher = DirWatcher::new(dir.path());
+        assert!(!watcher.is_polling());
+
+        assert!(!watcher.wait(Duration::from_millis(10)).unwrap());
+        std::fs::write(dir.path().join("a.local"), "nameserver 127.0.0.1\n").unwrap();
+        assert!(watcher.wait(Duration::from_secs(5)).unwrap());
+    }
+
+    #[test]
+    fn missing_dir_falls_back_to_polling() {
+        let watcher = DirWatcher::new(Path::new("/nonexistent/resolver"));
+        assert!(watcher.is_polling());
+
+        let start = Instant::now();
+        assert!(!watcher.wait(Duration::from_millis(20)).unwrap());
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+
+    fn event(domain: &str, kind: ChangeKind, owner: Owner) -> ChangeEvent {
+        ChangeEvent {
+            domain: domain.into(),
+            kind,
+            owner,
+        }
+    }
+
+    #[test]
+    fn changes_classify_owners() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("old.local"), "nameserver 10.0.0.1\n").unwrap();
+        let mut changes = Changes::new(dir.path(), "# managed by test");
+        assert!(changes.poll().is_empty());
+
+        std::fs::write(
+            dir.path().join("mine.local"),
+            "# managed by test (v=2, pid=1)\nnameserver 127.0.0.1\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.path().join("theirs.local"),
+            "# managed by other (v=2)\nnameserver 127.0.0.1\n",
+        )
+        .unwrap();
+        std::fs::write(dir.path().join(".mine.local.tmp-1"), "ignored").unwrap();
+        std::fs::write(dir.path().join("old.local"), "nameserver 10.0.0.2\n").unwrap();
+        std::fs::remove_file(dir.path().join("old.local")).unwrap();
+
+        assert_eq!(
+            changes.poll(),
+            vec![
+                event("old.local", ChangeKind::Removed, Owner::Unmanaged),
+                event("mine.local", ChangeKind::Added, Owner::Managed),
+                event(
+                    "theirs.local",
+                    ChangeKind::Added,
+                    Owner::Foreign {
+                        marker: "# managed by other".into()
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn changes_iterator_reports_modification() {
+        let dir = tempfile::tempdir().unwrap();
+        let resolver = crate::FileResolver::new("test").dir(dir.path());
+        let config = crate::ResolverConfig::new("a.local", "127.0.0.1", 5553);
+        resolver.register(&config).unwrap();
+        let mut changes = resolver
+            .changes()
+            .rescan_interval(Duration::from_millis(20));
+
+        resolver
+            .register(&crate::ResolverConfig::new("a.local", "127.0.0.1", 5554))
+            .unwrap();
+        assert_eq!(
+            changes.next(),
+            Some(event("a.local", ChangeKind::Modified, Owner::Managed))
+        );
+
+        resolver.unregister("a.local").unwrap();
+        assert_eq!(
+            changes.next_timeout(Duration::from_secs(5)),
+            Some(event("a.local", ChangeKind::Removed, Owner::Managed))
+        );
+        assert_eq!(changes.next_timeout(Duration::from_millis(50)), None);
+    }
+
+    #[test]
+    fn changes_pick_up_directory_created_later() {
+        let dir = tempfile::tempdir().unwrap();
+        let sub = dir.path().join("resolver");
+        let mut changes =
+            Changes::new(&sub, "# managed by test").rescan_interval(Duration::from_millis(20));
+        assert_eq!(changes.next_timeout(Duration::from_millis(30)), None);
+
+        std::fs::create_dir(&sub).unwrap();
+        std::fs::write(sub.join("a.local"), "nameserver 127.0.0.1\n").unwrap();
+        assert_eq!(
+            changes.next_timeout(Duration::from_secs(5)),
+            Some(event("a.local", ChangeKind::Added, Owner::Unmanaged))
+        );
+    }
+}
@@ -0,0 +1,433 @@
+//! Self-healing for registered domains.
+//!
+//! Other tools (VPN clients, cleanup scripts) sometimes delete or overwrite
+//! resolver files while the owning daemon is still running. A [`Watchdog`]
+//! watches the resolver directory and re-registers its domains whenever
+//! their files go missing or stop matching the expected configuration.
+//!
+//! ```rust,ignore
+//! use std::sync::Arc;
+//! use macos_resolver::{FileResolver, ResolverConfig, Watchdog};
+//!
+//! let resolver = Arc::new(FileResolver::new("myapp"));
+//! let handle = Watchdog::new(resolver)
+//!     .watch(ResolverConfig::new("myapp.local", "127.0.0.1", 5553))
+//!     .spawn()?;
+//!
+//! for event in handle.events() {
+//!     tracing::warn!(domain = %event.domain, found = ?event.found, "Repaired");
+//! }
+//! ```
+
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::config::ResolverConfig;
+use crate::error::{ResolverError, Result};
+use crate::file_resolver::FileResolver;
+use crate::status::DomainStatus;
+use crate::watch::DirWatcher;
+
+/// Default interval between full rescans when no change is reported.
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How often the background thread checks whether it has been stopped.
+const STOP_POLL: Duration = Duration::from_millis(100);
+
+/// A repair performed by a [`Watchdog`].
+#[derive(Debug)]
+pub struct RepairEvent {
+    /// The repaired domain.
+    pub domain: String,
+    /// What was found on disk before the repair, e.g.
+    /// [`DomainStatus::Missing`] or [`DomainStatus::ContentMismatch`].
+    pub found: DomainStatus,
+    /// Outcome of re-registering the domain;
+    /// [`ResolverError::OwnedByOther`] if the file belongs to another
+    /// instance and was left alone.
+    pub result: Result<()>,
+}
+
+#[derive(Debug, Clone)]
+struct Watched {
+    config: ResolverConfig,
+    permanent: bool,
+}
+
+/// Re-asserts registrations when their resolver files disappear or change.
+///
+/// Uses inotify on Linux and kqueue on macOS to react promptly, and rescans
+/// every [`interval`](Self::interval) regardless, so changes the native
+/// backend misses (or platforms without one) are still repaired.
+///
+/// Files carrying another instance's marker are reported but not rewritten,
+/// so two daemons watching the same domain do not take turns overwriting
+/// it; see [`reclaim_foreign`](Self::reclaim_foreign).
+pub struct Watchdog {
+    resolver: Arc<FileResolver>,
+    watched: Vec<Watched>,
+    interval: Duration,
+    polling: bool,
+    reclaim_foreign: bool,
+}
+
+impl Watchdog {
+    /// Creates a watchdog for domains registered through `resolver`.
+    #[must_use]
+    pub const fn new(resolver: Arc<FileResolver>) -> Self {
+        Self {
+            resolver,
+            watched: Vec::new(),
+            interval: DEFAULT_INTERVAL,
+            polling: false,
+            reclaim_foreign: false,
+        }
+    }
+
+    /// Keeps `config` registered via [`FileResolver::register`].
+    #[must_use]
+    pub fn watch(mut self, config: ResolverConfig) -> Self {
+        self.watched.push(Watched {
+            config,
+            permanent: false,
+        });
+        self
+    }
+
+    /// Keeps `config` registered via [`FileResolver::register_permanent`].
+    #[must_use]
+    pub fn watch_permanent(mut self, config: ResolverConfig) -> Self {
+        self.watched.push(Watched {
+            config,
+            permanent: true,
+        });
+        self
+    }
+
+    /// Sets the interval between full rescans (default: 5 seconds).
+    #[must_use]
+    pub const fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Disables native directory watching and relies on rescans only.
+    #[must_use]
+    pub const fn polling(mut self) -> Self {
+        self.polling = true;
+        self
+    }
+
+    /// Also re-registers domains whose file carries another instance's
+    /// marker. Only use this when no other daemon keeps the domain.
+    #[must_use]
+    pub const fn reclaim_foreign(mut self) -> Self {
+        self.reclaim_foreign = true;
+        self
+    }
+
+    /// Checks every watched domain once and repairs those that need it.
+    ///
+    /// A domain is repaired unless its file is managed by this resolver and
+    /// matches the watched configuration, or belongs to another instance.
+    /// Returns one event per repair, and one per foreign file left alone.
+    pub fn check(&self) -> Vec<RepairEvent> {
+        let mut events = Vec::new();
+        for watched in &self.watched {
+            let domain = &watched.config.domain;
+            let found = match self.resolver.status_against(&watched.config) {
+                Ok(DomainStatus::ManagedEphemeral { .. } | DomainStatus::ManagedPermanent) => {
+                    continue;
+                }
+                Ok(status) => status,
+                Err(e) => {
+                    tracing::warn!(domain = %domain, error = %e, "Cannot check resolver file");
+                    continue;
+                }
+            };
+
+            if let DomainStatus::ForeignOwner { marker } = &found {
+                if !self.reclaim_foreign {
+                    tracing::warn!(
+                        domain = %domain,
+                        marker = %marker,
+                        "Resolver file taken over by another instance, leaving it"
+                    );
+                    let result = Err(ResolverError::OwnedByOther {
+                        domain: domain.clone(),
+                        marker: marker.clone(),
+                    });
+                    events.push(RepairEvent {
+                        domain: domain.clone(),
+                        found,
+                        result,
+                    });
+                    continue;
+                }
+            }
+
+            let result = if watched.permanent {
+                self.resolver.register_permanent(&watched.config)
+            } else {
+                self.resolver.register(&watched.config)
+            };
+            match &result {
+                Ok(()) => tracing::warn!(
+                    domain = %domain,
+                    found = ?found,
+                    "Repaired resolver file"
+                ),
+                Err(e) => tracing::error!(
+                    domain = %domain,
+                    found = ?found,
+                    error = %e,
+                    "Failed to repair resolver file"
+                ),
+            }
+            events.push(RepairEvent {
+                domain: domain.clone(),
+                found,
+                result,
+            });
+        }
+        events
+    }
+
+    /// Repairs anything already broken, then keeps watching on a background
+    /// thread until the returned handle is stopped or dropped.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ResolverError::Io`] if the resolver directory cannot be
+    /// created or the thread cannot be spawned.
+    pub fn spawn(self) -> Result<WatchdogHandle> {
+        std::fs::create_dir_all(self.resolver.resolver_dir())?;
+
+        let (tx, events) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = Arc::clone(&stop);
+        let thread = std::thread::Builder::new()
+            .name("resolver-watchdog".into())
+            .spawn(move || {
+                let dir = self.resolver.resolver_dir().to_path_buf();
+                let mut watcher = self.watcher(&dir);
+                while !thread_stop.load(Ordering::Acquire) {
+                    let dir_was_missing = !dir.is_dir();
+                    for event in self.check() {
+                        // Keep repairing even if nobody listens for events.
+                        let _ = tx.send(event);
+                    }
+                    if dir_was_missing {
+                        // The old watch died with the directory.
+                        watcher = self.watcher(&dir);
+                    }
+                    let deadline = Instant::now() + self.interval;
+                    while !thread_stop.load(Ordering::Acquire) {
+                        let remaining = deadline.saturating_duration_since(Instant::now());
+                        if remaining.is_zero() {
+                            break;
+                        }
+                        match watcher.wait(remaining.min(STOP_POLL)) {
+                            Ok(true) => break,
+                            Ok(false) => {}
+                            Err(e) => {
+                                tracing::warn!(error = %e, "Directory watch failed, polling instead");
+                                watcher = DirWatcher::polling();
+                            }
+                        }
+                    }
+                }
+            })?;
+
+        Ok(WatchdogHandle {
+            stop,
+            thread: Some(thread),
+            events,
+        })
+    }
+
+    fn watcher(&self, dir: &Path) -> DirWatcher {
+        if self.polling {
+            DirWatcher::polling()
+        } else {
+            DirWatcher::new(dir)
+        }
+    }
+}
+
+/// Handle to a running [`Watchdog`]; stops it when dropped.
+#[derive(Debug)]
+pub struct WatchdogHandle {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+    events: Receiver<RepairEvent>,
+}
+
+impl WatchdogHandle {
+    /// Repair events, in the order they happened.
+    #[must_use]
+    pub const fn events(&self) -> &Receiver<RepairEvent> {
+        &self.events
+    }
+
+    /// Stops the watchdog and waits for its thread to exit.
+    pub fn stop(mut self) {
+        self.shutdown();
+    }
+
+    fn shutdown(&mut self) {
+        self.stop.store(true, Ordering::Release);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for WatchdogHandle {
+    fn drop(&mut self) {
+        self.shutdown();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn setup() -> (tempfile::TempDir, Arc<FileResolver>) {
+        let dir = tempfile::tempdir().unwrap();
+        let resolver = Arc::new(FileResolver::new("test").dir(dir.path()));
+        (dir, resolver)
+    }
+
+    #[test]
+    fn check_repairs_missing_and_changed() {
+        let (dir, resolver) = setup();
+        let a = ResolverConfig::new("a.local", "127.0.0.1", 5553);
+        let b = ResolverConfig::new("b.local", "127.0.0.1", 5554);
+        resolver.register(&b).unwrap();
+        resolver
+            .register(&ResolverConfig::new("b.local", "127.0.0.1", 9999))
+            .unwrap();
+
+        let watchdog = Watchdog::new(Arc::clone(&resolver))
+            .watch(a.clone())
+            .watch_permanent(b.clone());
+        let events = watchdog.check();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].domain, "a.local");
+        assert_eq!(events[0].found, DomainStatus::Missing);
+        assert!(matches!(
+            events[1].found,
+            DomainStatus::ContentMismatch { .. }
+        ));
+        assert!(events.iter().all(|e| e.result.is_ok()));
+
+        assert_eq!(
+            resolver.status_against(&a).unwrap(),
+            DomainStatus::ManagedEphemeral {
+                pid: Some(std::process::id()),
+                alive: true
+            }
+        );
+        assert_eq!(
+            resolver.status_against(&b).unwrap(),
+            DomainStatus::ManagedPermanent
+        );
+        assert!(watchdog.check().is_empty());
+        drop(dir);
+    }
+
+    #[test]
+    fn check_reclaims_overwritten_file() {
+        let (dir, resolver) = setup();
+        let config = ResolverConfig::new("a.local", "127.0.0.1", 5553);
+        std::fs::write(dir.path().join("a.local"), "nameserver 10.0.0.1\n").unwrap();
+
+        let events = Watchdog::new(Arc::clone(&resolver)).watch(config).check();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].found, DomainStatus::Unmanaged);
+        assert!(resolver.is_registered("a.local"));
+    }
+
+    #[test]
+    fn check_leaves_foreign_files_alone() {
+        let (dir, resolver) = setup();
+        let config = ResolverConfig::new("a.local", "127.0.0.1", 5553);
+        let path = dir.path().join("a.local");
+        let foreign = "# managed by otherapp\nnameserver 10.0.0.1\n";
+        std::fs::write(&path, foreign).unwrap();
+
+        let events = Watchdog::new(Arc::clone(&resolver))
+            .watch(config.clone())
+            .check();
+        assert_eq!(events.len(), 1);
+        assert_eq!(
+            events[0].found,
+            DomainStatus::ForeignOwner {
+                marker: "# managed by otherapp".into()
+            }
+        );
+        assert!(matches!(
+            events[0].result,
+            Err(ResolverError::OwnedByOther { .. })
+        ));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), foreign);
+
+        let events = Watchdog::new(Arc::clone(&resolver))
+            .watch(config)
+            .reclaim_foreign()
+            .check();
+        assert!(events[0].result.is_ok());
+        assert!(resolver.is_registered("a.local"));
+    }
+
+    fn repairs_deleted_file(watchdog: Watchdog, dir: &Path) {
+        let handle = watchdog.spawn().unwrap();
+        let path = dir.join("a.local");
+        let first = handle.events().recv_timeout(TIMEOUT).unwrap();
+        assert_eq!(first.found, DomainStatus::Missing);
+        assert!(path.exists());
+
+        std::fs::remove_file(&path).unwrap();
+        let event = handle.events().recv_timeout(TIMEOUT).unwrap();
+        assert_eq!(event.domain, "a.local");
+        assert_eq!(event.found, DomainStatus::Missing);
+        assert!(event.result.is_ok());
+        assert!(path.exists());
+
+        std::fs::write(&path, "nameserver 10.0.0.1\n").unwrap();
+        let event = handle.events().recv_timeout(TIMEOUT).unwrap();
+        assert_eq!(event.found, DomainStatus::Unmanaged);
+        handle.stop();
+    }
+
+    #[test]
+    fn spawned_watchdog_repairs_with_native_watcher() {
+        let (dir, resolver) = setup();
+        // A long interval proves repairs are driven by notifications.
+        let watchdog = Watchdog::new(resolver)
+            .watch(ResolverConfig::new("a.local", "127.0.0.1", 5553))
+            .interval(if cfg!(target_os = "linux") {
+                Duration::from_secs(60)
+            } else {
+                Duration::from_millis(50)
+            });
+        repairs_deleted_file(watchdog, dir.path());
+    }
+
+    #[test]
+    fn spawned_watchdog_repairs_when_polling() {
+        let (dir, resolver) = setup();
+        let watchdog = Watchdog::new(resolver)
+            .watch(ResolverConfig::new("a.local", "127.0.0.1", 5553))
+            .interval(Duration::from_millis(20))
+            .polling();
+        repairs_deleted_file(watchdog, dir.path());
+    }
+}
@@ -0,0 +1,17 @@
+#!/bin/sh
+# Builds the C ABI as a shared library and runs tests/capi/smoke.c against it.
+set -eu
+
+root=$(cd "$(dirname "$0")/../.." && pwd)
+cd "$root"
+
+cargo rustc --lib --features capi --crate-type cdylib
+target_dir=$(cd "${CARGO_TARGET_DIR:-target}/debug" && pwd)
+
+work=$(mktemp -d)
+trap 'rm -rf "$work"' EXIT
+
+${CC:-cc} -Wall -Wextra -Werror -Iinclude tests/capi/smoke.c \
+    -L"$target_dir" -lmacos_resolver -Wl,-rpath,"$target_dir" \
+    -o "$work/smoke"
+"$work/smoke" "$work/resolver"
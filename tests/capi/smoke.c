@@ -0,0 +1,56 @@
+/* Exercises the C API end to end; run via tests/capi/run.sh. */
+
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#include "macos_resolver.h"
+
+#define CHECK(cond)                                                         \
+    do {                                                                    \
+        if (!(cond)) {                                                      \
+            const char *msg = mr_last_error_message();                      \
+            fprintf(stderr, "%s:%d: check failed: %s (%s)\n", __FILE__,     \
+                    __LINE__, #cond, msg ? msg : "no error");               \
+            exit(1);                                                        \
+        }                                                                   \
+    } while (0)
+
+int main(int argc, char **argv) {
+    if (argc != 2) {
+        fprintf(stderr, "usage: %s <resolver-dir>\n", argv[0]);
+        return 2;
+    }
+    MrResolver *resolver = mr_resolver_new("capi-smoke", argv[1]);
+    CHECK(resolver != NULL);
+
+    CHECK(mr_register(resolver, "a.local", "127.0.0.1", 5553, 1) == MR_OK);
+    CHECK(mr_last_error_message() == NULL);
+    CHECK(mr_register_permanent(resolver, "b.local", "127.0.0.1", 5553, 1) == MR_OK);
+
+    char **domains = NULL;
+    size_t len = 0;
+    CHECK(mr_list(resolver, &domains, &len) == MR_OK);
+    CHECK(len == 2);
+    int seen_a = 0;
+    for (size_t i = 0; i < len; i++) {
+        seen_a |= strcmp(domains[i], "a.local") == 0;
+    }
+    CHECK(seen_a);
+    mr_string_array_free(domains, len);
+
+    size_t removed = 99;
+    CHECK(mr_cleanup_orphaned(resolver, &removed) == MR_OK);
+    CHECK(removed == 0);
+
+    CHECK(mr_register(resolver, "../escape", "127.0.0.1", 53, 1) == MR_ERR_INVALID_CONFIG);
+    CHECK(mr_last_error_message() != NULL);
+    CHECK(mr_unregister(resolver, NULL) == MR_ERR_NULL_ARGUMENT);
+
+    CHECK(mr_unregister(resolver, "a.local") == MR_OK);
+    CHECK(mr_unregister(resolver, "b.local") == MR_OK);
+    mr_resolver_free(resolver);
+
+    puts("capi smoke test passed");
+    return 0;
+}
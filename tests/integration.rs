@@ -6,7 +6,7 @@
 //! sudo cargo test -- --ignored
 //! ```
 
-use macos_resolver::{FileResolver, ResolverConfig};
+use macos_resolver::{FileResolver, ManagedHeader, ResolverConfig};
 
 // ---------------------------------------------------------------------------
 // Tempdir tests (no root required)
@@ -71,6 +71,26 @@ fn orphan_cleanup() {
     assert!(dir.path().join("other.local").exists());
 }
 
+#[test]
+fn orphan_cleanup_with_custom_liveness() {
+    let dir = tempfile::tempdir().unwrap();
+    let r = FileResolver::new("testapp")
+        .dir(dir.path())
+        .liveness(|h: &ManagedHeader| h.pid != Some(std::process::id()));
+
+    r.register(&ResolverConfig::new("mine.local", "127.0.0.1", 5553))
+        .unwrap();
+    std::fs::write(
+        dir.path().join("theirs.local"),
+        "# managed by testapp (pid=1)\nnameserver 127.0.0.1\nport 5553\nsearch_order 1\n",
+    )
+    .unwrap();
+
+    assert_eq!(r.cleanup_orphaned().unwrap(), 1);
+    assert!(!r.is_registered("mine.local"));
+    assert!(r.is_registered("theirs.local"));
+}
+
 #[test]
 fn idempotent_register() {
     let dir = tempfile::tempdir().unwrap();